@@ -0,0 +1,219 @@
+//! Docs of the primops built into Nix, such as `builtins.attrNames` and `map`, embedded so that
+//! they're available without a checkout of nixpkgs, or even Nix itself.
+//!
+//! The table is regenerated from the output of `nix __dump-builtins` with [`Table::update`],
+//! which keeps the types and versions that output doesn't describe.
+use crate::index::{Lambda, Param};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, sync::OnceLock};
+
+/// The version of the format of [`Table`], bumped on breaking changes.
+pub const VERSION: u32 = 1;
+/// The embedded table, as JSON.
+pub const PRIMOPS: &str = include_str!("builtins/primops.json");
+
+/// A versioned table of builtins, ordered by name.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Table {
+    pub version: u32,
+    /// The version of Nix the table was last regenerated from, such as `2.24`.
+    pub nix: String,
+    pub builtins: Vec<Builtin>,
+}
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Builtin {
+    pub name: String,
+    /// The number of arguments applied before the builtin is evaluated, `0` for constants such
+    /// as `builtins.currentSystem`.
+    pub arity: usize,
+    pub args: Vec<String>,
+    /// The type of the builtin, written in the notation of the Nix manual.
+    #[serde(rename = "type")]
+    pub ty: Option<String>,
+    /// Markdown describing the builtin.
+    pub description: String,
+    /// The version of Nix which introduced the builtin, `None` if it predates Nix 2.0.
+    pub since: Option<String>,
+    /// Whether the builtin is in scope without `builtins.`, such as `map` and `toString`.
+    #[serde(default)]
+    pub global: bool,
+}
+impl Builtin {
+    /// The builtin as a lambda of its args, such as to render its signature.
+    pub fn lambda(&self) -> Lambda {
+        Lambda {
+            params: self
+                .args
+                .iter()
+                .map(|arg| Param::Ident(arg.into()))
+                .collect(),
+        }
+    }
+    /// Whether the builtin is deprecated, which Nix notes at the start of its description.
+    pub fn deprecated(&self) -> bool {
+        self.description.starts_with("**DEPRECATED.**")
+    }
+}
+/// A builtin as output by `nix __dump-builtins`.
+#[derive(Debug, Deserialize)]
+struct Dumped {
+    #[serde(default)]
+    args: Vec<String>,
+    arity: Option<usize>,
+    doc: Option<String>,
+}
+impl Table {
+    /// The table embedded in tanc.
+    pub fn embedded() -> &'static Self {
+        static TABLE: OnceLock<Table> = OnceLock::new();
+        TABLE.get_or_init(|| serde_json::from_str(PRIMOPS).expect("embedded builtins are invalid"))
+    }
+    pub fn get(&self, name: &str) -> Option<&Builtin> {
+        self.builtins
+            .binary_search_by(|builtin| builtin.name.as_str().cmp(name))
+            .ok()
+            .map(|i| &self.builtins[i])
+    }
+    /// Update the table from the JSON output of `nix __dump-builtins` of the given version of
+    /// Nix, such as `2.24`.
+    ///
+    /// Builtins not yet in the table are recorded as introduced by that version. Those missing
+    /// from the dump are kept, as Nix omits builtins of disabled experimental features.
+    pub fn update(&mut self, nix: &str, dump: &str) -> serde_json::Result<()> {
+        let dumped: BTreeMap<String, Dumped> = serde_json::from_str(dump)?;
+        let mut builtins: BTreeMap<String, Builtin> = self
+            .builtins
+            .drain(..)
+            .map(|builtin| (builtin.name.clone(), builtin))
+            .collect();
+        for (name, dumped) in dumped {
+            let builtin = builtins.entry(name.clone()).or_insert_with(|| Builtin {
+                name,
+                arity: 0,
+                args: Vec::new(),
+                ty: None,
+                description: String::new(),
+                since: Some(nix.to_owned()),
+                global: false,
+            });
+            builtin.arity = dumped.arity.unwrap_or(dumped.args.len());
+            builtin.args = dumped.args;
+            if let Some(doc) = dumped.doc {
+                builtin.description = dedent(&doc);
+            }
+        }
+        self.version = VERSION;
+        self.nix = nix.to_owned();
+        self.builtins = builtins.into_values().collect();
+        Ok(())
+    }
+    /// The table as pretty printed JSON, in the format of [`PRIMOPS`].
+    pub fn to_json(&self) -> String {
+        let mut json = serde_json::to_string_pretty(self).expect("builtins serialize");
+        json.push('\n');
+        json
+    }
+}
+/// The builtin referred to by the given idents, such as `["builtins", "attrNames"]` or
+/// `["map"]` for a builtin in scope without `builtins.`.
+pub fn resolve(idents: &[&str]) -> Option<&'static Builtin> {
+    let table = Table::embedded();
+    match idents {
+        ["builtins", name] => table.get(name),
+        [name] => table.get(name).filter(|builtin| builtin.global),
+        _ => None,
+    }
+}
+/// Every builtin of the embedded table, ordered by name.
+pub fn all() -> &'static [Builtin] {
+    &Table::embedded().builtins
+}
+/// Strip the indentation common to every non-blank line, along with surrounding blank lines, as
+/// docs are indented within the sources of Nix.
+fn dedent(doc: &str) -> String {
+    let indent = doc
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    let lines: Vec<&str> = doc
+        .lines()
+        .map(|line| line.get(indent..).unwrap_or_default().trim_end())
+        .collect();
+    lines.join("\n").trim_matches('\n').to_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn embedded() {
+        let table = Table::embedded();
+        assert_eq!(table.version, VERSION);
+        assert!(table
+            .builtins
+            .windows(2)
+            .all(|pair| pair[0].name < pair[1].name));
+        assert!(table
+            .builtins
+            .iter()
+            .all(|builtin| builtin.arity == builtin.args.len()));
+        assert_eq!(table.to_json(), PRIMOPS);
+
+        let attr_names = resolve(&["builtins", "attrNames"]).unwrap();
+        assert_eq!(attr_names.args, ["set"]);
+        assert_eq!(attr_names.ty.as_deref(), Some("AttrSet -> [String]"));
+        assert_eq!(resolve(&["map"]).unwrap().name, "map");
+        // Only some builtins are in scope without `builtins.`.
+        assert_eq!(resolve(&["attrNames"]), None);
+        assert_eq!(resolve(&["builtins", "missing"]), None);
+    }
+    #[test]
+    fn dumped_names() {
+        // The names of `nix __dump-builtins` of Nix 2.24, one per line.
+        let dumped: Vec<_> = include_str!("builtins/names-2.24.txt").lines().collect();
+        let names: Vec<_> = Table::embedded()
+            .builtins
+            .iter()
+            .map(|builtin| builtin.name.as_str())
+            .collect();
+        assert_eq!(names, dumped);
+    }
+    #[test]
+    fn update() {
+        let mut table = Table {
+            version: VERSION,
+            nix: "2.18".into(),
+            builtins: vec![Builtin {
+                name: "map".into(),
+                arity: 2,
+                args: vec!["f".into(), "list".into()],
+                ty: Some("(a -> b) -> [a] -> [b]".into()),
+                description: "Old.".into(),
+                since: None,
+                global: true,
+            }],
+        };
+        let dump = r#"{
+            "map": { "args": ["f", "list"], "arity": 2, "doc": "\n  Apply *f*.\n\n    map f [ ]\n" },
+            "newThing": { "args": ["x"], "arity": 1, "doc": "  New." },
+            "storeDir": { "args": [], "doc": "The store." }
+        }"#;
+        table.update("2.24", dump).unwrap();
+        assert_eq!(table.nix, "2.24");
+        let names: Vec<_> = table.builtins.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, ["map", "newThing", "storeDir"]);
+        let map = table.get("map").unwrap();
+        assert_eq!(map.description, "Apply *f*.\n\n  map f [ ]");
+        // Types, versions and scope aren't dumped, so are kept.
+        assert_eq!(map.ty.as_deref(), Some("(a -> b) -> [a] -> [b]"));
+        assert_eq!(map.since, None);
+        assert!(map.global);
+        let new_thing = table.get("newThing").unwrap();
+        assert_eq!(new_thing.since.as_deref(), Some("2.24"));
+        assert_eq!(new_thing.description, "New.");
+        assert_eq!(table.get("storeDir").unwrap().arity, 0);
+    }
+}
@@ -0,0 +1,252 @@
+//! A persistent, on-disk cache of [`FileIndex`]es, so that unchanged files are not reparsed on
+//! every start.
+//!
+//! Entries are keyed by the commit of a file, if any, and a hash of its contents. A changed file
+//! hashes differently, so stale entries are never read, and are instead removed by
+//! [`Cache::prune`]. Entries are read lazily, as each file is indexed, rather than loading the
+//! entire cache on startup.
+use crate::index::FileIndex;
+use std::{
+    collections::HashSet,
+    env, fs, io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use xxhash_rust::xxh3::xxh3_128;
+
+/// The version of the on-disk format. Bump this whenever the serialized form of a [`FileIndex`]
+/// changes, or the indexing of a file would produce a different result, such that older entries
+/// are ignored.
+pub const VERSION: u32 = 4;
+
+/// The directory name of entries without a commit, ie files of a working tree.
+const WORKTREE: &str = "worktree";
+/// How long entries of working trees are kept after being written. Every edit of a file writes
+/// another entry, so those of the versions since edited are left behind.
+const WORKTREE_MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+#[derive(Debug, Clone)]
+pub struct Cache {
+    /// The versioned directory of entries.
+    dir: PathBuf,
+}
+impl Cache {
+    /// A cache within the given root directory, such as `~/.cache/tanc`.
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        Self {
+            dir: root.as_ref().join(format!("v{VERSION}")),
+        }
+    }
+    /// The cache of the current user, under `$XDG_CACHE_HOME/tanc`, falling back to
+    /// `$HOME/.cache/tanc`.
+    pub fn user() -> Option<Self> {
+        let cache_home = env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+        Some(Self::new(cache_home.join("tanc")))
+    }
+    /// The index of the given source, read from the cache or indexed and then written to it.
+    ///
+    /// Failing to read or write the cache is logged rather than returned, as the cache is only
+    /// an optimization.
+    pub fn get_or_index(&self, commit: Option<&str>, src: &str) -> FileIndex {
+        let path = self.entry_path(commit, src);
+        match self.read(&path) {
+            Ok(Some(fi)) => return fi,
+            Ok(None) => {},
+            Err(err) => tracing::warn!("failed to read cache entry {}: {err}", path.display()),
+        }
+        let fi = FileIndex::new(src);
+        if let Err(err) = self.write(&path, &fi) {
+            tracing::warn!("failed to write cache entry {}: {err}", path.display());
+        }
+        fi
+    }
+    /// The index of the given source, if cached.
+    pub fn get(&self, commit: Option<&str>, src: &str) -> io::Result<Option<FileIndex>> {
+        self.read(&self.entry_path(commit, src))
+    }
+    pub fn insert(&self, commit: Option<&str>, src: &str, fi: &FileIndex) -> io::Result<()> {
+        self.write(&self.entry_path(commit, src), fi)
+    }
+    /// Remove the entries which are unlikely to be read again: those of other versions of the
+    /// format, of commits other than `commits`, and of working trees older than a month.
+    pub fn prune<'a>(&self, commits: impl IntoIterator<Item = &'a str>) -> io::Result<()> {
+        self.prune_older_than(commits, WORKTREE_MAX_AGE)
+    }
+    fn prune_older_than<'a>(
+        &self,
+        commits: impl IntoIterator<Item = &'a str>,
+        max_age: Duration,
+    ) -> io::Result<()> {
+        let commits: HashSet<&str> = commits.into_iter().collect();
+        if let Some(root) = self.dir.parent() {
+            for entry in read_dir(root)? {
+                let entry = entry?;
+                let name = entry.file_name();
+                let is_version = name.to_str().map_or(false, |name| {
+                    name.strip_prefix('v')
+                        .map_or(false, |version| version.parse::<u32>().is_ok())
+                });
+                if is_version && entry.path() != self.dir {
+                    fs::remove_dir_all(entry.path())?;
+                }
+            }
+        }
+        for entry in read_dir(&self.dir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            match name.to_str() {
+                Some(WORKTREE) => {
+                    for entry in read_dir(&entry.path())? {
+                        let entry = entry?;
+                        let age = entry.metadata()?.modified()?.elapsed().unwrap_or_default();
+                        if age >= max_age {
+                            fs::remove_file(entry.path())?;
+                        }
+                    }
+                },
+                Some(commit) if commits.contains(commit) => {},
+                _ => fs::remove_dir_all(entry.path())?,
+            }
+        }
+        Ok(())
+    }
+    fn entry_path(&self, commit: Option<&str>, src: &str) -> PathBuf {
+        let hash = xxh3_128(src.as_bytes());
+        self.dir
+            .join(commit.unwrap_or(WORKTREE))
+            .join(format!("{hash:032x}"))
+    }
+    fn read(&self, path: &Path) -> io::Result<Option<FileIndex>> {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        bincode::deserialize(&bytes)
+            .map(Some)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+    fn write(&self, path: &Path, fi: &FileIndex) -> io::Result<()> {
+        let bytes = bincode::serialize(fi)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Write then rename, so that concurrent readers never see a partial entry. Workers of a
+        // process may write the same entry at once, so each write has its own file.
+        static WRITES: AtomicU64 = AtomicU64::new(0);
+        let write = WRITES.fetch_add(1, Ordering::Relaxed);
+        let tmp = path.with_extension(format!("tmp{}-{write}", std::process::id()));
+        fs::write(&tmp, bytes)?;
+        fs::rename(tmp, path)
+    }
+}
+/// The entries of a directory, none if it doesn't exist.
+fn read_dir(dir: &Path) -> io::Result<Vec<io::Result<fs::DirEntry>>> {
+    match fs::read_dir(dir) {
+        Ok(entries) => Ok(entries.collect()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::index::TancIndex;
+
+    /// A cache in a fresh temporary directory, removed when dropped.
+    struct TempCache(Cache, PathBuf);
+    impl TempCache {
+        fn new(name: &str) -> Self {
+            let root = env::temp_dir().join(format!("tanc-cache-{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&root);
+            Self(Cache::new(&root), root)
+        }
+    }
+    impl Drop for TempCache {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.1);
+        }
+    }
+
+    const SRC: &str = "{\n  # Add one.\n  inc = x: x + 1;\n  set = { a = 1; };\n}";
+
+    #[test]
+    fn roundtrip() {
+        let TempCache(cache, _guard) = &TempCache::new("roundtrip");
+        assert_eq!(cache.get(None, SRC).unwrap(), None);
+        let fi = cache.get_or_index(None, SRC);
+        let cached = cache.get(None, SRC).unwrap().expect("entry was written");
+        assert_eq!(cached, fi);
+        // The rebuilt position index resolves nested bindings.
+        assert_eq!(
+            cached.path(3, 10).map(ToString::to_string),
+            Some("set.a".into())
+        );
+        assert_eq!(
+            cached.doc(2, 2).and_then(|doc| doc.doc.as_deref()),
+            Some("Add one.")
+        );
+    }
+    #[test]
+    fn keyed_by_commit_and_content() {
+        let TempCache(cache, _guard) = &TempCache::new("keys");
+        cache.get_or_index(Some("a1b2c3"), SRC);
+        assert!(cache.get(Some("a1b2c3"), SRC).unwrap().is_some());
+        assert!(cache.get(None, SRC).unwrap().is_none());
+        assert!(cache.get(Some("a1b2c3"), "{ }").unwrap().is_none());
+    }
+    #[test]
+    fn index_reads_cache() {
+        let TempCache(cache, _guard) = &TempCache::new("index");
+        // A cached entry is used in place of parsing, so a fake entry proves it was read.
+        cache
+            .insert(None, SRC, &FileIndex::new("{ fake = 1; }"))
+            .unwrap();
+        let mut index = TancIndex::with_cache(cache.clone());
+        index.insert("lib.nix", SRC);
+        let paths: Vec<_> = index
+            .file("lib.nix")
+            .unwrap()
+            .bindings()
+            .map(|(path, _)| path.to_string())
+            .collect();
+        assert_eq!(paths, vec!["", "fake"]);
+    }
+    #[test]
+    fn prune() {
+        let TempCache(cache, root) = &TempCache::new("prune");
+        cache.get_or_index(Some("kept"), SRC);
+        cache.get_or_index(Some("dropped"), SRC);
+        cache.get_or_index(None, SRC);
+        let other_version = root.join(format!("v{}", VERSION - 1));
+        fs::create_dir_all(&other_version).unwrap();
+        fs::write(root.join("unrelated"), "").unwrap();
+
+        cache.prune(["kept"]).unwrap();
+        assert!(cache.get(Some("kept"), SRC).unwrap().is_some());
+        assert!(cache.get(Some("dropped"), SRC).unwrap().is_none());
+        assert!(cache.get(None, SRC).unwrap().is_some());
+        assert!(!other_version.exists());
+        assert!(root.join("unrelated").exists());
+        // Entries of working trees are dropped once old.
+        cache.prune_older_than(["kept"], Duration::ZERO).unwrap();
+        assert!(cache.get(None, SRC).unwrap().is_none());
+        assert!(cache.get(Some("kept"), SRC).unwrap().is_some());
+    }
+    #[test]
+    fn concurrent_writes() {
+        let TempCache(cache, _guard) = &TempCache::new("writes");
+        std::thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| cache.insert(None, SRC, &FileIndex::new(SRC)).unwrap());
+            }
+        });
+        assert!(cache.get(None, SRC).unwrap().is_some());
+    }
+}
@@ -0,0 +1,232 @@
+//! Rendering of an indexed [`Project`] into documentation for humans and other tools.
+use crate::{
+    index::{AstPath, Doc, FileIndex, Lambda, NixOption, PosRange},
+    line_index::LineIndex,
+    path,
+    project::Project,
+};
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+pub mod html;
+pub mod json;
+pub mod man;
+pub mod markdown;
+pub mod nixdoc;
+pub mod terminal;
+
+/// A single public binding of a file, along with everything known about it.
+#[derive(Debug, Clone, Copy)]
+pub struct Item<'a> {
+    pub file_path: &'a str,
+    pub path: &'a AstPath,
+    pub range: &'a PosRange,
+    pub doc: Option<&'a Doc>,
+    pub lambda: Option<&'a Lambda>,
+    /// The NixOS option declared by the binding, if any.
+    pub option: Option<&'a NixOption>,
+}
+impl<'a> Item<'a> {
+    /// The doc comment of the binding, falling back to the description of its option.
+    pub fn doc_str(&self) -> Option<&'a str> {
+        self.doc
+            .and_then(|doc| doc.doc.as_deref())
+            .or_else(|| self.option?.description.as_deref())
+    }
+    /// The first paragraph of the doc, if any.
+    pub fn summary(&self) -> Option<&'a str> {
+        let doc = self.doc_str()?;
+        Some(doc.split("\n\n").next().unwrap_or(doc).trim())
+    }
+    /// The name of the binding, or the module name for the root of the file.
+    pub fn name(&self) -> String {
+        match self.path.segs() {
+            [] => module_name(self.file_path),
+            _ => self.path.to_string(),
+        }
+    }
+    /// A one line signature of the lambda, such as `f = { a, b ? 1 }: x: …`.
+    pub fn signature(&self) -> Option<String> {
+        let lambda = self.lambda?;
+        let name = self
+            .path
+            .name()
+            .map_or_else(|| module_name(self.file_path), ToOwned::to_owned);
+        let params: Vec<_> = lambda.params.iter().map(ToString::to_string).collect();
+        Some(format!("{name} = {}: …", params.join(": ")))
+    }
+}
+/// Every public binding of the given file, ordered by path.
+pub fn items<'a>(file_path: &'a str, fi: &'a FileIndex) -> impl Iterator<Item = Item<'a>> {
+    bindings(file_path, fi).filter(|item| item.path.is_public())
+}
+/// Every binding of the given file, including those of `let` expressions, ordered by path.
+pub fn bindings<'a>(file_path: &'a str, fi: &'a FileIndex) -> impl Iterator<Item = Item<'a>> {
+    fi.bindings().map(move |(path, range)| Item {
+        file_path,
+        path,
+        range,
+        doc: fi.path_doc(path),
+        lambda: fi.lambda(path),
+        option: fi.option(path),
+    })
+}
+/// The bindings of the project at the given [`path::Path`]. Without a file path every file is
+/// searched, and without a syntax path the root of the file is returned.
+///
+/// The remote and commit of the path are ignored, as a project is a single local checkout.
+pub fn find_path<'a>(project: &'a Project, path: &path::Path) -> Vec<Item<'a>> {
+    let syntax_path = path.syntax_path.as_deref().unwrap_or_default();
+    project
+        .index()
+        .files()
+        .filter(|(key, _)| {
+            path.file_path
+                .as_ref()
+                .map_or(true, |file_path| key.file_path == *file_path)
+        })
+        .flat_map(|(key, fi)| bindings(&key.file_path, fi))
+        .filter(|item| item.path.to_string() == syntax_path)
+        .collect()
+}
+/// The public bindings of the project at the given attr path, such as `lib.strings.concat`.
+///
+/// The attr path is resolved against the module name of each file, such that
+/// `lib.strings.concat` finds `concat` of `lib/strings.nix`. When no module matches, bindings
+/// of any file with the full attr path are returned instead.
+pub fn find_attr<'a>(project: &'a Project, attr_path: &str) -> Vec<Item<'a>> {
+    let all: Vec<_> = project
+        .index()
+        .files()
+        .flat_map(|(key, fi)| items(&key.file_path, fi))
+        .collect();
+    let in_module: Vec<_> = all
+        .iter()
+        .filter(|item| {
+            let module = module_name(item.file_path);
+            let binding = item.path.to_string();
+            let qualified = match (module.is_empty(), binding.is_empty()) {
+                (true, _) => binding,
+                (false, true) => module,
+                (false, false) => format!("{module}.{binding}"),
+            };
+            qualified == attr_path
+        })
+        .copied()
+        .collect();
+    if !in_module.is_empty() {
+        return in_module;
+    }
+    all.into_iter()
+        .filter(|item| !item.path.segs().is_empty() && item.path.to_string() == attr_path)
+        .collect()
+}
+/// The full lines of source covered by the given range.
+pub fn snippet(project: &Project, item: &Item) -> Option<String> {
+    let src = project.source(item.file_path)?;
+    let (start, end) = (item.range.start().line, item.range.end().line);
+    let lines: Vec<&str> = LineIndex::new(src)
+        .lines()
+        .skip(start)
+        .take(end + 1 - start)
+        .collect();
+    Some(lines.join("\n"))
+}
+/// The dotted module name of a file, such as `lib.strings` for `lib/strings.nix`. A
+/// `default.nix` is named after its directory.
+pub fn module_name(file_path: &str) -> String {
+    let path = file_path.strip_suffix(".nix").unwrap_or(file_path);
+    let path = match path.strip_suffix("default") {
+        Some(dir) if dir.is_empty() || dir.ends_with('/') => dir.trim_end_matches('/'),
+        _ => path,
+    };
+    path.replace('/', ".")
+}
+/// Write rendered files into the `out` directory, keyed by their path relative to it.
+pub fn write_files(out: &Path, files: BTreeMap<String, String>) -> io::Result<()> {
+    for (file, contents) in files {
+        let path = out.join(file);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, contents)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn project() -> Project {
+        let mut project = Project::default();
+        project.insert(
+            "lib/strings.nix",
+            "let\n  helper = x: x;\nin {\n  concat = a: b: a + b;\n  escape = { shell = s: s; };\n}"
+                .into(),
+        );
+        project.insert("default.nix", "{ concat = 1; }".into());
+        project
+    }
+    fn found(items: Vec<Item>) -> Vec<(&str, String)> {
+        items
+            .iter()
+            .map(|item| (item.file_path, item.path.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn find_by_path() {
+        let project = project();
+        let find = |s: &str| found(find_path(&project, &s.parse().unwrap()));
+        assert_eq!(
+            find("lib/strings.nix::escape.shell"),
+            vec![("lib/strings.nix", "escape.shell".into())]
+        );
+        assert_eq!(
+            find("lib/strings.nix::<let>.helper"),
+            vec![("lib/strings.nix", "<let>.helper".into())]
+        );
+        assert_eq!(
+            find("lib/strings.nix"),
+            vec![("lib/strings.nix", "".into())]
+        );
+        assert_eq!(
+            find("::concat"),
+            vec![
+                ("default.nix", "concat".into()),
+                ("lib/strings.nix", "concat".into())
+            ]
+        );
+    }
+    #[test]
+    fn find_by_attr_path() {
+        let project = project();
+        let find = |s: &str| found(find_attr(&project, s));
+        assert_eq!(
+            find("lib.strings.escape.shell"),
+            vec![("lib/strings.nix", "escape.shell".into())]
+        );
+        assert_eq!(find("lib.strings"), vec![("lib/strings.nix", "".into())]);
+        // The root module is unnamed, so its bindings take precedence.
+        assert_eq!(find("concat"), vec![("default.nix", "concat".into())]);
+        assert_eq!(
+            find("escape.shell"),
+            vec![("lib/strings.nix", "escape.shell".into())]
+        );
+        assert_eq!(find("helper"), vec![]);
+    }
+    #[test]
+    fn snippets() {
+        for ending in ["\n", "\r\n", "\r"] {
+            let mut project = Project::default();
+            let src = ["{", "  a = 1;", "  f = x:", "    x;", "}"].join(ending);
+            project.insert("a.nix", src);
+            let items = find_path(&project, &"a.nix::f".parse().unwrap());
+            assert_eq!(
+                snippet(&project, &items[0]).as_deref(),
+                Some("  f = x:\n    x;"),
+                "{ending:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,484 @@
+//! A static HTML site, with a page per file and attribute set, similar to rustdoc.
+use super::{items, snippet, write_files, Item};
+use crate::{
+    index::{AstPath, AstSeg},
+    project::Project,
+};
+use pulldown_cmark::{html::push_html, Event, HeadingLevel, Options, Parser, Tag};
+use serde_json::json;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Write as _,
+    io,
+    path::Path,
+};
+
+const STYLE: &str = include_str!("html/style.css");
+const SEARCH_JS: &str = include_str!("html/search.js");
+
+/// Render the site for `project` into the `out` directory.
+pub fn write(project: &Project, out: &Path) -> io::Result<()> {
+    write_files(out, render(project))
+}
+/// Render the site for `project`, returning the contents of each file keyed by its path relative
+/// to the root of the site.
+pub fn render(project: &Project) -> BTreeMap<String, String> {
+    let site = Site::new(project);
+    let mut out = BTreeMap::new();
+    out.insert("style.css".to_owned(), STYLE.to_owned());
+    out.insert("search.js".to_owned(), SEARCH_JS.to_owned());
+    out.insert("search-index.js".to_owned(), site.search_index());
+    out.insert("index.html".to_owned(), site.index_page());
+    for file in site.files.iter() {
+        out.insert(page_url(file.file_path, None), site.page(file, None));
+        for path in file.pages.iter() {
+            out.insert(
+                page_url(file.file_path, Some(path)),
+                site.page(file, Some(path)),
+            );
+        }
+    }
+    out
+}
+struct SiteFile<'a> {
+    file_path: &'a str,
+    items: Vec<Item<'a>>,
+    /// The bindings which contain other bindings, and are given a page of their own.
+    pages: BTreeSet<&'a AstPath>,
+}
+impl<'a> SiteFile<'a> {
+    /// The page the item is documented on, `None` being the page of the file itself.
+    fn page_of(&self, path: &AstPath) -> Option<&'a AstPath> {
+        self.pages
+            .iter()
+            .filter(|page| path.starts_with(page) && path != **page)
+            .max_by_key(|page| page.segs().len())
+            .copied()
+    }
+    fn url(&self, path: &AstPath) -> String {
+        if self.pages.contains(path) {
+            page_url(self.file_path, Some(path))
+        } else if path.segs().is_empty() {
+            page_url(self.file_path, None)
+        } else {
+            format!(
+                "{}#{}",
+                page_url(self.file_path, self.page_of(path)),
+                path_id(path)
+            )
+        }
+    }
+}
+struct Site<'a> {
+    project: &'a Project,
+    files: Vec<SiteFile<'a>>,
+    /// The url of every item keyed by its path, for linking references in docs.
+    links: HashMap<String, Vec<(&'a str, String)>>,
+}
+impl<'a> Site<'a> {
+    fn new(project: &'a Project) -> Self {
+        let files: Vec<_> = project
+            .index()
+            .files()
+            .map(|(key, fi)| {
+                let file_path = key.file_path.as_str();
+                let items: Vec<_> = items(file_path, fi).collect();
+                let pages = items
+                    .iter()
+                    .filter(|item| {
+                        !item.path.segs().is_empty()
+                            && items.iter().any(|other| {
+                                other.path.starts_with(item.path) && other.path != item.path
+                            })
+                    })
+                    .map(|item| item.path)
+                    .collect();
+                SiteFile {
+                    file_path,
+                    items,
+                    pages,
+                }
+            })
+            .collect();
+        let mut links: HashMap<_, Vec<_>> = HashMap::new();
+        for file in files.iter() {
+            for item in file.items.iter().filter(|i| !i.path.segs().is_empty()) {
+                links
+                    .entry(attr_path(item.path))
+                    .or_default()
+                    .push((file.file_path, file.url(item.path)));
+            }
+        }
+        Self {
+            project,
+            files,
+            links,
+        }
+    }
+    /// Resolve a reference such as `` `foo.bar` `` in a doc to the url of the item, preferring
+    /// items in the same file and otherwise requiring the reference to be unambiguous.
+    fn link(&self, file_path: &str, reference: &str) -> Option<&str> {
+        let candidates = self.links.get(reference)?;
+        candidates
+            .iter()
+            .find(|(f, _)| *f == file_path)
+            .or_else(|| (candidates.len() == 1).then(|| &candidates[0]))
+            .map(|(_, url)| url.as_str())
+    }
+    fn markdown(&self, file_path: &str, root: &str, md: &str) -> String {
+        let options = Options::ENABLE_TABLES
+            | Options::ENABLE_FOOTNOTES
+            | Options::ENABLE_STRIKETHROUGH
+            | Options::ENABLE_TASKLISTS;
+        // Headings within docs are nested beneath the heading of the item.
+        let demote = |level| match level {
+            HeadingLevel::H1 => HeadingLevel::H4,
+            HeadingLevel::H2 => HeadingLevel::H5,
+            _ => HeadingLevel::H6,
+        };
+        let events = Parser::new_ext(md, options).map(|event| match event {
+            Event::Start(Tag::Heading(level, id, classes)) => {
+                Event::Start(Tag::Heading(demote(level), id, classes))
+            },
+            Event::End(Tag::Heading(level, id, classes)) => {
+                Event::End(Tag::Heading(demote(level), id, classes))
+            },
+            Event::Code(code) => match self.link(file_path, &code) {
+                Some(url) => Event::Html(
+                    format!("<a href=\"{root}{url}\"><code>{}</code></a>", escape(&code)).into(),
+                ),
+                None => Event::Code(code),
+            },
+            event => event,
+        });
+        let mut html = String::new();
+        push_html(&mut html, events);
+        html
+    }
+    fn search_index(&self) -> String {
+        let entries: Vec<_> = self
+            .files
+            .iter()
+            .flat_map(|file| {
+                file.items.iter().map(|item| {
+                    let path = if item.path.segs().is_empty() {
+                        file.file_path.to_owned()
+                    } else {
+                        attr_path(item.path)
+                    };
+                    json!({
+                        "path": path,
+                        "file": file.file_path,
+                        "url": file.url(item.path),
+                        "summary": item.summary(),
+                    })
+                })
+            })
+            .collect();
+        format!(
+            "window.TANC_SEARCH_INDEX = {};\n",
+            serde_json::Value::Array(entries)
+        )
+    }
+    fn index_page(&self) -> String {
+        let mut body = String::from("<h1>Files</h1>\n<ul>\n");
+        for file in self.files.iter() {
+            let summary = file
+                .items
+                .iter()
+                .find(|item| item.path.segs().is_empty())
+                .and_then(Item::summary)
+                .unwrap_or_default();
+            let _ = writeln!(
+                body,
+                "<li><a href=\"{}\">{}</a> {}</li>",
+                page_url(file.file_path, None),
+                escape(file.file_path),
+                escape(summary),
+            );
+        }
+        body.push_str("</ul>\n");
+        layout("Files", "", &body)
+    }
+    fn page(&self, file: &SiteFile, page: Option<&AstPath>) -> String {
+        let url = page_url(file.file_path, page);
+        let root = "../".repeat(url.matches('/').count());
+        let page_path = page.cloned().unwrap_or_default();
+
+        let mut body = String::new();
+        let _ = write!(
+            body,
+            "<nav class=\"breadcrumbs\"><a href=\"{root}index.html\">Files</a> / <a \
+             href=\"{root}{}\">{}</a>",
+            page_url(file.file_path, None),
+            escape(file.file_path),
+        );
+        let mut crumb = AstPath::default();
+        for seg in page_path.segs() {
+            crumb = crumb.new_child(seg.clone());
+            let _ = write!(
+                body,
+                " / <a href=\"{root}{}\">{}</a>",
+                file.url(&crumb),
+                escape(&attr_name(seg)),
+            );
+        }
+        body.push_str("</nav>\n");
+
+        let title = page.map_or_else(|| file.file_path.to_owned(), attr_path);
+        let _ = writeln!(body, "<h1>{}</h1>", escape(&title));
+        if let Some(item) = file.items.iter().find(|item| item.path == &page_path) {
+            body.push_str(&self.item_body(file, item, &root));
+        }
+
+        let children: Vec<_> = file
+            .items
+            .iter()
+            .filter(|item| !item.path.segs().is_empty() && item.path != &page_path)
+            .filter(|item| file.page_of(item.path) == page)
+            .collect();
+        if !children.is_empty() {
+            body.push_str("<h2>Attributes</h2>\n");
+        }
+        for item in children {
+            let name = attr_path(item.path);
+            let _ = write!(
+                body,
+                "<section class=\"item\" id=\"{}\">\n<h3><a href=\"{root}{}\">{}</a></h3>\n",
+                path_id(item.path),
+                file.url(item.path),
+                escape(&name),
+            );
+            if file.pages.contains(item.path) {
+                let summary = item.summary().unwrap_or_default();
+                body.push_str(&self.markdown(file.file_path, &root, summary));
+            } else {
+                body.push_str(&self.item_body(file, item, &root));
+            }
+            body.push_str("</section>\n");
+        }
+        layout(&title, &root, &body)
+    }
+    /// The signature, doc and source of a single item.
+    fn item_body(&self, file: &SiteFile, item: &Item, root: &str) -> String {
+        let mut body = String::new();
+        if let Some(signature) = item.signature() {
+            let _ = writeln!(
+                body,
+                "<pre class=\"signature\"><code>{}</code></pre>",
+                escape(&signature),
+            );
+        }
+        if let Some(doc) = item.doc_str() {
+            body.push_str(&self.markdown(file.file_path, root, doc));
+        }
+        if let Some(src) = snippet(self.project, item) {
+            let _ = writeln!(
+                body,
+                "<details><summary>Source <span class=\"file\">{}:{}</span></summary>\
+                 <pre><code>{}</code></pre></details>",
+                escape(file.file_path),
+                item.range.start().line + 1,
+                escape(&src),
+            );
+        }
+        body
+    }
+}
+/// The url of the page for the given path of a file, relative to the root of the site.
+fn page_url(file_path: &str, path: Option<&AstPath>) -> String {
+    match path {
+        None => format!("{file_path}/index.html"),
+        // `index` is the page of the file itself.
+        Some(path) => match path_id(path).as_str() {
+            "index" => format!("{file_path}/_69ndex.html"),
+            name => format!("{file_path}/{name}.html"),
+        },
+    }
+}
+/// The path as safe in file names, urls and ids, with distinct paths having distinct ids.
+///
+/// Bytes of each segment other than letters, digits, `-` and `'` are escaped as `_` and their
+/// hex, `_` and `.` included, and segments are joined by `.`. Such as `a._2eb_20c` of `a."b c"`.
+fn path_id(path: &AstPath) -> String {
+    let mut id = String::new();
+    for (i, seg) in path.segs().iter().enumerate() {
+        if i != 0 {
+            id.push('.');
+        }
+        for byte in seg.to_string().bytes() {
+            match byte {
+                b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'\'' => id.push(byte.into()),
+                _ => {
+                    let _ = write!(id, "_{byte:02x}");
+                },
+            }
+        }
+    }
+    id
+}
+/// The path as written in Nix, quoting segments which aren't plain identifiers such as `"a.b"`.
+fn attr_path(path: &AstPath) -> String {
+    let segs: Vec<_> = path.segs().iter().map(attr_name).collect();
+    segs.join(".")
+}
+fn attr_name(seg: &AstSeg) -> String {
+    let name = seg.to_string();
+    let AstSeg::Ident(ident) = seg else {
+        return name;
+    };
+    let plain = ident.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && ident
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '\''));
+    if plain {
+        name
+    } else {
+        format!("{:?}", ident.as_str())
+    }
+}
+fn layout(title: &str, root: &str, body: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title}</title>
+<link rel="stylesheet" href="{root}style.css">
+</head>
+<body data-root="{root}">
+<div class="search">
+<input id="search" type="search" placeholder="Search…" autocomplete="off">
+<ul id="search-results" hidden></ul>
+</div>
+<main>
+{body}</main>
+<script src="{root}search-index.js"></script>
+<script src="{root}search.js"></script>
+</body>
+</html>
+"#,
+        title = escape(title),
+    )
+}
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn project() -> Project {
+        let mut project = Project::default();
+        project.insert(
+            "lib/strings.nix",
+            r#"# String helpers.
+{ lib }:
+{
+  # Concatenate a list of strings, see also `escape.shell`.
+  concatStrings = list: lib.concatStringsSep "" list;
+  escape = {
+    # Escape for the shell.
+    shell = s: s;
+  };
+}"#
+            .into(),
+        );
+        project
+    }
+
+    #[test]
+    fn pages_per_file_and_attr_set() {
+        let site = render(&project());
+        let pages: Vec<_> = site.keys().map(String::as_str).collect();
+        assert_eq!(
+            pages,
+            vec![
+                "index.html",
+                "lib/strings.nix/escape.html",
+                "lib/strings.nix/index.html",
+                "search-index.js",
+                "search.js",
+                "style.css",
+            ]
+        );
+        let file_page = &site["lib/strings.nix/index.html"];
+        assert!(file_page.contains("<h1>lib/strings.nix</h1>"));
+        assert!(file_page.contains("<p>String helpers.</p>"));
+        assert!(file_page.contains("concatStrings = list: …"));
+        assert!(file_page.contains(r#"<link rel="stylesheet" href="../../style.css">"#));
+        let escape_page = &site["lib/strings.nix/escape.html"];
+        assert!(escape_page.contains(r#"<section class="item" id="escape.shell">"#));
+        assert!(escape_page.contains("<p>Escape for the shell.</p>"));
+    }
+    #[test]
+    fn cross_links_and_search_index() {
+        let site = render(&project());
+        assert!(site["lib/strings.nix/index.html"].contains(
+            r#"<a href="../../lib/strings.nix/escape.html#escape.shell"><code>escape.shell</code></a>"#
+        ));
+        let search_index = &site["search-index.js"];
+        assert!(search_index.starts_with("window.TANC_SEARCH_INDEX = ["));
+        assert!(search_index.contains(r#""path":"concatStrings""#));
+        assert!(search_index.contains(r#""url":"lib/strings.nix/index.html#concatStrings""#));
+    }
+    #[test]
+    fn distinct_pages() {
+        let mut project = Project::default();
+        project.insert(
+            "a.nix",
+            r#"{
+  index = { x = 1; };
+  a.b = { y = 1; };
+  "a.b" = { z = 1; };
+  "a b" = { w = 1; };
+  a_b = { v = 1; };
+}"#
+            .into(),
+        );
+        let site = render(&project);
+        let pages: Vec<_> = site
+            .keys()
+            .filter(|page| page.starts_with("a.nix/"))
+            .collect();
+        assert_eq!(
+            pages,
+            [
+                "a.nix/_69ndex.html",
+                "a.nix/a.b.html",
+                "a.nix/a_20b.html",
+                "a.nix/a_2eb.html",
+                "a.nix/a_5fb.html",
+                "a.nix/index.html",
+            ]
+        );
+        assert!(site["a.nix/index.html"].contains("<h1>a.nix</h1>"));
+        assert!(site["a.nix/_69ndex.html"].contains("<h1>index</h1>"));
+        assert!(site["a.nix/a.b.html"].contains(r#"<section class="item" id="a.b.y">"#));
+        let quoted = &site["a.nix/a_2eb.html"];
+        assert!(quoted.contains("<h1>&quot;a.b&quot;</h1>"));
+        assert!(quoted.contains(r#"<section class="item" id="a_2eb.z">"#));
+        let search_index = &site["search-index.js"];
+        for entry in [
+            r#""path":"a.b.y""#,
+            r#""url":"a.nix/a.b.html#a.b.y""#,
+            r#""path":"\"a.b\".z""#,
+            r#""url":"a.nix/a_2eb.html#a_2eb.z""#,
+        ] {
+            assert!(search_index.contains(entry), "{entry}");
+        }
+    }
+}
@@ -0,0 +1,42 @@
+(function () {
+  const root = document.body.dataset.root;
+  const input = document.getElementById("search");
+  const results = document.getElementById("search-results");
+  input.addEventListener("input", function () {
+    const query = input.value.trim().toLowerCase();
+    results.textContent = "";
+    results.hidden = query.length === 0;
+    if (results.hidden) {
+      return;
+    }
+    const rank = function (item) {
+      const path = item.path.toLowerCase();
+      return [path.endsWith(query) ? 0 : 1, path.length];
+    };
+    const matches = window.TANC_SEARCH_INDEX.filter(function (item) {
+      return item.path.toLowerCase().includes(query);
+    })
+      .sort(function (a, b) {
+        const [ra, rb] = [rank(a), rank(b)];
+        return ra[0] - rb[0] || ra[1] - rb[1];
+      })
+      .slice(0, 50);
+    for (const item of matches) {
+      const li = document.createElement("li");
+      const link = document.createElement("a");
+      link.href = root + item.url;
+      link.textContent = item.path;
+      li.appendChild(link);
+      const file = document.createElement("span");
+      file.className = "file";
+      file.textContent = item.file;
+      li.appendChild(file);
+      if (item.summary) {
+        const summary = document.createElement("p");
+        summary.textContent = item.summary;
+        li.appendChild(summary);
+      }
+      results.appendChild(li);
+    }
+  });
+})();
@@ -0,0 +1,365 @@
+//! A stable, versioned JSON representation of a [`TancIndex`], for tools which can't link against
+//! tanc.
+//!
+//! The format is described by [`SCHEMA`]. Fields may be added within a version, but are never
+//! removed or changed in meaning without bumping [`VERSION`].
+use super::Item;
+use crate::index::{self, AstPath, AstSeg, FileIndex, FileKey, PosRange, TancIndex};
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+
+pub const VERSION: u32 = 1;
+/// The JSON Schema of [`Export`], and of each [`Record`] in the `jsonl` format.
+pub const SCHEMA: &str = include_str!("json/schema.v1.json");
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Export {
+    pub version: u32,
+    pub files: Vec<File>,
+}
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct File {
+    pub path: String,
+    pub commit: Option<String>,
+    pub bindings: Vec<Binding>,
+}
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Binding {
+    pub path: Vec<Seg>,
+    pub name: String,
+    pub public: bool,
+    pub range: Range,
+    pub doc: Option<String>,
+    pub lambda: Option<Lambda>,
+    /// Added within version 1, and so absent from earlier exports.
+    #[serde(default)]
+    pub option: Option<Box<NixOption>>,
+}
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Seg {
+    Ident(String),
+    Structural { kind: SegKind },
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SegKind {
+    Let,
+    AttrSet,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lambda {
+    pub params: Vec<Param>,
+}
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Param {
+    Ident {
+        name: String,
+    },
+    Pattern {
+        formals: Vec<Formal>,
+        ellipsis: bool,
+        bind: Option<String>,
+    },
+}
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Formal {
+    pub name: String,
+    pub default: Option<String>,
+}
+/// A NixOS option declared with `mkOption` or similar, with each field as its source text.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NixOption {
+    pub description: Option<String>,
+    #[serde(rename = "type")]
+    pub ty: Option<String>,
+    pub default: Option<String>,
+    pub example: Option<String>,
+}
+/// A single line of the `jsonl` format.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Record {
+    Header {
+        version: u32,
+    },
+    File {
+        path: String,
+        commit: Option<String>,
+    },
+    Binding {
+        file: String,
+        #[serde(flatten)]
+        binding: Binding,
+    },
+}
+
+pub fn export(index: &TancIndex) -> Export {
+    Export {
+        version: VERSION,
+        files: index
+            .files()
+            .map(|(key, fi)| File {
+                path: key.file_path.to_string(),
+                commit: key.commit.as_ref().map(ToString::to_string),
+                bindings: bindings(&key.file_path, fi).collect(),
+            })
+            .collect(),
+    }
+}
+/// Write the index as a single JSON document.
+pub fn write_json(index: &TancIndex, mut w: impl Write) -> io::Result<()> {
+    serde_json::to_writer_pretty(&mut w, &export(index))?;
+    writeln!(w)
+}
+/// Write the index as newline delimited [`Record`]s, without holding the entire export in memory.
+pub fn write_jsonl(index: &TancIndex, mut w: impl Write) -> io::Result<()> {
+    let mut write_record = |record: &Record| -> io::Result<()> {
+        serde_json::to_writer(&mut w, record)?;
+        writeln!(w)
+    };
+    write_record(&Record::Header { version: VERSION })?;
+    for (FileKey { commit, file_path }, fi) in index.files() {
+        write_record(&Record::File {
+            path: file_path.to_string(),
+            commit: commit.as_ref().map(ToString::to_string),
+        })?;
+        for binding in bindings(file_path, fi) {
+            write_record(&Record::Binding {
+                file: file_path.to_string(),
+                binding,
+            })?;
+        }
+    }
+    Ok(())
+}
+fn bindings<'a>(file_path: &'a str, fi: &'a FileIndex) -> impl Iterator<Item = Binding> + 'a {
+    super::bindings(file_path, fi).map(|item| Binding::from(&item))
+}
+impl From<&Item<'_>> for Binding {
+    fn from(item: &Item) -> Self {
+        Self {
+            path: item.path.segs().iter().map(Seg::from).collect(),
+            name: item.path.to_string(),
+            public: item.path.is_public(),
+            range: item.range.into(),
+            doc: item.doc.and_then(|doc| doc.doc.clone()),
+            lambda: item.lambda.map(Lambda::from),
+            option: item.option.map(|option| Box::new(option.into())),
+        }
+    }
+}
+impl From<&AstSeg> for Seg {
+    fn from(seg: &AstSeg) -> Self {
+        match seg {
+            AstSeg::Ident(ident) => Self::Ident(ident.to_string()),
+            AstSeg::Let => Self::Structural { kind: SegKind::Let },
+            AstSeg::AttrSet => Self::Structural {
+                kind: SegKind::AttrSet,
+            },
+        }
+    }
+}
+impl From<&Seg> for AstSeg {
+    fn from(seg: &Seg) -> Self {
+        match seg {
+            Seg::Ident(ident) => Self::Ident(ident.into()),
+            Seg::Structural { kind: SegKind::Let } => Self::Let,
+            Seg::Structural {
+                kind: SegKind::AttrSet,
+            } => Self::AttrSet,
+        }
+    }
+}
+impl From<&[Seg]> for AstPath {
+    fn from(segs: &[Seg]) -> Self {
+        segs.iter().fold(AstPath::default(), |path, seg| {
+            path.new_child(AstSeg::from(seg))
+        })
+    }
+}
+impl From<&PosRange> for Range {
+    fn from(range: &PosRange) -> Self {
+        let position = |pos: index::Pos| Position {
+            line: pos.line,
+            column: pos.char,
+        };
+        Self {
+            start: position(range.start()),
+            end: position(range.end()),
+        }
+    }
+}
+impl From<&index::NixOption> for NixOption {
+    fn from(option: &index::NixOption) -> Self {
+        Self {
+            description: option.description.clone(),
+            ty: option.ty.clone(),
+            default: option.default.clone(),
+            example: option.example.clone(),
+        }
+    }
+}
+impl From<&index::Lambda> for Lambda {
+    fn from(lambda: &index::Lambda) -> Self {
+        Self {
+            params: lambda
+                .params
+                .iter()
+                .map(|param| match param {
+                    index::Param::Ident(name) => Param::Ident {
+                        name: name.to_string(),
+                    },
+                    index::Param::Pattern {
+                        formals,
+                        ellipsis,
+                        bind,
+                    } => Param::Pattern {
+                        formals: formals
+                            .iter()
+                            .map(|formal| Formal {
+                                name: formal.name.to_string(),
+                                default: formal.default.clone(),
+                            })
+                            .collect(),
+                        ellipsis: *ellipsis,
+                        bind: bind.as_ref().map(ToString::to_string),
+                    },
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn index() -> TancIndex {
+        let mut index = TancIndex::default();
+        index.insert(
+            "lib.nix",
+            "let\n  helper = x: x;\nin {\n  # Apply f.\n  apply = { f, x ? 1 }: f x;\n}",
+        );
+        index
+    }
+
+    #[test]
+    fn export_bindings() {
+        let export = export(&index());
+        assert_eq!(export.version, VERSION);
+        let file = &export.files[0];
+        assert_eq!(file.path, "lib.nix");
+        let names: Vec<_> = file
+            .bindings
+            .iter()
+            .map(|b| (b.name.as_str(), b.public))
+            .collect();
+        assert_eq!(
+            names,
+            vec![("", true), ("<let>.helper", false), ("apply", true)]
+        );
+        let apply = &file.bindings[2];
+        assert_eq!(apply.doc.as_deref(), Some("Apply f."));
+        assert_eq!(
+            apply.range,
+            Range {
+                start: Position { line: 4, column: 2 },
+                end: Position {
+                    line: 4,
+                    column: 28
+                },
+            }
+        );
+        assert_eq!(
+            serde_json::to_value(apply).unwrap()["lambda"],
+            serde_json::json!({
+                "params": [{
+                    "kind": "pattern",
+                    "formals": [
+                        { "name": "f", "default": null },
+                        { "name": "x", "default": "1" },
+                    ],
+                    "ellipsis": false,
+                    "bind": null,
+                }],
+            })
+        );
+        let helper = serde_json::to_value(&file.bindings[1]).unwrap();
+        assert_eq!(
+            helper["path"],
+            serde_json::json!([{ "kind": "let" }, "helper"])
+        );
+        assert_eq!(
+            AstPath::from(file.bindings[1].path.as_slice()),
+            AstPath::default()
+                .new_child(AstSeg::Let)
+                .new_child("helper")
+        );
+    }
+    #[test]
+    fn export_options() {
+        let mut index = TancIndex::default();
+        index.insert(
+            "module.nix",
+            "{ lib, ... }: {\n  options.foo = lib.mkOption {\n    type = lib.types.int;\n    \
+             description = \"The foo.\";\n  };\n}",
+        );
+        let export = export(&index);
+        let foo = export.files[0]
+            .bindings
+            .iter()
+            .find(|b| b.name == "options.foo")
+            .unwrap();
+        // The description is the option's, not a doc comment.
+        assert_eq!(foo.doc, None);
+        assert_eq!(
+            serde_json::to_value(foo).unwrap()["option"],
+            serde_json::json!({
+                "description": "The foo.",
+                "type": "lib.types.int",
+                "default": null,
+                "example": null,
+            })
+        );
+    }
+    #[test]
+    fn jsonl_records() {
+        let mut out = Vec::new();
+        write_jsonl(&index(), &mut out).unwrap();
+        let records: Vec<Record> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 5);
+        assert_eq!(records[0], Record::Header { version: VERSION });
+        assert_eq!(
+            records[1],
+            Record::File {
+                path: "lib.nix".into(),
+                commit: None,
+            }
+        );
+        let Record::Binding { file, binding } = &records[4] else {
+            panic!("expected a binding, got {:?}", records[4]);
+        };
+        assert_eq!((file.as_str(), binding.name.as_str()), ("lib.nix", "apply"));
+    }
+    #[test]
+    fn schema_is_json() {
+        let schema: serde_json::Value = serde_json::from_str(SCHEMA).unwrap();
+        assert_eq!(schema["properties"]["version"]["const"], VERSION);
+    }
+}
@@ -0,0 +1,211 @@
+//! roff export, with a section 3 man page for every top level function of a module.
+use super::{items, module_name, write_files, Item};
+use crate::project::Project;
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use std::{collections::BTreeMap, fmt::Write as _, io, path::Path};
+
+/// The manual section the pages are written for, library functions.
+pub const SECTION: &str = "3";
+
+/// Render the project and write it into the `out` directory.
+pub fn write(project: &Project, out: &Path) -> io::Result<()> {
+    write_files(out, render(project))
+}
+/// Render a page per top level function, keyed by file name such as `lib.strings.concat.3`.
+pub fn render(project: &Project) -> BTreeMap<String, String> {
+    let mut out = BTreeMap::new();
+    for (key, fi) in project.index().files() {
+        let module = module_name(&key.file_path);
+        for item in items(&key.file_path, fi) {
+            if item.lambda.is_none() || item.path.segs().len() != 1 {
+                continue;
+            }
+            let name = match item.path.name() {
+                Some(name) if module.is_empty() => name.to_owned(),
+                Some(name) => format!("{module}.{name}"),
+                None => continue,
+            };
+            out.insert(format!("{name}.{SECTION}"), page(&name, &item));
+        }
+    }
+    out
+}
+fn page(name: &str, item: &Item) -> String {
+    let mut page = format!(
+        ".TH \"{}\" \"{SECTION}\" \"\" \"tanc\" \"Nix Library Functions\"\n",
+        escape(&name.to_uppercase())
+    );
+    page.push_str(".SH NAME\n");
+    match item.summary() {
+        Some(summary) => {
+            // The NAME line is plain text, so drop code spans' backticks.
+            let summary = summary.replace('`', "");
+            let summary = summary.split_whitespace().collect::<Vec<_>>().join(" ");
+            let _ = writeln!(page, "{} \\- {}", escape(name), escape(&summary));
+        },
+        None => {
+            let _ = writeln!(page, "{}", escape(name));
+        },
+    }
+    if let Some(signature) = item.signature() {
+        let _ = writeln!(page, ".SH SYNOPSIS\n.nf\n{}\n.fi", line(&signature));
+    }
+    if let Some(doc) = item.doc_str() {
+        page.push_str(".SH DESCRIPTION\n");
+        page.push_str(&roff(doc));
+    }
+    let _ = writeln!(
+        page,
+        ".SH SOURCE\n{}:{}",
+        escape(item.file_path),
+        item.range.start().line + 1
+    );
+    page
+}
+/// Convert CommonMark to roff, using only requests of the `man` macro package.
+fn roff(md: &str) -> String {
+    let mut out = String::new();
+    // Requests must start on a fresh line.
+    let request = |out: &mut String, request: &str| {
+        end_line(out);
+        out.push_str(request);
+        out.push('\n');
+    };
+    let mut lists: Vec<Option<u64>> = Vec::new();
+    let mut in_code_block = false;
+    for event in Parser::new_ext(md, Options::ENABLE_STRIKETHROUGH) {
+        match event {
+            Event::Start(Tag::Paragraph) if lists.is_empty() => request(&mut out, ".PP"),
+            Event::Start(Tag::Paragraph) => {},
+            Event::Start(Tag::Heading(..)) => request(&mut out, ".SS"),
+            Event::Start(Tag::CodeBlock(_)) => {
+                request(&mut out, ".PP\n.RS 4\n.nf");
+                in_code_block = true;
+            },
+            Event::End(Tag::CodeBlock(_)) => {
+                request(&mut out, ".fi\n.RE");
+                in_code_block = false;
+            },
+            Event::Start(Tag::List(start)) => lists.push(start),
+            Event::End(Tag::List(_)) => {
+                lists.pop();
+            },
+            Event::Start(Tag::Item) => {
+                let bullet = match lists.last_mut() {
+                    Some(Some(n)) => {
+                        *n += 1;
+                        format!("{}.", *n - 1)
+                    },
+                    _ => "\\(bu".to_owned(),
+                };
+                request(&mut out, &format!(".IP \"{bullet}\" 4"));
+            },
+            Event::Start(Tag::Emphasis) => out.push_str("\\fI"),
+            Event::Start(Tag::Strong) => out.push_str("\\fB"),
+            Event::End(Tag::Emphasis | Tag::Strong) => out.push_str("\\fR"),
+            Event::End(Tag::Paragraph | Tag::Heading(..) | Tag::Item) => end_line(&mut out),
+            Event::Text(text) if in_code_block => {
+                for code_line in text.lines() {
+                    let _ = writeln!(out, "{}", line(code_line));
+                }
+            },
+            Event::Text(text) => push_text(&mut out, &text),
+            Event::Code(code) => {
+                out.push_str("\\fB");
+                push_text(&mut out, &code);
+                out.push_str("\\fR");
+            },
+            Event::SoftBreak => out.push('\n'),
+            Event::HardBreak => request(&mut out, ".br"),
+            Event::Rule => request(&mut out, ".PP\n\\l'\\n(.lu'"),
+            _ => {},
+        }
+    }
+    end_line(&mut out);
+    out
+}
+fn end_line(out: &mut String) {
+    if !out.is_empty() && !out.ends_with('\n') {
+        out.push('\n');
+    }
+}
+/// Push inline text, which may follow other text on the same output line.
+fn push_text(out: &mut String, text: &str) {
+    if out.is_empty() || out.ends_with('\n') {
+        out.push_str(&line(text));
+    } else {
+        out.push_str(&escape(text));
+    }
+}
+/// Escape text which starts an output line, where a leading `.` or `'` would begin a request.
+fn line(s: &str) -> String {
+    let escaped = escape(s);
+    if escaped.starts_with('.') || escaped.starts_with('\'') {
+        format!("\\&{escaped}")
+    } else {
+        escaped
+    }
+}
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\e"),
+            '-' => escaped.push_str("\\-"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn page_per_top_level_function() {
+        let mut project = Project::default();
+        project.insert(
+            "lib/strings.nix",
+            r#"{ lib }:
+{
+  # Concatenate a list of strings, with `sep-arator`.
+  #
+  # ```nix
+  # .leading "a\b"
+  # ```
+  concatStringsSep = sep: list: lib.concatStringsSep sep list;
+  nested = {
+    inner = x: x;
+  };
+  value = 1;
+}"#
+            .into(),
+        );
+        let out = render(&project);
+        let pages: Vec<_> = out.keys().map(String::as_str).collect();
+        assert_eq!(pages, vec!["lib.strings.concatStringsSep.3"]);
+        assert_eq!(
+            out["lib.strings.concatStringsSep.3"],
+            r#".TH "LIB.STRINGS.CONCATSTRINGSSEP" "3" "" "tanc" "Nix Library Functions"
+.SH NAME
+lib.strings.concatStringsSep \- Concatenate a list of strings, with sep\-arator.
+.SH SYNOPSIS
+.nf
+concatStringsSep = sep: list: …
+.fi
+.SH DESCRIPTION
+.PP
+Concatenate a list of strings, with \fBsep\-arator\fR.
+.PP
+.RS 4
+.nf
+\&.leading "a\eb"
+.fi
+.RE
+.SH SOURCE
+lib/strings.nix:8
+"#
+        );
+    }
+}
@@ -0,0 +1,144 @@
+//! CommonMark export, with one file per module and a `SUMMARY.md` so that the output can be used
+//! directly as the `src` of an mdBook.
+use super::{items, module_name, snippet, write_files, Item};
+use crate::project::Project;
+use std::{collections::BTreeMap, fmt::Write as _, io, path::Path};
+
+/// Render the project and write it into the `out` directory.
+pub fn write(project: &Project, out: &Path) -> io::Result<()> {
+    write_files(out, render(project))
+}
+/// Render every file of the project, keyed by path relative to the output directory.
+pub fn render(project: &Project) -> BTreeMap<String, String> {
+    let mut out = BTreeMap::new();
+    let mut summary = String::from("# Summary\n\n");
+    for (key, fi) in project.index().files() {
+        let file_path = key.file_path.as_str();
+        let page = page_path(file_path);
+        let module = module_name(file_path);
+        let _ = writeln!(summary, "- [{module}]({page})");
+        out.insert(page, module_page(project, &module, items(file_path, fi)));
+    }
+    out.insert("SUMMARY.md".to_owned(), summary);
+    out
+}
+/// The path of the page documenting the given file, such as `lib/strings.md`.
+pub fn page_path(file_path: &str) -> String {
+    format!("{}.md", file_path.strip_suffix(".nix").unwrap_or(file_path))
+}
+fn module_page<'a>(
+    project: &Project,
+    module: &str,
+    items: impl Iterator<Item = Item<'a>>,
+) -> String {
+    let mut page = format!("# {module}\n");
+    for item in items {
+        if item.path.segs().is_empty() {
+            // The root of the file documents the module itself.
+            if let Some(doc) = item.doc_str() {
+                let _ = write!(page, "\n{}\n", demote_headings(doc, 2));
+            }
+            continue;
+        }
+        let _ = write!(page, "\n## `{}`\n", item.path);
+        if let Some(signature) = item.signature() {
+            let _ = write!(page, "\n```nix\n{signature}\n```\n");
+        }
+        if let Some(doc) = item.doc_str() {
+            let _ = write!(page, "\n{}\n", demote_headings(doc, 2));
+        }
+        if snippet(project, &item).is_some() {
+            let _ = write!(
+                page,
+                "\nDefined in `{}:{}`.\n",
+                item.file_path,
+                item.range.start().line + 1
+            );
+        }
+    }
+    page
+}
+/// Nest the ATX headings of a doc beneath the heading of its item, leaving code blocks untouched.
+pub fn demote_headings(md: &str, by: usize) -> String {
+    let mut fence: Option<&str> = None;
+    let lines: Vec<String> = md
+        .lines()
+        .map(|line| {
+            let trimmed = line.trim_start();
+            match fence {
+                Some(open) if trimmed.starts_with(open) => fence = None,
+                Some(_) => {},
+                None if trimmed.starts_with("```") => fence = Some("```"),
+                None if trimmed.starts_with("~~~") => fence = Some("~~~"),
+                None if line.starts_with('#') => {
+                    let level = line.chars().take_while(|&c| c == '#').count();
+                    let rest = &line[level..];
+                    if rest.is_empty() || rest.starts_with(' ') {
+                        return format!("{}{rest}", "#".repeat((level + by).min(6)));
+                    }
+                },
+                None => {},
+            }
+            line.to_owned()
+        })
+        .collect();
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn module_pages_and_summary() {
+        let mut project = Project::default();
+        project.insert(
+            "lib/strings.nix",
+            r#"# String helpers.
+{ lib }:
+{
+  /**
+    Concatenate a list of strings.
+
+    # Example
+
+    ```nix
+    # not a heading
+    concatStrings [ "a" "b" ]
+    ```
+  */
+  concatStrings = list: lib.concatStringsSep "" list;
+}"#
+            .into(),
+        );
+        let out = render(&project);
+        assert_eq!(
+            out["SUMMARY.md"],
+            "# Summary\n\n- [lib.strings](lib/strings.md)\n"
+        );
+        assert_eq!(
+            out["lib/strings.md"],
+            r#"# lib.strings
+
+String helpers.
+
+## `concatStrings`
+
+```nix
+concatStrings = list: …
+```
+
+Concatenate a list of strings.
+
+### Example
+
+```nix
+# not a heading
+concatStrings [ "a" "b" ]
+```
+
+Defined in `lib/strings.nix:14`.
+"#
+        );
+    }
+}
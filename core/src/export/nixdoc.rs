@@ -0,0 +1,287 @@
+//! CommonMark export matching the structure emitted by `nixdoc`, which builds the library
+//! reference of the nixpkgs manual, so that either can be used to render `lib/*.nix`.
+//!
+//! Both styles of doc comment understood by `nixdoc` are supported. [RFC 145] comments are plain
+//! CommonMark, and are emitted with their headings nested beneath the heading of their binding.
+//! Legacy comments describe the binding with `Type:` and `Example:` sections, which are
+//! reformatted into a type signature and an example block.
+//!
+//! [RFC 145]: https://github.com/NixOS/rfcs/blob/master/rfcs/0145-doc-strings.md
+use super::{items, markdown::demote_headings, module_name, write_files, Item};
+use crate::{index::Param, project::Project};
+use std::{collections::BTreeMap, fmt::Write as _, io, path::Path};
+
+/// Render the project and write it into the `out` directory.
+pub fn write(project: &Project, out: &Path) -> io::Result<()> {
+    write_files(out, render(project))
+}
+/// Render every file of the project, keyed by path relative to the output directory.
+pub fn render(project: &Project) -> BTreeMap<String, String> {
+    project
+        .index()
+        .files()
+        .map(|(key, fi)| {
+            let file_path = key.file_path.as_str();
+            (
+                super::markdown::page_path(file_path),
+                module_page(&module_name(file_path), items(file_path, fi)),
+            )
+        })
+        .collect()
+}
+/// The anchor `nixdoc` gives the documentation of a binding of a module, such as
+/// `function-library-lib.strings.concatStrings`.
+pub fn anchor(module: &str, name: &str) -> String {
+    format!("function-library-{}", qualified(module, name))
+}
+fn qualified(module: &str, name: &str) -> String {
+    if module.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{module}.{name}")
+    }
+}
+fn module_page<'a>(module: &str, items: impl Iterator<Item = Item<'a>>) -> String {
+    // `nixdoc` names a module by its category, the final segment of the module.
+    let category = module.rsplit('.').next().unwrap_or(module);
+    let mut page = String::new();
+    let mut entries = Vec::new();
+    for item in items {
+        match item.path.segs() {
+            [] => {
+                let (title, rest) = match item.doc_str().map(|doc| doc.split_once("\n\n")) {
+                    Some(Some((title, rest))) => (title.trim(), Some(rest)),
+                    Some(None) => (item.doc_str().unwrap_or_default().trim(), None),
+                    None => (module, None),
+                };
+                let _ = writeln!(page, "# {title} {{#sec-functions-library-{category}}}");
+                if let Some(rest) = rest {
+                    let _ = writeln!(page, "\n{}", demote_headings(rest.trim(), 1));
+                }
+            },
+            // Only the bindings of the top level attribute set are documented, as with `nixdoc`.
+            [_] if item.doc_str().is_some() || item.lambda.is_some() => {
+                entries.push((item.range.start(), entry(module, &item)));
+            },
+            _ => {},
+        }
+    }
+    if page.is_empty() {
+        let _ = writeln!(page, "# {module} {{#sec-functions-library-{category}}}");
+    }
+    // Entries are in source order, as with `nixdoc`, rather than ordered by name.
+    entries.sort_by_key(|(start, _)| *start);
+    for (_, entry) in entries {
+        let _ = write!(page, "\n{}\n", entry.trim_end());
+    }
+    page
+}
+fn entry(module: &str, item: &Item) -> String {
+    let mut out = String::new();
+    let name = item.path.name().unwrap_or_default();
+    let qualified = qualified(module, name);
+    let _ = writeln!(out, "## `{qualified}` {{#{}}}\n", anchor(module, name));
+    let doc = item.doc_str().unwrap_or_default();
+    let Some(legacy) = LegacyDoc::parse(doc) else {
+        if !doc.is_empty() {
+            let _ = writeln!(out, "{}\n", demote_headings(doc, 2));
+        }
+        return out;
+    };
+    if let Some(ty) = legacy.ty {
+        let _ = writeln!(out, "**Type**: `{ty}`\n");
+    }
+    if !legacy.description.is_empty() {
+        let _ = writeln!(out, "{}\n", legacy.description);
+    }
+    for param in item.lambda.iter().flat_map(|lambda| &lambda.params) {
+        match param {
+            Param::Ident(ident) => {
+                let _ = writeln!(out, "`{ident}`\n\n: Function argument\n");
+            },
+            Param::Pattern { formals, .. } => {
+                out.push_str("structured function argument\n\n");
+                for formal in formals {
+                    let _ = writeln!(out, ": `{}`\n\n  : Function argument\n", formal.name);
+                }
+            },
+        }
+    }
+    if let Some(example) = legacy.example {
+        let _ = writeln!(
+            out,
+            ":::{{.example}}\n## `{qualified}` usage example\n\n```nix\n{example}\n```\n:::\n"
+        );
+    }
+    out
+}
+/// A doc comment in the style `nixdoc` supported before RFC 145.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct LegacyDoc {
+    description: String,
+    ty: Option<String>,
+    example: Option<String>,
+}
+impl LegacyDoc {
+    /// Parse the doc, if it has any of the legacy `Type:` or `Example:` sections.
+    fn parse(doc: &str) -> Option<Self> {
+        enum Section {
+            Description,
+            Type,
+            Example,
+        }
+        let mut section = Section::Description;
+        let mut legacy = Self::default();
+        let (mut description, mut ty, mut example) = (Vec::new(), Vec::new(), Vec::new());
+        let mut is_legacy = false;
+        for line in doc.lines() {
+            if let Some(rest) = line.strip_prefix("Type:") {
+                is_legacy = true;
+                section = Section::Type;
+                ty.push(rest.trim());
+                continue;
+            }
+            if let Some(rest) = line.strip_prefix("Example:") {
+                is_legacy = true;
+                section = Section::Example;
+                example.push(rest.trim_end());
+                continue;
+            }
+            // Sections end at the first unindented line.
+            if !line.is_empty() && !line.starts_with(char::is_whitespace) {
+                section = Section::Description;
+            }
+            match section {
+                Section::Description => description.push(line),
+                Section::Type => ty.push(line.trim()),
+                Section::Example => example.push(line),
+            }
+        }
+        if !is_legacy {
+            return None;
+        }
+        legacy.description = description.join("\n").trim().to_owned();
+        let ty = ty.join(" ");
+        let ty = ty.trim();
+        legacy.ty = (!ty.is_empty()).then(|| ty.to_owned());
+        let example = dedent(&example);
+        legacy.example = (!example.is_empty()).then_some(example);
+        Some(legacy)
+    }
+}
+/// Join the lines, removing their common indentation and any surrounding blank lines.
+fn dedent(lines: &[&str]) -> String {
+    let indent = lines
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    let lines: Vec<_> = lines
+        .iter()
+        .map(|line| line.get(indent..).unwrap_or_default().trim_end())
+        .collect();
+    lines.join("\n").trim_matches('\n').to_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn legacy_comments() {
+        let mut project = Project::default();
+        project.insert(
+            "lib/strings.nix",
+            r#"/* String manipulation functions. */
+{ lib }:
+rec {
+  /* Concatenate a list of strings.
+
+     Type: concatStrings :: [string] -> string
+
+     Example:
+       concatStrings ["foo" "bar"]
+       => "foobar"
+  */
+  concatStrings = list: lib.concatStringsSep "" list;
+
+  # Map a function over a list and concatenate the results.
+  concatMapStrings = f: list: concatStrings (map f list);
+
+  # Helpers, whose bindings are not documented.
+  nested = { inner = x: x; };
+}"#
+            .into(),
+        );
+        let out = render(&project);
+        assert_eq!(
+            out["lib/strings.md"],
+            r#"# String manipulation functions. {#sec-functions-library-strings}
+
+## `lib.strings.concatStrings` {#function-library-lib.strings.concatStrings}
+
+**Type**: `concatStrings :: [string] -> string`
+
+Concatenate a list of strings.
+
+`list`
+
+: Function argument
+
+:::{.example}
+## `lib.strings.concatStrings` usage example
+
+```nix
+concatStrings ["foo" "bar"]
+=> "foobar"
+```
+:::
+
+## `lib.strings.concatMapStrings` {#function-library-lib.strings.concatMapStrings}
+
+Map a function over a list and concatenate the results.
+
+## `lib.strings.nested` {#function-library-lib.strings.nested}
+
+Helpers, whose bindings are not documented.
+"#
+        );
+    }
+    #[test]
+    fn rfc145_comments() {
+        let mut project = Project::default();
+        project.insert(
+            "lib/lists.nix",
+            r#"{
+  /**
+    Return the first element of a list.
+
+    # Type
+
+    ```
+    head :: [a] -> a
+    ```
+  */
+  head = list: builtins.head list;
+}"#
+            .into(),
+        );
+        let out = render(&project);
+        assert_eq!(
+            out["lib/lists.md"],
+            r#"# lib.lists {#sec-functions-library-lists}
+
+## `lib.lists.head` {#function-library-lib.lists.head}
+
+Return the first element of a list.
+
+### Type
+
+```
+head :: [a] -> a
+```
+"#
+        );
+    }
+}
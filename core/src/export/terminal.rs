@@ -0,0 +1,242 @@
+//! Rendering of a single binding for reading in a terminal, with the Markdown of its doc styled
+//! by ANSI escape codes.
+use super::{module_name, Item};
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag};
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const ITALIC: &str = "\x1b[3m";
+const UNDERLINE: &str = "\x1b[4m";
+const CYAN: &str = "\x1b[36m";
+const GREEN: &str = "\x1b[32m";
+
+/// Render the binding, its location, signature and doc. Without `color` no escape codes are
+/// emitted, for output which isn't a terminal.
+pub fn render(item: &Item, color: bool) -> String {
+    let mut out = Styled::new(color);
+    let module = module_name(item.file_path);
+    let name = match (module.is_empty(), item.path.segs().is_empty()) {
+        (_, true) => item.name(),
+        (true, false) => item.path.to_string(),
+        (false, false) => format!("{module}.{}", item.path),
+    };
+    out.styled(BOLD, &name);
+    out.push('\n');
+    out.styled(
+        DIM,
+        &format!("{}:{}", item.file_path, item.range.start().line + 1),
+    );
+    out.push('\n');
+    if let Some(signature) = item.signature() {
+        out.push('\n');
+        out.styled(GREEN, &format!("    {signature}"));
+        out.push('\n');
+    }
+    if let Some(doc) = item.doc_str() {
+        out.push('\n');
+        out.markdown(doc);
+    }
+    out.text
+}
+/// Text with styles tracked as a stack, so that the enclosing style can be restored when a
+/// nested one ends.
+struct Styled {
+    color: bool,
+    text: String,
+    styles: Vec<&'static str>,
+}
+impl Styled {
+    fn new(color: bool) -> Self {
+        Self {
+            color,
+            text: String::new(),
+            styles: Vec::new(),
+        }
+    }
+    fn push(&mut self, c: char) {
+        self.text.push(c);
+    }
+    fn push_str(&mut self, s: &str) {
+        self.text.push_str(s);
+    }
+    fn start(&mut self, style: &'static str) {
+        self.styles.push(style);
+        if self.color {
+            self.text.push_str(style);
+        }
+    }
+    fn end(&mut self) {
+        self.styles.pop();
+        if self.color {
+            self.text.push_str(RESET);
+            for style in &self.styles {
+                self.text.push_str(style);
+            }
+        }
+    }
+    fn styled(&mut self, style: &'static str, s: &str) {
+        self.start(style);
+        self.push_str(s);
+        self.end();
+    }
+    /// A blank line between blocks, but not before the first.
+    fn block(&mut self) {
+        if !self.text.is_empty() && !self.text.ends_with("\n\n") {
+            self.end_line();
+            self.push('\n');
+        }
+    }
+    fn end_line(&mut self) {
+        if !self.text.is_empty() && !self.text.ends_with('\n') {
+            self.push('\n');
+        }
+    }
+    fn markdown(&mut self, md: &str) {
+        // The prefix of each line within the current list item, and the number of the next item
+        // of each ordered list.
+        let mut indent = String::new();
+        let mut lists: Vec<Option<u64>> = Vec::new();
+        let mut in_code_block = false;
+        let mut link_dest = None;
+        for event in Parser::new_ext(md, Options::ENABLE_STRIKETHROUGH) {
+            match event {
+                Event::Start(Tag::Paragraph) if lists.is_empty() => self.block(),
+                Event::Start(Tag::Heading(level, ..)) => {
+                    self.block();
+                    self.start(if level == HeadingLevel::H1 {
+                        "\x1b[1;4m"
+                    } else {
+                        BOLD
+                    });
+                },
+                Event::End(Tag::Heading(..)) => {
+                    self.end();
+                    self.push('\n');
+                },
+                Event::Start(Tag::CodeBlock(_)) => {
+                    self.block();
+                    self.start(CYAN);
+                    in_code_block = true;
+                },
+                Event::End(Tag::CodeBlock(_)) => {
+                    self.end();
+                    in_code_block = false;
+                },
+                Event::Start(Tag::List(start)) => {
+                    if lists.is_empty() {
+                        self.block();
+                    }
+                    lists.push(start);
+                },
+                Event::End(Tag::List(_)) => {
+                    lists.pop();
+                },
+                Event::Start(Tag::Item) => {
+                    self.end_line();
+                    let bullet = match lists.last_mut() {
+                        Some(Some(n)) => {
+                            *n += 1;
+                            format!("{}. ", *n - 1)
+                        },
+                        _ => "• ".to_owned(),
+                    };
+                    indent = "  ".repeat(lists.len().saturating_sub(1));
+                    self.push_str(&indent);
+                    self.push_str(&bullet);
+                    indent.push_str(&" ".repeat(bullet.chars().count()));
+                },
+                Event::Start(Tag::Emphasis) => self.start(ITALIC),
+                Event::Start(Tag::Strong) => self.start(BOLD),
+                Event::Start(Tag::Strikethrough) => self.start(DIM),
+                Event::Start(Tag::Link(_, dest, _)) => {
+                    self.start(UNDERLINE);
+                    link_dest = Some(dest);
+                },
+                Event::End(Tag::Link(..)) => {
+                    self.end();
+                    if let Some(dest) = link_dest.take() {
+                        self.styled(DIM, &format!(" <{dest}>"));
+                    }
+                },
+                Event::End(Tag::Emphasis | Tag::Strong | Tag::Strikethrough) => self.end(),
+                Event::Text(text) if in_code_block => {
+                    for line in text.lines() {
+                        self.push_str("    ");
+                        self.push_str(line);
+                        self.push('\n');
+                    }
+                },
+                Event::Text(text) => self.push_str(&text),
+                Event::Code(code) => self.styled(CYAN, &code),
+                Event::SoftBreak | Event::HardBreak => {
+                    self.push('\n');
+                    let indent = indent.clone();
+                    self.push_str(&indent);
+                },
+                Event::Rule => {
+                    self.block();
+                    self.styled(DIM, "────────");
+                },
+                _ => {},
+            }
+        }
+        self.end_line();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{export::find_attr, project::Project};
+
+    fn project() -> Project {
+        let mut project = Project::default();
+        project.insert(
+            "lib/strings.nix",
+            r#"{
+  /**
+    Concatenate a list of strings, see *also* `concatMap`.
+
+    # Example
+
+    ```nix
+    concatStrings [ "a" "b" ]
+    ```
+  */
+  concatStrings = list: list;
+}"#
+            .into(),
+        );
+        project
+    }
+
+    #[test]
+    fn plain() {
+        let project = project();
+        let items = find_attr(&project, "lib.strings.concatStrings");
+        assert_eq!(
+            render(&items[0], false),
+            r#"lib.strings.concatStrings
+lib/strings.nix:11
+
+    concatStrings = list: …
+
+Concatenate a list of strings, see also concatMap.
+
+Example
+
+    concatStrings [ "a" "b" ]
+"#
+        );
+    }
+    #[test]
+    fn colored() {
+        let project = project();
+        let items = find_attr(&project, "lib.strings.concatStrings");
+        let out = render(&items[0], true);
+        assert!(out.starts_with("\x1b[1mlib.strings.concatStrings\x1b[0m\n"));
+        assert!(out.contains("see \x1b[3malso\x1b[0m \x1b[36mconcatMap\x1b[0m."));
+        assert!(out.contains("\x1b[1;4mExample\x1b[0m\n"));
+    }
+}
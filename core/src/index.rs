@@ -1,30 +1,322 @@
+use crate::{cache::Cache, line_index::LineIndex};
 use compact_str::CompactString;
-use rnix::SyntaxKind;
-use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    num::NonZeroUsize,
+    sync::{Arc, Mutex, OnceLock},
+    thread,
+};
 
 mod ast_path;
 mod consume_ast;
+mod incremental;
+mod lambda;
+mod option;
 mod pos_index;
 
-#[derive(Debug, Default, Clone)]
+pub use ast_path::{AstPath, AstSeg};
+pub use incremental::Edit;
+pub use lambda::{Formal, Lambda, Param};
+pub use option::{DeclaredOption, NixOption, OptionTree};
+pub use pos_index::{InsertError, Pos, PosIndex, PosRange};
+
+/// The index of every file, cheap to clone since each [`FileIndex`] is immutable and shared.
+///
+/// Rather than mutating an index being read, a clone is modified and then swapped in, such that
+/// readers always see a consistent snapshot. See [`crate::server::SharedIndex`].
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct TancIndex {
-    files: BTreeMap<FileKey, FileIndex>,
+    files: BTreeMap<FileKey, Arc<FileIndex>>,
+    /// The root of every library indexed, such as a checkout of nixpkgs, along with the commit
+    /// its files are keyed by.
+    #[serde(default)]
+    libraries: BTreeMap<CompactString, Option<CompactString>>,
+    /// The on-disk cache of indexed files, consulted before parsing a file.
+    #[serde(skip)]
+    cache: Option<Cache>,
+    /// The tree of options of [`Self::option_tree`], built once per snapshot and reset by any
+    /// change of files.
+    #[serde(skip)]
+    option_tree: OnceLock<Arc<OptionTree>>,
 }
+/// The file listing the NixOS modules of nixpkgs, relative to the root of nixpkgs. Once indexed
+/// as part of a library, every NixOS option is assumed to be declared.
+const NIXOS_MODULE_LIST: &str = "nixos/modules/module-list.nix";
 impl TancIndex {
+    /// An empty index backed by the given on-disk cache.
+    pub fn with_cache(cache: Cache) -> Self {
+        Self {
+            cache: Some(cache),
+            ..Default::default()
+        }
+    }
+    /// An empty index backed by the user's cache, if it can be located.
+    pub fn with_user_cache() -> Self {
+        Cache::user().map(Self::with_cache).unwrap_or_default()
+    }
+    /// Index the source of a file, loading it from the cache if it was indexed before.
     pub fn insert(&mut self, file_path: impl Into<CompactString>, src: &str) {
+        self.insert_at(None, file_path, src);
+    }
+    /// Index the source of a file at the given commit, such as a file of a library.
+    ///
+    /// TODO: Associate sources of a working tree with a commit too, such as through a
+    /// `flake.lock`.
+    pub fn insert_at(
+        &mut self,
+        commit: Option<&str>,
+        file_path: impl Into<CompactString>,
+        src: &str,
+    ) {
+        let fi = self.index_file(commit, src);
+        self.insert_file_at(commit, file_path, fi);
+    }
+    /// Index the source of a file without inserting it, loading it from the cache if it was
+    /// indexed before. Useful to index without holding on to the index being modified.
+    pub fn index_file(&self, commit: Option<&str>, src: &str) -> FileIndex {
+        match &self.cache {
+            Some(cache) => cache.get_or_index(commit, src),
+            None => FileIndex::new(src),
+        }
+    }
+    /// Insert an already indexed file, replacing any previous index of it.
+    pub fn insert_file(
+        &mut self,
+        file_path: impl Into<CompactString>,
+        fi: impl Into<Arc<FileIndex>>,
+    ) {
+        self.insert_file_at(None, file_path, fi);
+    }
+    /// Insert an already indexed file at the given commit.
+    pub fn insert_file_at(
+        &mut self,
+        commit: Option<&str>,
+        file_path: impl Into<CompactString>,
+        fi: impl Into<Arc<FileIndex>>,
+    ) {
+        let key = FileKey {
+            commit: commit.map(Into::into),
+            file_path: file_path.into(),
+        };
         // drop the previous index. Currently no use in persisting on a full new file.
-        let _: Option<FileIndex> = self.files.insert(
-            FileKey {
-                // TODO: Include commit, if any? Need some way to associate any given source with a
-                // flakes.lock for automatic association.
+        let _: Option<Arc<FileIndex>> = self.files.insert(key, fi.into());
+        self.option_tree = OnceLock::new();
+    }
+    /// Index many files in parallel across a pool of worker threads, merging them into this
+    /// index once all are indexed.
+    ///
+    /// Each file is read by `load`, on the worker that indexes it, and skipped if that returns
+    /// `None`. `progress` is called after each file, whether or not it was loaded. Files are
+    /// keyed by the given commit, if any.
+    pub fn par_insert<I, L, P>(&mut self, commit: Option<&str>, files: I, load: L, progress: P)
+    where
+        I: IntoIterator,
+        I::IntoIter: Send,
+        L: Fn(I::Item) -> Option<(CompactString, String)> + Sync,
+        P: Fn() + Sync,
+    {
+        let files = Mutex::new(files.into_iter());
+        let workers = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        let worker = || {
+            let mut index = self.fork();
+            loop {
+                // Bind the next file first, so the lock is released before it's indexed.
+                let next = files.lock().expect("file queue lock poisoned").next();
+                let Some(file) = next else {
+                    break index;
+                };
+                if let Some((file_path, src)) = load(file) {
+                    index.insert_at(commit, file_path, &src);
+                }
+                progress();
+            }
+        };
+        let indexes: Vec<Self> = thread::scope(|s| {
+            let handles: Vec<_> = (0..workers).map(|_| s.spawn(worker)).collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("indexing worker panicked"))
+                .collect()
+        });
+        for index in indexes {
+            self.merge(index);
+        }
+    }
+    /// Move every file of `other` into this index, replacing any of the same key.
+    pub fn merge(&mut self, other: Self) {
+        self.files.extend(other.files);
+        self.libraries.extend(other.libraries);
+        self.option_tree = OnceLock::new();
+    }
+    /// Record the given directory as a library, whose files are keyed by the given commit.
+    pub fn insert_library(&mut self, root: impl Into<CompactString>, commit: Option<&str>) {
+        self.libraries.insert(root.into(), commit.map(Into::into));
+        self.option_tree = OnceLock::new();
+    }
+    /// Replace the library at `root`, if any, with `files`, an index of every file of it at
+    /// `commit`.
+    ///
+    /// Files of a library without a commit are keyed like those of the workspace, so such a
+    /// library mustn't overlap the workspace. See [`Self::remove_library`].
+    pub fn replace_library(&mut self, root: &str, commit: Option<&str>, files: Self) {
+        self.remove_library(root);
+        self.merge(files);
+        self.insert_library(root, commit);
+    }
+    /// Drop the given library along with every file under its root at its commit, which for a
+    /// library without a commit includes any other file under it.
+    pub fn remove_library(&mut self, root: &str) {
+        let Some(commit) = self.libraries.remove(root) else {
+            return;
+        };
+        let dir = format!("{root}/");
+        self.files
+            .retain(|key, _| !(key.commit == commit && key.file_path.starts_with(&dir)));
+        self.option_tree = OnceLock::new();
+    }
+    /// The root of every library along with its commit, if any.
+    pub fn libraries(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        self.libraries
+            .iter()
+            .map(|(root, commit)| (root.as_str(), commit.as_deref()))
+    }
+    /// Keep only the files for which `f` returns true.
+    pub fn retain(&mut self, mut f: impl FnMut(&FileKey) -> bool) {
+        self.files.retain(|key, _| f(key));
+        self.option_tree = OnceLock::new();
+    }
+    /// Remove the entries of the cache which won't be read again, keeping those of the commits
+    /// of the libraries of this index.
+    pub fn prune_cache(&self) -> std::io::Result<()> {
+        let Some(cache) = &self.cache else {
+            return Ok(());
+        };
+        cache.prune(
+            self.libraries
+                .values()
+                .filter_map(|commit| commit.as_deref()),
+        )
+    }
+    /// An empty index sharing the cache of this index, such as to be built concurrently and
+    /// then merged.
+    pub fn fork(&self) -> Self {
+        Self {
+            files: Default::default(),
+            libraries: Default::default(),
+            cache: self.cache.clone(),
+            option_tree: Default::default(),
+        }
+    }
+    /// Index the source of a file, bypassing the cache. For unsaved edits, which are unlikely to
+    /// be seen again.
+    pub fn update(&mut self, file_path: impl Into<CompactString>, src: &str) {
+        self.insert_file(file_path, FileIndex::new(src));
+    }
+    /// Every indexed file, ordered by path.
+    pub fn files(&self) -> impl Iterator<Item = (&FileKey, &FileIndex)> {
+        self.files.iter().map(|(key, fi)| (key, &**fi))
+    }
+    pub fn file(&self, file_path: impl Into<CompactString>) -> Option<&FileIndex> {
+        self.files
+            .get(&FileKey {
                 commit: None,
                 file_path: file_path.into(),
-            },
-            FileIndex::new(src),
-        );
+            })
+            .map(|fi| &**fi)
+    }
+    /// The file of the given key, along with the key as stored in the index.
+    pub fn file_entry(&self, key: &FileKey) -> Option<(&FileKey, &FileIndex)> {
+        self.files.get_key_value(key).map(|(key, fi)| (key, &**fi))
+    }
+    /// Every file of the given commit within the given directory, ordered by path.
+    pub fn files_within<'a>(
+        &'a self,
+        commit: Option<&'a str>,
+        dir: &str,
+    ) -> impl Iterator<Item = (&'a FileKey, &'a FileIndex)> + 'a {
+        let dir = CompactString::from(dir);
+        let start = FileKey {
+            commit: commit.map(Into::into),
+            file_path: dir.clone(),
+        };
+        self.files
+            .range(start..)
+            .take_while(move |(key, _)| {
+                key.commit.as_deref() == commit && key.file_path.starts_with(dir.as_str())
+            })
+            .map(|(key, fi)| (key, &**fi))
+    }
+    pub fn doc(
+        &self,
+        file_path: impl Into<CompactString>,
+        line: usize,
+        char: usize,
+    ) -> Option<&Doc> {
+        self.file(file_path)?.doc(line, char)
     }
-    pub fn doc(&self, file_path: impl Into<CompactString>, line: usize, char: usize) -> &Doc {
-        todo!()
+    /// Find the lambda most likely referred to by the given ident path, such as `["lib",
+    /// "foldl'"]` for `lib.foldl'`.
+    ///
+    /// NIT: This is a naive name based lookup, preferring lambdas in the given file and then
+    /// lambdas whose path shares the longest suffix with `idents`. It will be replaced once
+    /// identifiers are resolved through scopes.
+    pub fn resolve_lambda(
+        &self,
+        file_path: impl Into<CompactString>,
+        idents: &[&str],
+    ) -> Option<(&FileKey, &AstPath, &Lambda, Option<&Doc>)> {
+        let file_path = &file_path.into();
+        self.files
+            .iter()
+            .flat_map(|(key, fi)| {
+                fi.lambdas.iter().filter_map(move |(path, lambda)| {
+                    let suffix_len = path
+                        .idents()
+                        .rev()
+                        .zip(idents.iter().rev())
+                        .take_while(|(a, b)| a == *b)
+                        .count();
+                    if suffix_len == 0 {
+                        return None;
+                    }
+                    let rank = (key.file_path == *file_path, suffix_len);
+                    Some((rank, (key, path, lambda, fi.data.get(path))))
+                })
+            })
+            // `max_by_key` returns the last max, reverse to prefer the first.
+            .rev()
+            .max_by_key(|(rank, _)| *rank)
+            .map(|(_, found)| found)
+    }
+    /// The tree of every option declared by the indexed files, built on first use by each
+    /// snapshot of the index.
+    pub fn option_tree(&self) -> Arc<OptionTree> {
+        self.option_tree
+            .get_or_init(|| {
+                let mut tree = OptionTree::default();
+                for (file, fi) in self.files.iter() {
+                    for (binding, option) in fi.options.iter() {
+                        let Some(option_path) = binding.option_path() else {
+                            continue;
+                        };
+                        let declared = DeclaredOption {
+                            file: file.clone(),
+                            binding: binding.clone(),
+                            option: option.clone(),
+                        };
+                        tree.insert(option_path.into_iter().collect(), declared);
+                    }
+                }
+                tree.complete = self.libraries.iter().any(|(root, commit)| {
+                    self.files.contains_key(&FileKey {
+                        commit: commit.clone(),
+                        file_path: format!("{root}/{NIXOS_MODULE_LIST}").into(),
+                    })
+                });
+                Arc::new(tree)
+            })
+            .clone()
     }
     #[cfg(test)]
     pub fn docs(&self, file_path: impl Into<CompactString>) -> Vec<&Doc> {
@@ -39,30 +331,101 @@ impl TancIndex {
             .collect()
     }
 }
-#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord)]
-struct FileKey {
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct FileKey {
     pub commit: Option<CompactString>,
     pub file_path: CompactString,
 }
-#[derive(Debug, Default, Clone)]
+/// The serialized form of a [`FileIndex`] omits the [`PosIndex`], as it's rebuilt from the
+/// ranges of the bindings.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "FileIndexParts")]
 pub struct FileIndex {
-    pos_index: BTreeMap<(usize, usize), String>,
-    data: BTreeMap<String, Doc>,
+    #[serde(skip_serializing)]
+    pos_index: PosIndex<AstPath>,
+    data: BTreeMap<AstPath, Doc>,
+    lambdas: BTreeMap<AstPath, Lambda>,
+    options: BTreeMap<AstPath, NixOption>,
+    /// The full source range of every binding.
+    ranges: BTreeMap<AstPath, PosRange>,
+}
+#[derive(Deserialize)]
+struct FileIndexParts {
+    data: BTreeMap<AstPath, Doc>,
+    lambdas: BTreeMap<AstPath, Lambda>,
+    #[serde(default)]
+    options: BTreeMap<AstPath, NixOption>,
+    ranges: BTreeMap<AstPath, PosRange>,
+}
+impl From<FileIndexParts> for FileIndex {
+    fn from(parts: FileIndexParts) -> Self {
+        // Insert outer ranges before the ranges they contain, so that none are re-parented.
+        let mut bindings: Vec<_> = parts.ranges.iter().collect();
+        bindings.sort_by_key(|(_, range)| (range.start(), std::cmp::Reverse(range.end())));
+        let mut pos_index = PosIndex::new();
+        for (path, range) in bindings {
+            if let Err(err) = pos_index.insert(*range, path.clone()) {
+                tracing::error!("failed to index the position of {path}: {err}");
+            }
+        }
+        Self {
+            pos_index,
+            data: parts.data,
+            lambdas: parts.lambdas,
+            options: parts.options,
+            ranges: parts.ranges,
+        }
+    }
 }
 impl FileIndex {
     pub fn new(s: &str) -> Self {
         let ast = rnix::Root::parse(s);
-        dbg!(&ast.tree());
-        let docs = consume_ast::docs_from_ast(ast.syntax().into());
-        dbg!(&ast.syntax().kind() == &SyntaxKind::NODE_ROOT);
-        todo!();
+        let consumed = consume_ast::consume_ast(&LineIndex::new(s), ast.syntax().into());
+        FileIndexParts {
+            data: consumed.docs,
+            lambdas: consumed.lambdas,
+            options: consumed.options,
+            ranges: consumed
+                .bindings
+                .into_iter()
+                .map(|(range, path)| (path, range))
+                .collect(),
+        }
+        .into()
+    }
+    /// The path of the innermost binding at the given position.
+    pub fn path(&self, line: usize, char: usize) -> Option<&AstPath> {
+        self.pos_index.get(&(line, char).into())
+    }
+    pub fn doc(&self, line: usize, char: usize) -> Option<&Doc> {
+        self.data.get(self.path(line, char)?)
+    }
+    pub fn path_doc(&self, path: &AstPath) -> Option<&Doc> {
+        self.data.get(path)
+    }
+    pub fn lambda(&self, path: &AstPath) -> Option<&Lambda> {
+        self.lambdas.get(path)
+    }
+    pub fn lambdas(&self) -> impl Iterator<Item = (&AstPath, &Lambda)> {
+        self.lambdas.iter()
+    }
+    /// The option declared by the binding of the given path, if any.
+    pub fn option(&self, path: &AstPath) -> Option<&NixOption> {
+        self.options.get(path)
+    }
+    pub fn options(&self) -> impl Iterator<Item = (&AstPath, &NixOption)> {
+        self.options.iter()
+    }
+    /// Every binding in the file along with its full source range, ordered by path.
+    pub fn bindings(&self) -> impl Iterator<Item = (&AstPath, &PosRange)> {
+        self.ranges.iter()
     }
-    pub fn doc(&self, line: usize, char: usize) -> &Doc {
-        todo!()
+    pub fn range(&self, path: &AstPath) -> Option<&PosRange> {
+        self.ranges.get(path)
     }
     #[cfg(test)]
     pub fn docs(&self) -> Vec<&Doc> {
-        self.data.iter().map(|(_, doc)| doc).collect()
+        self.data.values().collect()
     }
 }
 
@@ -72,7 +435,7 @@ pub struct AstId {
     pub id: u64,
 }
 
-#[derive(Debug, Default, Clone, PartialEq, Hash)]
+#[derive(Debug, Default, Clone, PartialEq, Hash, Serialize, Deserialize)]
 pub struct Doc {
     pub doc: Option<String>,
 }
@@ -1,6 +1,8 @@
 use compact_str::CompactString;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 
-#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct AstPath(Vec<AstSeg>);
 impl AstPath {
     pub fn new_child(&self, seg: impl Into<AstSeg>) -> Self {
@@ -8,11 +10,65 @@ impl AstPath {
         child.0.push(seg.into());
         child
     }
+    pub fn segs(&self) -> &[AstSeg] {
+        &self.0
+    }
+    pub fn parent(&self) -> Option<Self> {
+        let (_, parent) = self.0.split_last()?;
+        Some(Self(parent.to_vec()))
+    }
+    pub fn starts_with(&self, other: &AstPath) -> bool {
+        self.0.starts_with(&other.0)
+    }
+    /// Whether this path is reachable from outside of the file, ie is not within a `let` block.
+    pub fn is_public(&self) -> bool {
+        !self.0.contains(&AstSeg::Let)
+    }
+    /// The identifiers of this path, skipping any structural segments such as
+    /// [`AstSeg::Let`].
+    pub fn idents(&self) -> impl DoubleEndedIterator<Item = &str> + '_ {
+        self.0.iter().filter_map(|seg| match seg {
+            AstSeg::Ident(ident) => Some(ident.as_str()),
+            AstSeg::AttrSet | AstSeg::Let => None,
+        })
+    }
+    /// The option declared at this path, such as `["services", "foo", "enable"]` for
+    /// `options.services.foo.enable`. `None` if this path is not beneath an `options` attr.
+    pub fn option_path(&self) -> Option<Vec<&str>> {
+        if !self.is_public() {
+            return None;
+        }
+        let mut idents = self.idents().skip_while(|ident| *ident != "options");
+        idents.next()?;
+        Some(idents.collect())
+    }
+    /// The final identifier of this path, if any.
+    pub fn name(&self) -> Option<&str> {
+        self.idents().last()
+    }
+}
+impl<S: Into<AstSeg>> FromIterator<S> for AstPath {
+    fn from_iter<I: IntoIterator<Item = S>>(iter: I) -> Self {
+        Self(iter.into_iter().map(Into::into).collect())
+    }
+}
+impl fmt::Display for AstPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, seg) in self.0.iter().enumerate() {
+            if i != 0 {
+                write!(f, ".")?;
+            }
+            write!(f, "{seg}")?;
+        }
+        Ok(())
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum AstSeg {
     AttrSet,
+    /// The bindings of a `let` block, distinguishing them from attributes of the same name.
+    Let,
     Ident(CompactString),
 }
 impl From<CompactString> for AstSeg {
@@ -20,3 +76,17 @@ impl From<CompactString> for AstSeg {
         Self::Ident(value)
     }
 }
+impl From<&str> for AstSeg {
+    fn from(value: &str) -> Self {
+        Self::Ident(value.into())
+    }
+}
+impl fmt::Display for AstSeg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AttrSet => write!(f, "{{}}"),
+            Self::Let => write!(f, "<let>"),
+            Self::Ident(ident) => write!(f, "{ident}"),
+        }
+    }
+}
@@ -1,41 +1,119 @@
-use crate::index::ast_path::AstSeg;
-
-use super::{ast_path::AstPath, Doc};
+use super::{
+    ast_path::{AstPath, AstSeg},
+    lambda::{Formal, Lambda, Param},
+    option::NixOption,
+    pos_index::PosRange,
+    Doc,
+};
+use crate::line_index::{Encoding, LineIndex};
 use compact_str::CompactString;
 use rnix::{
-    ast::{AstNode, AstToken, Comment, Ident},
-    NodeOrToken, SyntaxKind, SyntaxNode, SyntaxToken, TextRange,
+    ast::{self, AstToken, Comment},
+    NodeOrToken, SyntaxKind, SyntaxNode, SyntaxToken, TextSize,
 };
-use std::collections::BTreeMap;
+use rowan::ast::AstNode;
+use std::{collections::BTreeMap, mem};
 use tracing::error;
 
-pub fn docs_from_ast(
+/// Walk the given AST of the source of `line_index`, producing the docs, lambdas, options and
+/// binding ranges found within it.
+pub fn consume_ast(
+    line_index: &LineIndex,
     node_or_token: NodeOrToken<SyntaxNode, SyntaxToken>,
-) -> BTreeMap<AstPath, Doc> {
-    let mut shared_state = Default::default();
-    let mut consume = Root::new(&mut shared_state, node_or_token);
-    shared_state.ast_index
+) -> ConsumedAst {
+    let mut shared_state = SharedState {
+        line_index,
+        consumed: Default::default(),
+        at_line_start: true,
+    };
+    Root::new(&mut shared_state, node_or_token);
+    shared_state.consumed
 }
 
 #[derive(Debug, Default)]
-struct SharedState {
-    ast_index: BTreeMap<AstPath, Doc>,
-    /// A cursor of the current line for indexing.
-    ///
-    line_cursor: usize,
-    line_cursor_char_offset: usize,
+pub struct ConsumedAst {
+    pub docs: BTreeMap<AstPath, Doc>,
+    pub lambdas: BTreeMap<AstPath, Lambda>,
+    /// The NixOS options declared by bindings, such as `enable = mkEnableOption "foo";`.
+    pub options: BTreeMap<AstPath, NixOption>,
+    /// The full source range of every binding, in the order they were encountered.
+    pub bindings: Vec<(PosRange, AstPath)>,
 }
-impl SharedState {
-    // pub fn consume_whitespace(&mut self, )
+
+#[derive(Debug)]
+struct SharedState<'s> {
+    /// The lines of the source, from which every position is derived.
+    line_index: &'s LineIndex<'s>,
+    consumed: ConsumedAst,
+    /// Whether only whitespace has been seen since the last newline. Comments trailing code on
+    /// the same line are not treated as docs for the following binding.
+    at_line_start: bool,
+}
+impl SharedState<'_> {
+    fn token(&mut self, token: SyntaxToken, comment_buf: &mut Vec<Comment>) {
+        match token.kind() {
+            SyntaxKind::TOKEN_COMMENT => {
+                let Some(comment) = Comment::cast(token) else {
+                    error!("TOKEN_COMMENT failed to cast to Comment");
+                    return;
+                };
+                if self.at_line_start {
+                    comment_buf.push(comment);
+                }
+            },
+            SyntaxKind::TOKEN_WHITESPACE => {
+                let newline_count = self.newline_count(&token);
+                // A blank line detaches any preceding comments from the next binding.
+                if newline_count > 1 {
+                    comment_buf.clear();
+                }
+                if newline_count > 0 {
+                    self.at_line_start = true;
+                }
+                return;
+            },
+            _ => {
+                comment_buf.clear();
+            },
+        }
+        self.at_line_start = false;
+    }
+    /// The number of line endings within the given token.
+    fn newline_count(&self, token: &SyntaxToken) -> usize {
+        let range = token.text_range();
+        self.pos(range.end()).0 - self.pos(range.start()).0
+    }
+    /// Consume every token in the given node, without indexing anything.
+    fn skip(&mut self, node: &SyntaxNode) {
+        let mut comment_buf = Vec::new();
+        for token in node
+            .descendants_with_tokens()
+            .filter_map(NodeOrToken::into_token)
+        {
+            self.token(token, &mut comment_buf);
+        }
+    }
+    /// The `(line, char)` of the given offset.
+    fn pos(&self, offset: TextSize) -> (usize, usize) {
+        let pos = self.line_index.position(offset.into(), Encoding::Utf8);
+        (pos.line, pos.char)
+    }
+    fn record_doc(&mut self, path: &AstPath, comment_buf: &[Comment]) {
+        if let Some(doc) = doc_from_comments(comment_buf) {
+            self.consumed
+                .docs
+                .insert(path.clone(), Doc { doc: Some(doc) });
+        }
+    }
 }
 #[derive(Debug)]
-struct Root<'a> {
-    shared: &'a mut SharedState,
+struct Root<'a, 's> {
+    shared: &'a mut SharedState<'s>,
     comment_buf: Vec<Comment>,
 }
-impl<'a> Root<'a> {
+impl<'a, 's> Root<'a, 's> {
     pub fn new(
-        shared_state: &'a mut SharedState,
+        shared_state: &'a mut SharedState<'s>,
         node_or_token: NodeOrToken<SyntaxNode, SyntaxToken>,
     ) -> Self {
         let mut self_ = Self {
@@ -48,215 +126,340 @@ impl<'a> Root<'a> {
     fn node_or_token(&mut self, node_or_token: NodeOrToken<SyntaxNode, SyntaxToken>) {
         match node_or_token {
             NodeOrToken::Node(node) => self.node(node),
-            NodeOrToken::Token(token) => self.token(token),
+            NodeOrToken::Token(token) => self.shared.token(token, &mut self.comment_buf),
         }
     }
     fn node(&mut self, node: SyntaxNode) {
-        dbg!(node.kind());
         match node.kind() {
             SyntaxKind::NODE_ROOT => {
                 for child in node.children_with_tokens() {
                     self.node_or_token(child);
                 }
             },
-            SyntaxKind::NODE_ATTR_SET => {
-                // // NIT: attrSet is just an experimental name, undecided atm.
-                // let path = self.path.new_child(AstSeg::AttrSet);
-                // let mut children: Vec<CompactString> = Vec::new();
-                // for child in node.children_with_tokens() {
-                //     let mut ast = Root::new(self.shared, path.clone());
-                //     ast.node_or_token(child);
-                // }
+            _ => {
+                let path = AstPath::default();
+                // The doc of a file is only its first comment.
+                let doc = self.comment_buf.get(..1).unwrap_or_default();
+                self.shared.record_doc(&path, doc);
+                self.comment_buf.clear();
+                let start = self.shared.pos(node.text_range().start());
+                Expr::consume(self.shared, path.clone(), &node);
+                let end = self.shared.pos(node.text_range().end());
+                self.shared
+                    .consumed
+                    .bindings
+                    .push(((start.0, start.1, end.0, end.1).into(), path));
             },
-            _ => todo!(),
         }
     }
-    fn token(&mut self, token: SyntaxToken) {
-        dbg!(token.kind());
-        match token.kind() {
-            SyntaxKind::TOKEN_COMMENT => {
-                // NIT: Are there cases where a comment should advance the cursor?
-                let Some(comment) = Comment::cast(token) else {
-                    error!("TOKEN_COMMENT failed to cast to Comment");
-                    return;
-                };
-                // TODO: Parse out TANC syntax, etc. Not sure if i want to parse it here or in the
-                // consumer of the doc.
-                self.comment_buf.push(comment);
+}
+/// Any expression which may contain bindings. Expressions such as lambdas and `let` blocks are
+/// transparent, in that the bindings within them share the path of the expression itself.
+struct Expr<'a, 's> {
+    shared: &'a mut SharedState<'s>,
+    path: AstPath,
+}
+impl Expr<'_, '_> {
+    pub fn consume(shared: &mut SharedState, path: AstPath, node: &SyntaxNode) {
+        let mut self_ = Expr { shared, path };
+        self_.node(node);
+    }
+    fn node(&mut self, node: &SyntaxNode) {
+        match node.kind() {
+            SyntaxKind::NODE_ATTR_SET => {
+                AttrSet::consume(self.shared, self.path.clone(), node);
             },
-            SyntaxKind::TOKEN_WHITESPACE => {
-                let start_incl: usize = token.text_range().start().into();
-                let (count, index) = token
-                    .text()
-                    .char_indices()
-                    // NIT: Support various newline types? I think just \n vs \r\n, but i'm not
-                    // positive how best to support multi-os here. It also may
-                    // not matter, as if it's always \r\n, then \n is still the
-                    // final char and can be counted and indexed in the same
-                    // manner.
-                    .filter(|&(_, c)| c == '\n')
-                    .enumerate()
-                    .last()
-                    .map(|(line_index, (index, _))| (line_index + 1, index))
-                    .unwrap_or_default();
-                self.shared.line_cursor += count;
-                self.shared.line_cursor_char_offset = start_incl + index;
+            SyntaxKind::NODE_LET_IN => {
+                let let_path = self.path.new_child(AstSeg::Let);
+                let mut comment_buf = Vec::new();
+                for child in node.children_with_tokens() {
+                    match child {
+                        NodeOrToken::Token(token) => self.shared.token(token, &mut comment_buf),
+                        NodeOrToken::Node(child) => match child.kind() {
+                            SyntaxKind::NODE_ATTRPATH_VALUE => {
+                                let comment_buf = mem::take(&mut comment_buf);
+                                AttrPathValue::consume(self.shared, &let_path, comment_buf, &child);
+                            },
+                            SyntaxKind::NODE_INHERIT => {
+                                let comment_buf = mem::take(&mut comment_buf);
+                                Inherit::consume(self.shared, &let_path, comment_buf, &child);
+                            },
+                            _ => self.node(&child),
+                        },
+                    }
+                }
+            },
+            SyntaxKind::NODE_LAMBDA => {
+                let mut lambda = Lambda::default();
+                self.lambda(node, &mut lambda);
+                self.shared
+                    .consumed
+                    .lambdas
+                    .insert(self.path.clone(), lambda);
             },
-            SyntaxKind::TOKEN_L_BRACE | SyntaxKind::TOKEN_R_BRACE => {},
-            _ => todo!(),
+            SyntaxKind::NODE_APPLY => {
+                if let Some(option) =
+                    ast::Apply::cast(node.clone()).and_then(|apply| NixOption::from_apply(&apply))
+                {
+                    self.shared
+                        .consumed
+                        .options
+                        .insert(self.path.clone(), option);
+                }
+                self.shared.skip(node);
+            },
+            SyntaxKind::NODE_PAREN | SyntaxKind::NODE_WITH | SyntaxKind::NODE_ASSERT => {
+                // Only the final expression is the value, the rest (`with` targets, assertions)
+                // are skipped.
+                let body = node.children().last();
+                let mut comment_buf = Vec::new();
+                for child in node.children_with_tokens() {
+                    match child {
+                        NodeOrToken::Token(token) => self.shared.token(token, &mut comment_buf),
+                        NodeOrToken::Node(child) if Some(&child) == body.as_ref() => {
+                            self.node(&child)
+                        },
+                        NodeOrToken::Node(child) => self.shared.skip(&child),
+                    }
+                }
+            },
+            _ => self.shared.skip(node),
         }
     }
-    fn doc_pos(&self, range: TextRange) -> (usize, usize) {
-        todo!()
+    /// Consume the given lambda, appending the params of it and any directly curried lambdas.
+    fn lambda(&mut self, node: &SyntaxNode, lambda: &mut Lambda) {
+        let mut comment_buf = Vec::new();
+        for child in node.children_with_tokens() {
+            match child {
+                NodeOrToken::Token(token) => self.shared.token(token, &mut comment_buf),
+                NodeOrToken::Node(child) => {
+                    if let Some(param) = ast::Param::cast(child.clone()) {
+                        lambda.params.extend(param_from_ast(param));
+                        self.shared.skip(&child);
+                    } else if child.kind() == SyntaxKind::NODE_LAMBDA {
+                        self.lambda(&child, lambda);
+                    } else {
+                        self.node(&child);
+                    }
+                },
+            }
+        }
     }
 }
-#[derive(Debug)]
-struct AttrSet<'a> {
-    shared: &'a mut SharedState,
+struct AttrSet<'a, 's> {
+    shared: &'a mut SharedState<'s>,
     path: AstPath,
     comment_buf: Vec<Comment>,
-    children: Vec<CompactString>,
 }
-impl AttrSet<'_> {
-    pub fn new(
-        shared: &mut SharedState,
-        path: AstPath,
-        comment_buf: Vec<Comment>,
-        node: SyntaxNode,
-    ) -> AttrSet<'_> {
-        todo!()
-    }
-}
-#[derive(Debug)]
-struct AttrPathValue<'a> {
-    shared: &'a mut SharedState,
-    comment_buf: Vec<Comment>,
-}
-impl AttrPathValue<'_> {
-    pub fn new(
-        shared: &'_ mut SharedState,
-        comment_buf: Vec<Comment>,
-        node: SyntaxNode,
-    ) -> AttrPathValue<'_> {
-        let mut self_ = AttrPathValue {
+impl AttrSet<'_, '_> {
+    pub fn consume(shared: &mut SharedState, path: AstPath, node: &SyntaxNode) {
+        let mut self_ = AttrSet {
             shared,
-            comment_buf,
+            path,
+            comment_buf: Vec::new(),
         };
-        self_.node(node);
-        self_
-    }
-    fn node(&mut self, node: SyntaxNode) {
-        dbg!(node.kind());
-        match node.kind() {
-            SyntaxKind::NODE_ATTRPATH_VALUE => {
-                // for child in node.children_with_tokens() {
-                //     self.node_or_token(child);
-                // }
-                todo!();
-            },
-            SyntaxKind::NODE_ATTRPATH => {
-                eprintln!("{}", node.text());
-                // for child in node.children() {
-                //     self.node(child);
-                // }
-            },
-            SyntaxKind::NODE_IDENT => {
-                // for child in node.children_with_tokens() {
-                //     self.node_or_token(child);
-                // }
-            },
-            _ => todo!(),
+        for child in node.children_with_tokens() {
+            self_.node_or_token(child);
         }
     }
-    fn token(&mut self, token: SyntaxToken) {
-        dbg!(token.kind());
-        match token.kind() {
-            SyntaxKind::TOKEN_COMMENT => {
-                // NIT: Are there cases where a comment should advance the cursor?
-                let Some(comment) = Comment::cast(token) else {
-                    error!("TOKEN_COMMENT failed to cast to Comment");
-                    return;
-                };
-                // TODO: Parse out TANC syntax, etc. Not sure if i want to parse it here or in the
-                // consumer of the doc.
-                self.comment_buf.push(comment);
-            },
-            SyntaxKind::TOKEN_WHITESPACE => {
-                let start_incl: usize = token.text_range().start().into();
-                let (count, index) = token
-                    .text()
-                    .char_indices()
-                    // NIT: Support various newline types? I think just \n vs \r\n, but i'm not
-                    // positive how best to support multi-os here. It also may
-                    // not matter, as if it's always \r\n, then \n is still the
-                    // final char and can be counted and indexed in the same
-                    // manner.
-                    .filter(|&(_, c)| c == '\n')
-                    .enumerate()
-                    .last()
-                    .map(|(line_index, (index, _))| (line_index + 1, index))
-                    .unwrap_or_default();
-                self.shared.line_cursor += count;
-                self.shared.line_cursor_char_offset = start_incl + index;
+    fn node_or_token(&mut self, node_or_token: NodeOrToken<SyntaxNode, SyntaxToken>) {
+        match node_or_token {
+            NodeOrToken::Node(node) => match node.kind() {
+                SyntaxKind::NODE_ATTRPATH_VALUE => {
+                    let comment_buf = mem::take(&mut self.comment_buf);
+                    AttrPathValue::consume(self.shared, &self.path, comment_buf, &node);
+                },
+                SyntaxKind::NODE_INHERIT => {
+                    let comment_buf = mem::take(&mut self.comment_buf);
+                    Inherit::consume(self.shared, &self.path, comment_buf, &node);
+                },
+                _ => self.shared.skip(&node),
             },
-            SyntaxKind::TOKEN_L_BRACE | SyntaxKind::TOKEN_R_BRACE => {},
-            _ => todo!(),
+            NodeOrToken::Token(token) => self.shared.token(token, &mut self.comment_buf),
         }
     }
 }
+/// A single `a.b.c = value;` binding, within an attrset or `let` block.
+struct AttrPathValue;
+impl AttrPathValue {
+    pub fn consume(
+        shared: &mut SharedState,
+        parent: &AstPath,
+        comment_buf: Vec<Comment>,
+        node: &SyntaxNode,
+    ) {
+        let Some(path) = ast::AttrpathValue::cast(node.clone())
+            .and_then(|apv| apv.attrpath())
+            .and_then(|attrpath| {
+                attrpath
+                    .attrs()
+                    .map(|attr| attr_name(&attr).map(AstSeg::Ident))
+                    .try_fold(parent.clone(), |path, seg| Some(path.new_child(seg?)))
+            })
+        else {
+            // Dynamic attrs can't be addressed statically.
+            shared.skip(node);
+            return;
+        };
+        shared.record_doc(&path, &comment_buf);
 
-enum TokenOrNode {
-    /// An unused or unrecognized node or token.
-    Skip,
-    Comment {
-        comment: Comment,
-    },
-    Whitespace {
-        /// The total count of newlines in this whitespace.
-        ///
-        /// See also: [`SharedState::line_cursor`]
-        newline_count: usize,
-        /// The char offset of the final newline in this whitespace. Offset is relative to the
-        /// origin source, and is necessary to understand syntax positioning relative to
-        /// lines.
-        ///
-        /// See also: [`SharedState::line_cursor_char_offset`]
-        last_newline_char_offset: usize,
-    },
+        let start = shared.pos(node.text_range().start());
+        let value = ast::AttrpathValue::cast(node.clone())
+            .and_then(|apv| apv.value())
+            .map(|value| value.syntax().clone());
+        let mut scratch_buf = Vec::new();
+        for child in node.children_with_tokens() {
+            match child {
+                NodeOrToken::Token(token) => shared.token(token, &mut scratch_buf),
+                NodeOrToken::Node(child) if Some(&child) == value.as_ref() => {
+                    Expr::consume(shared, path.clone(), &child);
+                },
+                NodeOrToken::Node(child) => shared.skip(&child),
+            }
+        }
+        let end = shared.pos(node.text_range().end());
+        shared
+            .consumed
+            .bindings
+            .push(((start.0, start.1, end.0, end.1).into(), path));
+    }
 }
-impl From<SyntaxToken> for TokenOrNode {
-    fn from(token: SyntaxToken) -> Self {
-        match token.kind() {
-            SyntaxKind::TOKEN_COMMENT => {
-                // NIT: Are there cases where a comment should advance the cursor? May need to
-                // include positional information.
-                Comment::cast(token).unwrap_or_else(|| {
-                    error!("TOKEN_COMMENT failed to cast to Comment");
-                    Self::Skip
+/// An `inherit a b;` or `inherit (x) a b;` statement, recording each inherited name as a binding.
+struct Inherit;
+impl Inherit {
+    pub fn consume(
+        shared: &mut SharedState,
+        parent: &AstPath,
+        comment_buf: Vec<Comment>,
+        node: &SyntaxNode,
+    ) {
+        let attrs: Vec<_> = ast::Inherit::cast(node.clone())
+            .into_iter()
+            .flat_map(|inherit| inherit.attrs())
+            .filter_map(|attr| {
+                let name = attr_name(&attr)?;
+                Some((attr.syntax().text_range(), parent.new_child(name)))
+            })
+            .collect();
+        for (_, path) in attrs.iter() {
+            shared.record_doc(path, &comment_buf);
+        }
+        let mut attrs = attrs.into_iter().peekable();
+        let mut scratch_buf = Vec::new();
+        for token in node
+            .descendants_with_tokens()
+            .filter_map(NodeOrToken::into_token)
+        {
+            let token_range = token.text_range();
+            shared.token(token, &mut scratch_buf);
+            if let Some((range, path)) =
+                attrs.next_if(|(range, _)| range.end() == token_range.end())
+            {
+                let start = shared.pos(range.start());
+                let end = shared.pos(range.end());
+                shared
+                    .consumed
+                    .bindings
+                    .push(((start.0, start.1, end.0, end.1).into(), path));
+            }
+        }
+    }
+}
+/// The static name of an attr, if it has one.
+fn attr_name(attr: &ast::Attr) -> Option<CompactString> {
+    match attr {
+        ast::Attr::Ident(ident) => ident_name(ident),
+        ast::Attr::Str(s) => match s.normalized_parts().as_slice() {
+            [ast::InterpolPart::Literal(lit)] => Some(lit.into()),
+            [] => Some(CompactString::default()),
+            _ => None,
+        },
+        ast::Attr::Dynamic(_) => None,
+    }
+}
+fn ident_name(ident: &ast::Ident) -> Option<CompactString> {
+    // `or` is a valid identifier, but is lexed as a keyword token.
+    Some(ident.syntax().first_token()?.text().into())
+}
+fn param_from_ast(param: ast::Param) -> Option<Param> {
+    match param {
+        ast::Param::IdentParam(ident_param) => ident_param
+            .ident()
+            .as_ref()
+            .and_then(ident_name)
+            .map(Param::Ident),
+        ast::Param::Pattern(pattern) => Some(Param::Pattern {
+            formals: pattern
+                .pat_entries()
+                .filter_map(|entry| {
+                    Some(Formal {
+                        name: ident_name(&entry.ident()?)?,
+                        default: entry.default().map(|d| d.syntax().text().to_string()),
+                    })
                 })
-            },
-            SyntaxKind::TOKEN_WHITESPACE => {
-                let start_incl: usize = token.text_range().start().into();
-                let (count, index) = token
-                    .text()
-                    .char_indices()
-                    // NIT: Support various newline types? I think just \n vs \r\n, but i'm not
-                    // positive how best to support multi-os here. It also may
-                    // not matter, as if it's always \r\n, then \n is still the
-                    // final char and can be counted and indexed in the same
-                    // manner.
-                    .filter(|&(_, c)| c == '\n')
-                    .enumerate()
-                    .last()
-                    .map(|(line_index, (index, _))| (line_index + 1, index))
-                    .unwrap_or_default();
-                Self::Whitespace {
-                    newline_count: count,
-                    last_newline_char_offset: start_incl + index,
-                }
-            },
-            SyntaxKind::TOKEN_L_BRACE | SyntaxKind::TOKEN_R_BRACE => {},
-            _ => todo!(),
+                .collect(),
+            ellipsis: pattern.ellipsis_token().is_some(),
+            bind: pattern
+                .pat_bind()
+                .and_then(|bind| bind.ident())
+                .as_ref()
+                .and_then(ident_name),
+        }),
+    }
+}
+/// Convert a run of comments into doc text, stripping comment syntax and common indentation.
+fn doc_from_comments(comments: &[Comment]) -> Option<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for comment in comments {
+        let raw = comment.syntax().text();
+        if raw.starts_with('#') {
+            // Line comments end before `\n`, which leaves the `\r` of `\r\n`.
+            let line = comment.text().trim_end_matches('\r');
+            lines.push(line.strip_prefix(' ').unwrap_or(line).to_owned());
+        } else {
+            // `/** */` doc comments are treated the same as `/* */`.
+            let text = comment.text();
+            let text = text.strip_prefix('*').unwrap_or(text);
+            lines.extend(dedent_block(text));
         }
     }
+    while lines.last().map_or(false, |l| l.trim().is_empty()) {
+        lines.pop();
+    }
+    let first = lines.iter().position(|l| !l.trim().is_empty())?;
+    Some(lines[first..].join("\n"))
+}
+/// Dedent the lines of a block comment, also stripping a leading `*` gutter if every line has one.
+fn dedent_block(text: &str) -> Vec<String> {
+    let line_index = LineIndex::new(text);
+    let mut lines = line_index.lines();
+    let first = lines.next().unwrap_or_default().trim().to_owned();
+    let rest: Vec<&str> = lines.collect();
+    let gutter = !rest.is_empty()
+        && rest
+            .iter()
+            .filter(|l| !l.trim().is_empty())
+            .all(|l| l.trim_start().starts_with('*'));
+    let rest: Vec<&str> = if gutter {
+        rest.iter()
+            .map(|l| {
+                let l = l.trim_start().strip_prefix('*').unwrap_or_default();
+                l.strip_prefix(' ').unwrap_or(l)
+            })
+            .collect()
+    } else {
+        rest
+    };
+    let indent = rest
+        .iter()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.len() - l.trim_start().len())
+        .min()
+        .unwrap_or_default();
+    std::iter::once(first)
+        .chain(
+            rest.iter()
+                .map(|l| l.get(indent..).unwrap_or_default().trim_end().to_owned()),
+        )
+        .collect()
 }
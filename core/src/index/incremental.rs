@@ -0,0 +1,251 @@
+//! Updating the index of a file for an edit of its source, re-parsing only the binding
+//! enclosing the edit rather than the entire file.
+use super::{
+    ast_path::{AstPath, AstSeg},
+    consume_ast::consume_ast,
+    pos_index::{Pos, PosRange},
+    FileIndex,
+};
+use crate::line_index::{Encoding, LineIndex};
+use rnix::ast::{self, HasEntry};
+use std::{cmp::Reverse, collections::BTreeMap};
+
+/// A change to the source of a file, replacing a range of it with new text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    /// The replaced range, in positions of the source before the edit.
+    pub range: PosRange,
+    pub text: String,
+}
+impl Edit {
+    /// Apply this edit to the source it was made against.
+    pub fn apply(&self, src: &mut String) {
+        let line_index = LineIndex::new(src);
+        let start = line_index.offset(self.range.start(), Encoding::Utf8);
+        let end = line_index
+            .offset(self.range.end(), Encoding::Utf8)
+            .max(start);
+        src.replace_range(start..end, &self.text);
+    }
+    /// The end of the inserted text, in positions of the source after the edit.
+    fn new_end(&self) -> Pos {
+        let start = self.range.start();
+        let text = LineIndex::new(&self.text);
+        let end = text.position(self.text.len(), Encoding::Utf8);
+        if end.line == 0 {
+            Pos {
+                line: start.line,
+                char: start.char + end.char,
+            }
+        } else {
+            Pos {
+                line: start.line + end.line,
+                char: end.char,
+            }
+        }
+    }
+    /// Move a position of the source before the edit to where it is after the edit. Positions
+    /// within the replaced range are left as they are.
+    fn shift(&self, pos: Pos) -> Pos {
+        let end = self.range.end();
+        if pos < end {
+            return pos;
+        }
+        let new_end = self.new_end();
+        if pos.line == end.line {
+            Pos {
+                line: new_end.line,
+                char: new_end.char + (pos.char - end.char),
+            }
+        } else {
+            Pos {
+                line: pos.line - end.line + new_end.line,
+                char: pos.char,
+            }
+        }
+    }
+    fn shift_range(&self, range: &PosRange) -> PosRange {
+        let (start, end) = (self.shift(range.start()), self.shift(range.end()));
+        (start.line, start.char, end.line, end.char).into()
+    }
+}
+impl FileIndex {
+    /// The index of `src`, the source of this index after the given edit.
+    ///
+    /// Only the innermost binding enclosing the edit is re-parsed. The entries of every other
+    /// binding are copied as they are, with those after the edit moved by the size of it, which
+    /// takes time linear in the number of bindings but is far cheaper than parsing the file. If
+    /// re-parsing that binding alone would change the bindings around it, such as when the edit
+    /// adds a sibling or breaks the syntax of it, the next enclosing binding is tried instead.
+    /// Edits outside of any binding re-index the entire file.
+    pub fn edit(&self, src: &str, edit: &Edit) -> FileIndex {
+        let (start, end) = (edit.range.start(), edit.range.end());
+        // Comments before a binding are its doc, and text after it may be a new sibling, so the
+        // edit must be strictly within it.
+        let mut enclosing: Vec<_> = self
+            .ranges
+            .iter()
+            .filter(|(_, range)| range.start() < start && end < range.end())
+            .collect();
+        enclosing.sort_by_key(|(_, range)| (Reverse(range.start()), range.end()));
+        for (i, (path, range)) in enclosing.iter().enumerate() {
+            let Some((parent, _)) = enclosing.get(i + 1) else {
+                // The root expression, which is no cheaper than the entire file.
+                break;
+            };
+            if let Some(fi) = self.reindex_binding(src, edit, path, range, parent) {
+                tracing::debug!("re-indexed {path} for edit at {start:?}");
+                return fi;
+            }
+        }
+        FileIndex::new(src)
+    }
+    /// Re-index the binding of `path`, which encloses the edit, by parsing it alone within an
+    /// attrset. `None` if it no longer parses as a single binding.
+    fn reindex_binding(
+        &self,
+        src: &str,
+        edit: &Edit,
+        path: &AstPath,
+        range: &PosRange,
+        parent: &AstPath,
+    ) -> Option<FileIndex> {
+        let new_range = edit.shift_range(range);
+        let line_index = LineIndex::new(src);
+        let text = src.get(
+            line_index.offset(new_range.start(), Encoding::Utf8)
+                ..line_index.offset(new_range.end(), Encoding::Utf8),
+        )?;
+        let snippet = format!("{{ {text} }}");
+        // The offset of the binding within the snippet, on its first line.
+        const SNIPPET_PREFIX_LEN: usize = 2;
+        let parse = rnix::Root::parse(&snippet);
+        if !parse.errors().is_empty() {
+            return None;
+        }
+        let Some(ast::Expr::AttrSet(set)) = parse.tree().expr() else {
+            return None;
+        };
+        let mut entries = set.entries();
+        if !matches!(
+            (entries.next(), entries.next()),
+            (Some(ast::Entry::AttrpathValue(_)), None)
+        ) {
+            return None;
+        }
+        // Bindings of a `let` block are beneath the path of the expression they're within.
+        if !path.starts_with(parent) {
+            return None;
+        }
+        let prefix = match path.segs().get(parent.segs().len()) {
+            Some(AstSeg::Let) => parent.new_child(AstSeg::Let),
+            _ => parent.clone(),
+        };
+        let rebase = |rel: AstPath| -> AstPath {
+            rel.segs()
+                .iter()
+                .cloned()
+                .fold(prefix.clone(), |path, seg| path.new_child(seg))
+        };
+        let binding_start = new_range.start();
+        let rebase_pos = |pos: Pos| -> Pos {
+            if pos.line == 0 {
+                Pos {
+                    line: binding_start.line,
+                    char: binding_start.char + pos.char - SNIPPET_PREFIX_LEN,
+                }
+            } else {
+                Pos {
+                    line: binding_start.line + pos.line,
+                    char: pos.char,
+                }
+            }
+        };
+
+        // Drop the binding and everything within it, moving everything after it.
+        let within = |r: &PosRange| range.start() <= r.start() && r.end() <= range.end();
+        let mut data = self.data.clone();
+        let mut lambdas = self.lambdas.clone();
+        let mut options = self.options.clone();
+        // The doc of the binding is from comments before it, outside of the edit.
+        let doc = data.remove(path);
+        let mut ranges = BTreeMap::new();
+        for (p, r) in self.ranges.iter() {
+            if within(r) {
+                data.remove(p);
+                lambdas.remove(p);
+                options.remove(p);
+            } else {
+                ranges.insert(p.clone(), edit.shift_range(r));
+            }
+        }
+
+        let consumed = consume_ast(&LineIndex::new(&snippet), parse.syntax().into());
+        // The attrset wrapping the binding is recorded as the root, which is dropped.
+        let bindings: Vec<_> = consumed
+            .bindings
+            .into_iter()
+            .filter(|(_, rel)| !rel.segs().is_empty())
+            .collect();
+        let top = bindings
+            .iter()
+            .map(|(_, rel)| rel)
+            .min_by_key(|rel| rel.segs().len())
+            .cloned()?;
+        if let Some(doc) = doc {
+            data.insert(rebase(top), doc);
+        }
+        data.extend(
+            consumed
+                .docs
+                .into_iter()
+                .filter(|(rel, _)| !rel.segs().is_empty())
+                .map(|(rel, doc)| (rebase(rel), doc)),
+        );
+        lambdas.extend(
+            consumed
+                .lambdas
+                .into_iter()
+                .map(|(rel, lambda)| (rebase(rel), lambda)),
+        );
+        options.extend(
+            consumed
+                .options
+                .into_iter()
+                .map(|(rel, option)| (rebase(rel), option)),
+        );
+        let mut pos_index = self.pos_index.clone();
+        pos_index.remove(*range);
+        let (end, new_end) = (edit.range.end(), edit.new_end());
+        pos_index.shift_after(
+            end,
+            new_end.line as isize - end.line as isize,
+            new_end.char as isize - end.char as isize,
+        );
+        let mut bindings: Vec<(PosRange, AstPath)> = bindings
+            .into_iter()
+            .map(|(r, rel)| {
+                let (start, end) = (rebase_pos(r.start()), rebase_pos(r.end()));
+                (
+                    (start.line, start.char, end.line, end.char).into(),
+                    rebase(rel),
+                )
+            })
+            .collect();
+        // Insert outer ranges before the ranges they contain, so that none are re-parented.
+        bindings.sort_by_key(|(range, _)| (range.start(), Reverse(range.end())));
+        for (range, path) in bindings {
+            if let Err(err) = pos_index.insert(range, path.clone()) {
+                tracing::error!("failed to index the position of {path}: {err}");
+            }
+            ranges.insert(path, range);
+        }
+        Some(FileIndex {
+            pos_index,
+            data,
+            lambdas,
+            options,
+            ranges,
+        })
+    }
+}
@@ -0,0 +1,68 @@
+use compact_str::CompactString;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The parameters of a (possibly curried) lambda, as written in the source.
+///
+/// `{ a, b ? 1 }: x: body` is recorded as a single `Lambda` with two params, the pattern and then
+/// `x`, since that is how callers apply it.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Lambda {
+    pub params: Vec<Param>,
+}
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Param {
+    /// A plain positional param, `x: ...`.
+    Ident(CompactString),
+    /// An attrset pattern, `{ a, b ? 1, ... }@args: ...`.
+    Pattern {
+        formals: Vec<Formal>,
+        ellipsis: bool,
+        bind: Option<CompactString>,
+    },
+}
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Formal {
+    pub name: CompactString,
+    /// The source text of the default expression, if any.
+    pub default: Option<String>,
+}
+impl fmt::Display for Formal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.default {
+            Some(default) => write!(f, "{} ? {default}", self.name),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ident(ident) => write!(f, "{ident}"),
+            Self::Pattern {
+                formals,
+                ellipsis,
+                bind,
+            } => {
+                write!(f, "{{ ")?;
+                for (i, formal) in formals.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{formal}")?;
+                }
+                if *ellipsis {
+                    if !formals.is_empty() {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "...")?;
+                }
+                write!(f, " }}")?;
+                if let Some(bind) = bind {
+                    write!(f, "@{bind}")?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
@@ -0,0 +1,281 @@
+use super::{AstPath, AstSeg, FileKey};
+use rnix::ast::{self, HasEntry};
+use rowan::ast::AstNode;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A NixOS module option, declared with `mkOption` or one of its shorthands such as
+/// `mkEnableOption`.
+///
+/// Everything but the description is the source text of the expression, as options are not
+/// evaluated.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct NixOption {
+    pub description: Option<String>,
+    /// The type of the option, such as `types.bool`.
+    pub ty: Option<String>,
+    pub default: Option<String>,
+    pub example: Option<String>,
+}
+impl NixOption {
+    /// The option declared by the given application, if it's a call of `mkOption`,
+    /// `mkEnableOption` or `mkPackageOption`.
+    pub fn from_apply(apply: &ast::Apply) -> Option<Self> {
+        let (func, args) = flatten_apply(apply)?;
+        match (func_name(&func)?.as_str(), args.as_slice()) {
+            ("mkOption", [ast::Expr::AttrSet(attrs)]) => Some(Self::from_attrs(attrs)),
+            ("mkEnableOption", [name]) => Some(Self {
+                description: Some(format!("Whether to enable {}.", string(name))),
+                ty: Some("types.bool".into()),
+                default: Some("false".into()),
+                example: Some("true".into()),
+            }),
+            ("mkPackageOption", [_pkgs, name, rest @ ..]) => {
+                let attrs = match rest {
+                    [] => None,
+                    [ast::Expr::AttrSet(attrs)] => Some(attrs),
+                    _ => return None,
+                };
+                Some(Self::package(name, attrs))
+            },
+            _ => None,
+        }
+    }
+    fn from_attrs(attrs: &ast::AttrSet) -> Self {
+        let mut option = Self::default();
+        let mut default_text = None;
+        for (name, value) in attrs_of(attrs) {
+            match name.as_str() {
+                "description" => option.description = Some(string(&unwrap_call(value, DOC_FNS))),
+                "type" => option.ty = Some(value.syntax().text().to_string()),
+                "default" => option.default = Some(literal(&value)),
+                "defaultText" => default_text = Some(literal(&value)),
+                "example" => option.example = Some(literal(&value)),
+                _ => {},
+            }
+        }
+        // `defaultText` documents defaults which can't be rendered from the source.
+        option.default = default_text.or(option.default);
+        option
+    }
+    /// The option of `mkPackageOption pkgs name { ... }`, where packages are given as attr paths
+    /// of `pkgs`.
+    fn package(name: &ast::Expr, attrs: Option<&ast::AttrSet>) -> Self {
+        let name = package_path(name).unwrap_or_else(|| name.syntax().text().to_string());
+        let mut option = Self {
+            description: Some(format!("The {name} package to use.")),
+            ty: Some("types.package".into()),
+            default: Some(format!("pkgs.{name}")),
+            example: None,
+        };
+        for (attr, value) in attrs.into_iter().flat_map(attrs_of) {
+            let package_or_literal = |value: &ast::Expr| {
+                package_path(value).map_or_else(|| literal(value), |path| format!("pkgs.{path}"))
+            };
+            match attr.as_str() {
+                "default" => option.default = Some(package_or_literal(&value)),
+                "example" => option.example = Some(package_or_literal(&value)),
+                "extraDescription" => {
+                    if let Some(description) = &mut option.description {
+                        description.push(' ');
+                        description.push_str(&string(&unwrap_call(value, DOC_FNS)));
+                    }
+                },
+                _ => {},
+            }
+        }
+        option
+    }
+}
+/// Every option declared across an index, keyed by the path of the option such as
+/// `services.foo.enable` rather than by the bindings declaring them.
+#[derive(Debug, Default, Clone)]
+pub struct OptionTree {
+    options: BTreeMap<AstPath, DeclaredOption>,
+    /// Whether the NixOS modules of nixpkgs are indexed, see [`Self::is_complete`].
+    pub(super) complete: bool,
+}
+/// An option along with the binding declaring it.
+#[derive(Debug, Clone)]
+pub struct DeclaredOption {
+    pub file: FileKey,
+    /// The path of the binding, such as `options.services.foo.enable`.
+    pub binding: AstPath,
+    pub option: NixOption,
+}
+impl OptionTree {
+    /// Insert an option at the given option path. Only the first declaration of a path is kept.
+    pub fn insert(&mut self, path: AstPath, declared: DeclaredOption) {
+        self.options.entry(path).or_insert(declared);
+    }
+    /// Whether every option is declared by the tree. Otherwise options declared by modules which
+    /// aren't indexed are missing, and so can't be told apart from typos.
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+    pub fn get(&self, path: &AstPath) -> Option<&DeclaredOption> {
+        self.options.get(path)
+    }
+    /// Every option, ordered by path.
+    pub fn iter(&self) -> impl Iterator<Item = (&AstPath, &DeclaredOption)> {
+        self.options.iter()
+    }
+    /// The attrs directly beneath the given path, such as `enable` of `services.foo`, along with
+    /// the option declared by each if it's not an attrset of options.
+    pub fn children<'t>(
+        &'t self,
+        path: &'t AstPath,
+    ) -> impl Iterator<Item = (&'t AstSeg, Option<&'t DeclaredOption>)> + 't {
+        let depth = path.segs().len();
+        let mut prev: Option<&AstSeg> = None;
+        self.options
+            .range(path.clone()..)
+            .take_while(move |(p, _)| p.starts_with(path))
+            .filter_map(move |(p, declared)| {
+                let seg = p.segs().get(depth)?;
+                if prev == Some(seg) {
+                    return None;
+                }
+                prev = Some(seg);
+                // Paths sort before those beneath them, so the first of each attr is the option
+                // declared by it, if any.
+                Some((seg, (p.segs().len() == depth + 1).then_some(declared)))
+            })
+    }
+}
+/// Functions wrapping option descriptions, to mark them as markdown.
+const DOC_FNS: &[&str] = &["mdDoc", "literalMD"];
+/// Functions wrapping defaults and examples, to render them as the given Nix source.
+const LITERAL_FNS: &[&str] = &["literalExpression", "literalExample"];
+
+/// The function and arguments of a curried application, in application order.
+fn flatten_apply(apply: &ast::Apply) -> Option<(ast::Expr, Vec<ast::Expr>)> {
+    let mut args = Vec::new();
+    let mut func = ast::Expr::Apply(apply.clone());
+    while let ast::Expr::Apply(apply) = func {
+        args.push(apply.argument()?);
+        func = apply.lambda()?;
+    }
+    args.reverse();
+    Some((func, args))
+}
+/// The name of a function, ignoring any attrset it's selected from such as `lib.mkOption`.
+fn func_name(func: &ast::Expr) -> Option<String> {
+    match func {
+        ast::Expr::Ident(ident) => Some(ident.syntax().text().to_string()),
+        ast::Expr::Select(select) => match select.attrpath()?.attrs().last()? {
+            ast::Attr::Ident(ident) => Some(ident.syntax().text().to_string()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+/// The argument of a call of one of the given functions, otherwise the expression itself.
+fn unwrap_call(expr: ast::Expr, names: &[&str]) -> ast::Expr {
+    if let ast::Expr::Apply(apply) = &expr {
+        if let Some((func, args)) = flatten_apply(apply) {
+            if let ([arg], Some(name)) = (args.as_slice(), func_name(&func)) {
+                if names.contains(&name.as_str()) {
+                    return arg.clone();
+                }
+            }
+        }
+    }
+    expr
+}
+/// The statically named attrs of an attrset.
+fn attrs_of(attrs: &ast::AttrSet) -> impl Iterator<Item = (String, ast::Expr)> {
+    attrs.attrpath_values().filter_map(|apv| {
+        let mut attrs = apv.attrpath()?.attrs();
+        let name = match (attrs.next()?, attrs.next()) {
+            (ast::Attr::Ident(ident), None) => ident.syntax().text().to_string(),
+            _ => return None,
+        };
+        Some((name, apv.value()?))
+    })
+}
+/// The contents of a string, with interpolations as written. Anything else is its source text.
+fn string(expr: &ast::Expr) -> String {
+    let ast::Expr::Str(s) = expr else {
+        return expr.syntax().text().to_string();
+    };
+    s.normalized_parts()
+        .into_iter()
+        .map(|part| match part {
+            ast::InterpolPart::Literal(lit) => lit,
+            ast::InterpolPart::Interpolation(interpol) => interpol.syntax().text().to_string(),
+        })
+        .collect()
+}
+/// The source of a default or example, unwrapping `literalExpression "..."`.
+fn literal(expr: &ast::Expr) -> String {
+    match unwrap_call(expr.clone(), LITERAL_FNS) {
+        unwrapped @ ast::Expr::Str(_) if unwrapped.syntax() != expr.syntax() => string(&unwrapped),
+        _ => expr.syntax().text().to_string(),
+    }
+}
+/// The attr path of a package, given as a string or list of strings such as `[ "a" "b" ]`.
+fn package_path(expr: &ast::Expr) -> Option<String> {
+    match expr {
+        ast::Expr::Str(_) => Some(string(expr)),
+        ast::Expr::List(list) => {
+            let parts: Option<Vec<_>> = list
+                .items()
+                .map(|item| matches!(item, ast::Expr::Str(_)).then(|| string(&item)))
+                .collect();
+            Some(parts?.join("."))
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn option(src: &str) -> Option<NixOption> {
+        let root = rnix::Root::parse(src).tree();
+        let ast::Expr::Apply(apply) = root.expr()? else {
+            return None;
+        };
+        NixOption::from_apply(&apply)
+    }
+
+    #[test]
+    fn mk_option() {
+        let src = r#"lib.mkOption {
+  type = types.listOf types.str;
+  default = [ "a" ];
+  example = literalExpression "[ \"b\" ]";
+  description = lib.mdDoc ''
+    The things.
+  '';
+}"#;
+        assert_eq!(
+            option(src),
+            Some(NixOption {
+                description: Some("The things.\n".into()),
+                ty: Some("types.listOf types.str".into()),
+                default: Some("[ \"a\" ]".into()),
+                example: Some("[ \"b\" ]".into()),
+            })
+        );
+    }
+    #[test]
+    fn shorthands() {
+        assert_eq!(
+            option(r#"mkEnableOption "the foo service""#).and_then(|o| o.description),
+            Some("Whether to enable the foo service.".into())
+        );
+        assert_eq!(
+            option(r#"mkPackageOption pkgs "nginx" { example = [ "nginxQuic" ]; }"#),
+            Some(NixOption {
+                description: Some("The nginx package to use.".into()),
+                ty: Some("types.package".into()),
+                default: Some("pkgs.nginx".into()),
+                example: Some("pkgs.nginxQuic".into()),
+            })
+        );
+        assert_eq!(option("mkMerge [ ]"), None);
+    }
+}
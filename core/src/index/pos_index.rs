@@ -1,14 +1,16 @@
-use std::{
-    cmp::Reverse,
-    collections::{btree_map, BTreeMap},
-};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use thiserror::Error;
 
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
 pub struct Pos {
     pub line: usize,
     pub char: usize,
 }
 impl Pos {
+    #[allow(dead_code)]
     const MAX: Pos = Pos {
         line: usize::MAX,
         char: usize::MAX,
@@ -25,11 +27,21 @@ impl From<(usize, usize)> for Pos {
         Self { line, char }
     }
 }
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
 pub struct PosRange {
     start_incl: Pos,
     end_excl: Pos,
 }
+impl PosRange {
+    pub fn start(&self) -> Pos {
+        self.start_incl
+    }
+    pub fn end(&self) -> Pos {
+        self.end_excl
+    }
+}
 impl From<(usize, usize, usize)> for PosRange {
     fn from((line, char_start_incl, char_end_excl): (usize, usize, usize)) -> Self {
         Self {
@@ -53,6 +65,11 @@ impl From<(usize, usize, usize, usize)> for PosRange {
         }
     }
 }
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum InsertError {
+    #[error("range {range:?} partially overlaps the existing range {existing:?}")]
+    PartialOverlap { range: PosRange, existing: PosRange },
+}
 /// The ending position of a `PosRange`.
 #[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord)]
 struct EndExclPos(pub Pos);
@@ -69,7 +86,7 @@ impl From<PosRange> for StartInclPos {
         Self(range.start_incl)
     }
 }
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq)]
 struct Entry<T> {
     pub start_incl: StartInclPos,
     pub value: T,
@@ -81,7 +98,7 @@ type EntryMap<T> = BTreeMap<EndExclPos, Entry<T>>;
 ///
 /// NIT: This is a pretty naive implementation and could/should be improved at some point. However
 /// it's simple and correct, so good for a first pass.
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct PosIndex<T>(EntryMap<T>);
 impl<T> PosIndex<T>
 where
@@ -90,29 +107,54 @@ where
     pub fn new() -> Self {
         Self(Default::default())
     }
-    pub fn insert(&mut self, range: impl Into<PosRange>, value: T) {
+    /// Insert a range, beneath the innermost existing range containing it. Existing ranges
+    /// within it are moved beneath it, while a range equal to an existing one is inserted beneath
+    /// that one.
+    ///
+    /// Ranges must nest, so a range partially overlapping an existing one is rejected.
+    pub fn insert(&mut self, range: impl Into<PosRange>, value: T) -> Result<(), InsertError> {
         let range = range.into();
-        Self::recur_insert_into_entry(&mut self.0, range, value);
+        Self::recur_insert_into_entry(&mut self.0, range, value)
     }
-    fn recur_insert_into_entry(entry_map: &mut EntryMap<T>, range: PosRange, value: T) {
+    fn recur_insert_into_entry(
+        entry_map: &mut EntryMap<T>,
+        range: PosRange,
+        value: T,
+    ) -> Result<(), InsertError> {
         // NIT: This works around missing upper/lower bound features in stdlib[1]. By storing with
         // the end, we can grab any point from a range and use next to grab the nearest bound.
         // See also `Self::get`
         //
         // [1]: https://doc.rust-lang.org/std/collections/struct.BTreeMap.html#method.lower_bound
         if let Some((_, entry)) = Self::get_entry_mut(entry_map, range) {
-            Self::recur_insert_into_entry(&mut entry.children, range, value);
-        } else {
-            let prev = entry_map.insert(
-                range.into(),
-                Entry {
-                    start_incl: range.into(),
-                    value,
-                    children: Default::default(),
-                },
-            );
-            debug_assert!(prev.is_none());
+            return Self::recur_insert_into_entry(&mut entry.children, range, value);
+        }
+        // Any sibling intersecting the range must be within it, to be wrapped by it.
+        let mut wrapped = Vec::new();
+        for (end_pos, entry) in entry_map
+            .range(EndExclPos(range.start_incl.next_char())..)
+            .take_while(|(_, entry)| entry.start_incl.0 < range.end_excl)
+        {
+            let existing = entry.range(end_pos);
+            if existing.start_incl < range.start_incl || range.end_excl < existing.end_excl {
+                return Err(InsertError::PartialOverlap { range, existing });
+            }
+            wrapped.push(end_pos.clone());
         }
+        let children = wrapped
+            .into_iter()
+            .filter_map(|end_pos| entry_map.remove_entry(&end_pos))
+            .collect();
+        let prev = entry_map.insert(
+            range.into(),
+            Entry {
+                start_incl: range.into(),
+                value,
+                children,
+            },
+        );
+        debug_assert!(prev.is_none());
+        Ok(())
     }
     fn get_entry_mut(
         entry_map: &mut EntryMap<T>,
@@ -120,55 +162,174 @@ where
     ) -> Option<(&EndExclPos, &mut Entry<T>)> {
         entry_map
             .range_mut(EndExclPos::from(range)..)
-            .filter(|(_, entry)| entry.start_incl <= range.into())
-            .next()
+            .find(|(_, entry)| entry.start_incl <= range.into())
     }
     // NIT: This uses Pos, [`Self::get_entry_mut`] uses PosRange. Could prob just make them
     // `P: Into<StartInclPos> + P: Into<EndExclPos>`.
     fn recur_get_entry(entry_map: &EntryMap<T>, pos: Pos) -> Option<(&EndExclPos, &Entry<T>)> {
         let (end_pos, entry) = entry_map
             .range(EndExclPos(pos.next_char())..)
-            .filter(|(_, entry)| entry.start_incl <= StartInclPos(pos))
-            .next()?;
+            .find(|(_, entry)| entry.start_incl <= StartInclPos(pos))?;
         // Check the children for a match. If one is found, we choose the inner layer (them) over
         // the outer layer (this fn).
         if let Some(child_match) = Self::recur_get_entry(&entry.children, pos) {
-            return Some(child_match);
+            Some(child_match)
         } else {
-            return Some((end_pos, entry));
+            Some((end_pos, entry))
         }
     }
     pub fn get(&self, &pos: &Pos) -> Option<&T> {
         Self::recur_get_entry(&self.0, pos).map(|(_, entry)| &entry.value)
     }
+    /// Remove the entry of exactly the given range, along with every entry within it, returning
+    /// its value.
+    pub fn remove(&mut self, range: impl Into<PosRange>) -> Option<T> {
+        Self::recur_remove(&mut self.0, range.into())
+    }
+    fn recur_remove(entry_map: &mut EntryMap<T>, range: PosRange) -> Option<T> {
+        let (end_pos, entry) = Self::get_entry_mut(entry_map, range)?;
+        if entry.start_incl == range.into() && *end_pos == range.into() {
+            return entry_map
+                .remove(&EndExclPos::from(range))
+                .map(|entry| entry.value);
+        }
+        Self::recur_remove(&mut entry.children, range)
+    }
+    /// Move every position at or after `pos`, such as to follow an edit of the source ending at
+    /// `pos`. Positions on the line of `pos` move by both deltas, those on later lines only move
+    /// by `line_delta`.
+    ///
+    /// Positions before `pos` are unchanged, so entries within the replaced text of an edit must
+    /// be removed first. See [`Self::remove`].
+    pub fn shift_after(&mut self, pos: Pos, line_delta: isize, char_delta: isize) {
+        let shift = |p: Pos| -> Pos {
+            if p < pos {
+                p
+            } else if p.line == pos.line {
+                Pos {
+                    line: p.line.saturating_add_signed(line_delta),
+                    char: p.char.saturating_add_signed(char_delta),
+                }
+            } else {
+                Pos {
+                    line: p.line.saturating_add_signed(line_delta),
+                    char: p.char,
+                }
+            }
+        };
+        Self::recur_shift_after(&mut self.0, pos, &shift);
+    }
+    fn recur_shift_after(entry_map: &mut EntryMap<T>, pos: Pos, shift: &impl Fn(Pos) -> Pos) {
+        // Entries ending before `pos` are entirely before it, as are their children. Shifting
+        // preserves the order of the rest, so they can be re-keyed in order.
+        let after = entry_map.split_off(&EndExclPos(pos));
+        entry_map.extend(after.into_iter().map(|(end_pos, mut entry)| {
+            entry.start_incl = StartInclPos(shift(entry.start_incl.0));
+            Self::recur_shift_after(&mut entry.children, pos, shift);
+            (EndExclPos(shift(end_pos.0)), entry)
+        }));
+    }
+    /// Every entry, in document order. Entries come before the entries within them.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            stack: vec![self.0.iter()],
+        }
+    }
+    /// Every entry intersecting the given range, in document order. An empty range intersects
+    /// the entries containing its position.
+    pub fn overlapping(&self, range: impl Into<PosRange>) -> impl Iterator<Item = (PosRange, &T)> {
+        let range = range.into();
+        let end = range.end_excl.max(range.start_incl.next_char());
+        let mut found = Vec::new();
+        Self::recur_overlapping(&self.0, range.start_incl, end, &mut found);
+        found.into_iter()
+    }
+    fn recur_overlapping<'a>(
+        entry_map: &'a EntryMap<T>,
+        start: Pos,
+        end: Pos,
+        found: &mut Vec<(PosRange, &'a T)>,
+    ) {
+        for (end_pos, entry) in entry_map
+            .range(EndExclPos(start.next_char())..)
+            .take_while(|(_, entry)| entry.start_incl.0 < end)
+        {
+            found.push((entry.range(end_pos), &entry.value));
+            Self::recur_overlapping(&entry.children, start, end, found);
+        }
+    }
+    /// Every entry containing the given position, from the innermost outwards.
+    pub fn ancestors(&self, &pos: &Pos) -> impl Iterator<Item = (PosRange, &T)> {
+        let mut found = Vec::new();
+        let mut entry_map = &self.0;
+        while let Some((end_pos, entry)) = entry_map
+            .range(EndExclPos(pos.next_char())..)
+            .find(|(_, entry)| entry.start_incl <= StartInclPos(pos))
+        {
+            found.push((entry.range(end_pos), &entry.value));
+            entry_map = &entry.children;
+        }
+        found.into_iter().rev()
+    }
+}
+impl<T> Entry<T> {
+    fn range(&self, end_pos: &EndExclPos) -> PosRange {
+        PosRange {
+            start_incl: self.start_incl.0,
+            end_excl: end_pos.0,
+        }
+    }
+}
+/// An iterator over the entries of a [`PosIndex`], in document order. See [`PosIndex::iter`].
+#[derive(Debug)]
+pub struct Iter<'a, T> {
+    /// The remaining entries of each level, from the outermost inwards.
+    stack: Vec<std::collections::btree_map::Iter<'a, EndExclPos, Entry<T>>>,
+}
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = (PosRange, &'a T);
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let level = self.stack.last_mut()?;
+            match level.next() {
+                Some((end_pos, entry)) => {
+                    self.stack.push(entry.children.iter());
+                    return Some((entry.range(end_pos), &entry.value));
+                },
+                None => {
+                    self.stack.pop();
+                },
+            }
+        }
+    }
 }
 #[test]
 fn get_non_overlapping_single_line() {
     let mut pi = PosIndex::new();
-    pi.insert((0, 0, 5), "a");
+    pi.insert((0, 0, 5), "a").unwrap();
     assert_eq!(pi.get(&(0, 0).into()), Some(&"a"));
     assert_eq!(pi.get(&(0, 4).into()), Some(&"a"));
     assert_eq!(pi.get(&(0, 5).into()), None);
-    pi.insert((0, 5, 10), "b");
+    pi.insert((0, 5, 10), "b").unwrap();
     assert_eq!(pi.get(&(0, 4).into()), Some(&"a"));
     assert_eq!(pi.get(&(0, 5).into()), Some(&"b"));
 }
 #[test]
 fn get_non_overlapping_multi_line() {
     let mut pi = PosIndex::new();
-    pi.insert((0, 0, 3, 5), "a");
+    pi.insert((0, 0, 3, 5), "a").unwrap();
     assert_eq!(pi.get(&(0, 0).into()), Some(&"a"));
     assert_eq!(pi.get(&(1, 10).into()), Some(&"a"));
     assert_eq!(pi.get(&(3, 5).into()), None);
-    pi.insert((3, 5, 3, 10), "b");
+    pi.insert((3, 5, 3, 10), "b").unwrap();
     assert_eq!(pi.get(&(3, 4).into()), Some(&"a"));
     assert_eq!(pi.get(&(3, 5).into()), Some(&"b"));
 }
 #[test]
 fn get_overlapping_single_line() {
     let mut pi = PosIndex::new();
-    pi.insert((0, 0, 9), "a");
-    pi.insert((0, 3, 5), "b");
+    pi.insert((0, 0, 9), "a").unwrap();
+    pi.insert((0, 3, 5), "b").unwrap();
     assert_eq!(pi.get(&(0, 2).into()), Some(&"a"));
     assert_eq!(pi.get(&(0, 5).into()), Some(&"a"));
     assert_eq!(pi.get(&(0, 3).into()), Some(&"b"));
@@ -177,11 +338,11 @@ fn get_overlapping_single_line() {
 #[test]
 fn get_multi_line_overlapping_single_line() {
     let mut pi = PosIndex::new();
-    pi.insert((0, 0, 3, 5), "a");
+    pi.insert((0, 0, 3, 5), "a").unwrap();
     assert_eq!(pi.get(&(0, 0).into()), Some(&"a"));
     assert_eq!(pi.get(&(1, 9).into()), Some(&"a"));
     assert_eq!(pi.get(&(3, 5).into()), None);
-    pi.insert((1, 5, 1, 10), "b");
+    pi.insert((1, 5, 1, 10), "b").unwrap();
     assert_eq!(pi.get(&(0, 5).into()), Some(&"a"));
     assert_eq!(pi.get(&(1, 4).into()), Some(&"a"));
     assert_eq!(pi.get(&(1, 5).into()), Some(&"b"));
@@ -189,3 +350,223 @@ fn get_multi_line_overlapping_single_line() {
     assert_eq!(pi.get(&(1, 10).into()), Some(&"a"));
     assert_eq!(pi.get(&(3, 4).into()), Some(&"a"));
 }
+#[test]
+fn remove_with_children() {
+    let mut pi = PosIndex::new();
+    pi.insert((0, 0, 3, 0), "a").unwrap();
+    pi.insert((1, 0, 2, 0), "b").unwrap();
+    pi.insert((1, 2, 1, 4), "c").unwrap();
+    pi.insert((2, 0, 2, 5), "d").unwrap();
+    assert_eq!(pi.remove((1, 0, 1, 4)), None);
+    assert_eq!(pi.remove((1, 0, 2, 0)), Some("b"));
+    assert_eq!(pi.get(&(1, 2).into()), Some(&"a"));
+    assert_eq!(pi.get(&(2, 1).into()), Some(&"d"));
+    assert_eq!(pi.remove((0, 0, 3, 0)), Some("a"));
+    assert_eq!(pi.iter().count(), 0);
+}
+#[test]
+fn shift_after_edit() {
+    let mut pi = PosIndex::new();
+    pi.insert((0, 0, 3, 5), "a").unwrap();
+    pi.insert((0, 2, 0, 4), "b").unwrap();
+    pi.insert((1, 2, 1, 6), "c").unwrap();
+    pi.insert((1, 8, 2, 3), "d").unwrap();
+    // Replacing `(1, 6)..(1, 8)` with two lines, the last of which is 1 char.
+    pi.shift_after((1, 8).into(), 2, -7);
+    let entries: Vec<_> = pi.iter().collect();
+    assert_eq!(
+        entries,
+        vec![
+            ((0, 0, 5, 5).into(), &"a"),
+            ((0, 2, 0, 4).into(), &"b"),
+            ((1, 2, 1, 6).into(), &"c"),
+            ((3, 1, 4, 3).into(), &"d"),
+        ]
+    );
+    assert_eq!(pi.get(&(3, 1).into()), Some(&"d"));
+    assert_eq!(pi.get(&(2, 0).into()), Some(&"a"));
+}
+#[test]
+fn overlapping_in_document_order() {
+    let mut pi = PosIndex::new();
+    pi.insert((0, 0, 9), "a").unwrap();
+    pi.insert((0, 1, 3), "b").unwrap();
+    pi.insert((0, 4, 6), "c").unwrap();
+    pi.insert((0, 7, 8), "d").unwrap();
+    pi.insert((0, 9, 12), "e").unwrap();
+    let values = |range: (usize, usize, usize)| -> Vec<_> {
+        pi.overlapping(range).map(|(_, value)| *value).collect()
+    };
+    assert_eq!(values((0, 2, 5)), vec!["a", "b", "c"]);
+    assert_eq!(values((0, 3, 4)), vec!["a"]);
+    assert_eq!(values((0, 8, 10)), vec!["a", "e"]);
+    // An empty range, at a position.
+    assert_eq!(values((0, 4, 4)), vec!["a", "c"]);
+    assert_eq!(values((0, 12, 12)), Vec::<&str>::new());
+}
+#[test]
+fn ancestors_innermost_first() {
+    let mut pi = PosIndex::new();
+    pi.insert((0, 0, 3, 0), "a").unwrap();
+    pi.insert((1, 0, 2, 0), "b").unwrap();
+    pi.insert((1, 2, 1, 4), "c").unwrap();
+    let values = |pos: (usize, usize)| -> Vec<_> {
+        pi.ancestors(&pos.into()).map(|(_, value)| *value).collect()
+    };
+    assert_eq!(values((1, 3)), vec!["c", "b", "a"]);
+    assert_eq!(values((1, 5)), vec!["b", "a"]);
+    assert_eq!(values((2, 0)), vec!["a"]);
+    assert_eq!(values((3, 0)), Vec::<&str>::new());
+}
+#[test]
+fn insert_wraps_siblings() {
+    let mut pi = PosIndex::new();
+    pi.insert((0, 2, 4), "b").unwrap();
+    pi.insert((0, 5, 7), "c").unwrap();
+    pi.insert((0, 8, 9), "d").unwrap();
+    pi.insert((0, 0, 8), "a").unwrap();
+    let entries: Vec<_> = pi.iter().map(|(_, value)| *value).collect();
+    assert_eq!(entries, vec!["a", "b", "c", "d"]);
+    assert_eq!(pi.get(&(0, 4).into()), Some(&"a"));
+    assert_eq!(pi.get(&(0, 5).into()), Some(&"c"));
+    let ancestors: Vec<_> = pi.ancestors(&(0, 6).into()).map(|(_, v)| *v).collect();
+    assert_eq!(ancestors, vec!["c", "a"]);
+}
+#[test]
+fn insert_partial_overlap() {
+    let mut pi = PosIndex::new();
+    pi.insert((0, 0, 5), "a").unwrap();
+    pi.insert((0, 6, 9), "b").unwrap();
+    assert_eq!(
+        pi.insert((0, 3, 7), "c"),
+        Err(InsertError::PartialOverlap {
+            range: (0, 3, 7).into(),
+            existing: (0, 0, 5).into(),
+        })
+    );
+    assert_eq!(
+        pi.insert((0, 7, 12), "c"),
+        Err(InsertError::PartialOverlap {
+            range: (0, 7, 12).into(),
+            existing: (0, 6, 9).into(),
+        })
+    );
+    // Rejected inserts leave the index as it was.
+    let entries: Vec<_> = pi.iter().map(|(_, value)| *value).collect();
+    assert_eq!(entries, vec!["a", "b"]);
+}
+/// Property tests of [`PosIndex`] against a brute force oracle, a list of every range inserted.
+#[cfg(test)]
+mod oracle {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[derive(Debug, Clone)]
+    enum Op {
+        Insert(PosRange),
+        Remove(PosRange),
+    }
+    /// Non-empty ranges over a few short lines, such that they often overlap.
+    fn range() -> impl Strategy<Value = PosRange> {
+        ((0..3usize, 0..6usize), (0..3usize, 0..6usize))
+            .prop_filter("empty range", |(a, b)| a != b)
+            .prop_map(|(a, b)| {
+                let (start, end) = if a < b { (a, b) } else { (b, a) };
+                (start.0, start.1, end.0, end.1).into()
+            })
+    }
+    fn op() -> impl Strategy<Value = Op> {
+        prop_oneof![4 => range().prop_map(Op::Insert), 1 => range().prop_map(Op::Remove)]
+    }
+    fn contains(outer: &PosRange, inner: &PosRange) -> bool {
+        outer.start() <= inner.start() && inner.end() <= outer.end()
+    }
+    fn intersects(a: &PosRange, b: &PosRange) -> bool {
+        a.start() < b.end() && b.start() < a.end()
+    }
+    /// Document order, where an equal range inserted later is within the earlier one.
+    fn doc_order(&(range, id): &(PosRange, usize)) -> (Pos, std::cmp::Reverse<Pos>, usize) {
+        (range.start(), std::cmp::Reverse(range.end()), id)
+    }
+
+    proptest! {
+        #[test]
+        fn matches_oracle(ops in prop::collection::vec(op(), 1..24)) {
+            let mut pi = PosIndex::new();
+            // Every range in the index, along with the id of its insert as its value.
+            let mut oracle: Vec<(PosRange, usize)> = Vec::new();
+            for (id, op) in ops.into_iter().enumerate() {
+                match op {
+                    Op::Insert(range) => {
+                        let overlap = oracle.iter().find(|(existing, _)| {
+                            intersects(&range, existing)
+                                && !contains(&range, existing)
+                                && !contains(existing, &range)
+                        });
+                        let expected = match overlap {
+                            Some(&(existing, _)) => {
+                                Err(InsertError::PartialOverlap { range, existing })
+                            },
+                            None => Ok(()),
+                        };
+                        let result = pi.insert(range, id);
+                        // Any partially overlapping range may be reported.
+                        prop_assert_eq!(result.is_ok(), expected.is_ok());
+                        if result.is_ok() {
+                            oracle.push((range, id));
+                        }
+                    },
+                    Op::Remove(range) => {
+                        // The outermost of any equal ranges is removed, along with every range
+                        // within it.
+                        let expected = oracle
+                            .iter()
+                            .filter(|(existing, _)| *existing == range)
+                            .map(|&(_, id)| id)
+                            .min();
+                        prop_assert_eq!(pi.remove(range), expected);
+                        if expected.is_some() {
+                            oracle.retain(|(existing, _)| !contains(&range, existing));
+                        }
+                    },
+                }
+                oracle.sort_by_key(doc_order);
+                let entries: Vec<_> = pi.iter().map(|(range, &id)| (range, id)).collect();
+                prop_assert_eq!(&entries, &oracle);
+            }
+            for line in 0..4 {
+                for char in 0..7 {
+                    let pos = Pos { line, char };
+                    let point = PosRange { start_incl: pos, end_excl: pos.next_char() };
+                    let mut expected: Vec<_> = oracle
+                        .iter()
+                        .filter(|(range, _)| contains(range, &point))
+                        .cloned()
+                        .collect();
+                    expected.reverse();
+                    let ancestors: Vec<_> =
+                        pi.ancestors(&pos).map(|(range, &id)| (range, id)).collect();
+                    prop_assert_eq!(&ancestors, &expected);
+                    prop_assert_eq!(pi.get(&pos).copied(), expected.first().map(|&(_, id)| id));
+                }
+            }
+        }
+        #[test]
+        fn overlapping_matches_oracle(
+            ranges in prop::collection::vec(range(), 1..16),
+            query in range(),
+        ) {
+            let mut pi = PosIndex::new();
+            let mut oracle = Vec::new();
+            for (id, range) in ranges.into_iter().enumerate() {
+                if pi.insert(range, id).is_ok() {
+                    oracle.push((range, id));
+                }
+            }
+            oracle.sort_by_key(doc_order);
+            oracle.retain(|(range, _)| intersects(range, &query));
+            let found: Vec<_> = pi.overlapping(query).map(|(range, &id)| (range, id)).collect();
+            prop_assert_eq!(found, oracle);
+        }
+    }
+}
@@ -21,3 +21,335 @@ pub mod ast {
         );
     }
 }
+pub mod binding {
+    use super::*;
+
+    #[test]
+    fn doc_at_pos() {
+        let mut ti = TancIndex::default();
+        ti.insert(
+            "foo.nix",
+            r#"{
+  # The foo.
+  foo = {
+    /* The bar. */
+    bar = 1;
+    baz = 2; # Not a doc.
+    qux = 3;
+  };
+}"#,
+        );
+        let doc = |line, char| ti.doc("foo.nix", line, char).and_then(|d| d.doc.as_deref());
+        assert_eq!(doc(2, 2), Some("The foo."));
+        assert_eq!(doc(4, 4), Some("The bar."));
+        assert_eq!(doc(5, 4), None);
+        assert_eq!(doc(6, 4), None);
+        assert_eq!(doc(7, 2), Some("The foo."));
+    }
+    #[test]
+    fn block_doc_dedent() {
+        let fi = FileIndex::new(
+            r#"{
+  /**
+    Summary.
+
+    # Example
+      foo
+  */
+  foo = 1;
+}"#,
+        );
+        assert_eq!(
+            fi.path_doc(&AstPath::default().new_child("foo")),
+            Some(&Doc {
+                doc: Some("Summary.\n\n# Example\n  foo".into())
+            })
+        );
+    }
+}
+pub mod lambda {
+    use super::*;
+
+    #[test]
+    fn curried_lambda_params() {
+        let fi = FileIndex::new("{ lib }: { f = { a, b ? 1, ... }@args: x: a; }");
+        assert_eq!(
+            fi.lambda(&AstPath::default()),
+            Some(&Lambda {
+                params: vec![Param::Pattern {
+                    formals: vec![Formal {
+                        name: "lib".into(),
+                        default: None,
+                    }],
+                    ellipsis: false,
+                    bind: None,
+                }]
+            })
+        );
+        assert_eq!(
+            fi.lambda(&AstPath::default().new_child("f")),
+            Some(&Lambda {
+                params: vec![
+                    Param::Pattern {
+                        formals: vec![
+                            Formal {
+                                name: "a".into(),
+                                default: None,
+                            },
+                            Formal {
+                                name: "b".into(),
+                                default: Some("1".into()),
+                            },
+                        ],
+                        ellipsis: true,
+                        bind: Some("args".into()),
+                    },
+                    Param::Ident("x".into()),
+                ]
+            })
+        );
+    }
+}
+pub mod concurrent {
+    use super::*;
+
+    #[test]
+    fn par_insert_merges() {
+        let mut ti = TancIndex::default();
+        ti.insert("0.nix", "{ stale = 1; }");
+        let files: Vec<_> = (0..64).map(|i| format!("{i}.nix")).collect();
+        let indexed = std::sync::atomic::AtomicUsize::new(0);
+        ti.par_insert(
+            None,
+            &files,
+            |file_path| {
+                let src = format!("{{ f{} = x: x; }}", file_path.trim_end_matches(".nix"));
+                // Unreadable files are skipped, but still count towards progress.
+                (file_path != "63.nix").then(|| (file_path.into(), src))
+            },
+            || {
+                indexed.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            },
+        );
+        assert_eq!(indexed.into_inner(), 64);
+        assert_eq!(ti.files().count(), 63);
+        let first = ti.file("0.nix").unwrap();
+        let paths: Vec<_> = first.bindings().map(|(p, _)| p.to_string()).collect();
+        assert_eq!(paths, vec!["", "f0"]);
+        assert!(ti.resolve_lambda("any.nix", &["f42"]).is_some());
+    }
+}
+pub mod incremental {
+    use super::*;
+
+    const SRC: &str = r#"let
+  # The version.
+  version = "1.0";
+in {
+  # Add one.
+  inc = x: x + 1;
+  set = {
+    # The a.
+    a = { y }: y;
+    b = 2;
+  };
+  after = "after";
+}"#;
+
+    /// Apply the edit of `range` to `SRC`, asserting the incremental index matches a full index
+    /// of the edited source.
+    fn assert_edit(range: (usize, usize, usize, usize), text: &str) {
+        let edit = Edit {
+            range: range.into(),
+            text: text.into(),
+        };
+        let mut src = SRC.to_string();
+        edit.apply(&mut src);
+        assert_eq!(
+            FileIndex::new(SRC).edit(&src, &edit),
+            FileIndex::new(&src),
+            "edited source:\n{src}"
+        );
+    }
+    #[test]
+    fn matches_full_index() {
+        // Changing a value.
+        assert_edit((8, 8, 8, 13), "{ y, z }");
+        // Renaming a binding, keeping its doc.
+        assert_edit((8, 4, 8, 5), "renamed");
+        // Adding lines, moving every binding after them.
+        assert_edit((9, 8, 9, 9), "{\n      c = 3;\n    }");
+        // Removing lines.
+        assert_edit((7, 4, 9, 4), "");
+        // Adding a sibling, which re-indexes the enclosing set.
+        assert_edit((8, 17, 8, 17), "\n    c = z: z;");
+        // A binding of a `let` block.
+        assert_edit((2, 13, 2, 16), "2.0");
+        // Breaking the syntax, which re-indexes the entire file.
+        assert_edit((6, 8, 6, 9), "");
+        // Outside of any binding.
+        assert_edit((3, 3, 3, 3), " # in\n");
+    }
+    #[test]
+    fn reindexes_only_enclosing_binding() {
+        let mut top = String::from("{\n");
+        for i in 0..10_000 {
+            top.push_str(&format!("  f{i} = x: x;\n"));
+        }
+        let src = format!("{top}  last = {{ a = 1; }};\n}}");
+        // An index of a source with a different top proves that it isn't re-parsed, though its
+        // entries are still copied.
+        let fi = FileIndex::new(&src.replacen("f0 = x: x;", "g0 = x: x;", 1));
+        let edit = Edit {
+            range: (10_001, 15, 10_001, 16).into(),
+            text: "{ b = 2; }".into(),
+        };
+        let mut edited = src;
+        edit.apply(&mut edited);
+        let fi = fi.edit(&edited, &edit);
+        let path = |s: &str| {
+            s.split('.')
+                .fold(AstPath::default(), |p, seg| p.new_child(seg))
+        };
+        assert!(fi.range(&path("g0")).is_some());
+        assert!(fi.range(&path("f0")).is_none());
+        assert_eq!(fi.path(10_001, 19), Some(&path("last.a.b")));
+        let root = AstPath::default();
+        assert_eq!(fi.range(&root), FileIndex::new(&edited).range(&root));
+    }
+}
+pub mod line_endings {
+    use super::*;
+
+    const SRC: &str = "{
+  /* A block
+     comment. */
+  block = 1;
+  string = ''
+    multi
+    line
+  '';
+  # A line comment.
+  after = x: x;
+}";
+
+    #[test]
+    fn crlf_and_cr_match_lf() {
+        let lf = FileIndex::new(SRC);
+        let path = AstPath::default().new_child("after");
+        // As reported by editors, the line after two multi-line tokens and columns without `\r`.
+        assert_eq!(lf.range(&path), Some(&(9, 2, 9, 15).into()));
+        assert_eq!(lf.path(9, 2), Some(&path));
+        assert_eq!(
+            lf.path_doc(&path).and_then(|doc| doc.doc.as_deref()),
+            Some("A line comment.")
+        );
+        assert_eq!(
+            lf.path_doc(&AstPath::default().new_child("block"))
+                .and_then(|doc| doc.doc.as_deref()),
+            Some("A block\ncomment.")
+        );
+        assert_eq!(FileIndex::new(&SRC.replace('\n', "\r\n")), lf);
+
+        // Line comments only end at `\n`, so a lone `\r` is within them.
+        let src = SRC.replace("  # A line comment.\n", "");
+        let lf = FileIndex::new(&src);
+        assert_eq!(FileIndex::new(&src.replace('\n', "\r")), lf);
+        // Mixed, alternating between each.
+        let lines: Vec<_> = src.split('\n').collect();
+        let mixed: String = lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| match (i % 3, i == lines.len() - 1) {
+                (_, true) => line.to_string(),
+                (0, _) => format!("{line}\n"),
+                (1, _) => format!("{line}\r\n"),
+                _ => format!("{line}\r"),
+            })
+            .collect();
+        assert_eq!(FileIndex::new(&mixed), lf);
+    }
+    #[test]
+    fn crlf_edit() {
+        let src = SRC.replace('\n', "\r\n");
+        let edit = Edit {
+            range: (6, 8, 6, 8).into(),
+            text: "\r\n    lines".into(),
+        };
+        let mut edited = src.clone();
+        edit.apply(&mut edited);
+        let fi = FileIndex::new(&src).edit(&edited, &edit);
+        assert_eq!(fi, FileIndex::new(&edited));
+        let path = AstPath::default().new_child("after");
+        assert_eq!(fi.range(&path), Some(&(10, 2, 10, 15).into()));
+    }
+}
+pub mod option {
+    use super::*;
+
+    const SRC: &str = r#"{ lib, pkgs, ... }: {
+  options.services.foo = {
+    enable = lib.mkEnableOption "foo";
+    package = lib.mkPackageOption pkgs "foo" { };
+  };
+  config = { };
+}"#;
+
+    #[test]
+    fn option_paths() {
+        let fi = FileIndex::new(SRC);
+        let paths: Vec<_> = fi
+            .options()
+            .filter_map(|(path, _)| Some(path.option_path()?.join(".")))
+            .collect();
+        assert_eq!(paths, vec!["services.foo.enable", "services.foo.package"]);
+
+        let mut index = TancIndex::default();
+        index.insert("foo.nix", SRC);
+        let tree = index.option_tree();
+        let services: AstPath = ["services"].into_iter().collect();
+        let package = tree.get(&services.new_child("foo").new_child("package"));
+        assert_eq!(
+            package.and_then(|declared| declared.option.default.as_deref()),
+            Some("pkgs.foo")
+        );
+        assert!(tree.get(&services.new_child("foo")).is_none());
+        let children: Vec<_> = tree
+            .children(&services)
+            .map(|(seg, declared)| (seg.to_string(), declared.is_some()))
+            .collect();
+        assert_eq!(children, vec![("foo".to_owned(), false)]);
+        let foo = services.new_child("foo");
+        let children: Vec<_> = tree
+            .children(&foo)
+            .map(|(seg, _)| seg.to_string())
+            .collect();
+        assert_eq!(children, vec!["enable", "package"]);
+        // The tree is built once per snapshot of the index.
+        assert!(Arc::ptr_eq(&tree, &index.option_tree()));
+        assert!(!tree.is_complete());
+        index.insert("bar.nix", "{ }");
+        assert!(!Arc::ptr_eq(&tree, &index.option_tree()));
+    }
+    #[test]
+    fn option_edit() {
+        let fi = FileIndex::new(SRC);
+        let edit = Edit {
+            range: (2, 36, 2, 36).into(),
+            text: " service".into(),
+        };
+        let mut src = SRC.to_owned();
+        edit.apply(&mut src);
+        let edited = fi.edit(&src, &edit);
+        assert_eq!(edited, FileIndex::new(&src));
+        let path = AstPath::default()
+            .new_child("options")
+            .new_child("services")
+            .new_child("foo")
+            .new_child("enable");
+        assert_eq!(
+            edited.option(&path).and_then(|o| o.description.as_deref()),
+            Some("Whether to enable foo service.")
+        );
+    }
+}
@@ -1,5 +1,13 @@
+pub mod builtins;
+pub mod cache;
+pub mod export;
 pub mod index;
+pub mod library;
+pub mod line_index;
 pub mod parser;
 pub mod path;
+pub mod project;
+pub mod scope;
+pub mod search_path;
 pub mod server;
 pub mod srcbuf;
@@ -0,0 +1,359 @@
+//! Directories indexed as read-only libraries, such as a local checkout of nixpkgs, and the
+//! resolution of references into them such as `lib.strings.concatStrings` or `pkgs.hello`.
+//!
+//! Files of a library are keyed by the commit of its git `HEAD`, if it's a git working tree, so
+//! that cached indexes of a checkout are reused until it changes.
+use crate::index::{AstPath, Doc, FileIndex, FileKey, Lambda, PosRange, TancIndex};
+use compact_str::CompactString;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// A directory indexed as a library.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Library {
+    /// The canonical path of the directory.
+    pub root: PathBuf,
+    /// The commit of the git `HEAD` of the directory, if any.
+    pub commit: Option<CompactString>,
+}
+impl Library {
+    pub fn open(root: impl AsRef<Path>) -> io::Result<Self> {
+        let root = root.as_ref().canonicalize()?;
+        let commit = git_head(&root);
+        Ok(Self { root, commit })
+    }
+}
+/// The commit checked out in the git working tree at `root`, read from `.git` directly so that
+/// neither `git` nor the network is needed. `None` if `root` is not the root of a working tree.
+pub fn git_head(root: &Path) -> Option<CompactString> {
+    let dot_git = root.join(".git");
+    // Linked worktrees and submodules have a `.git` file pointing at their git dir.
+    let git_dir = match fs::read_to_string(&dot_git) {
+        Ok(link) => root.join(link.strip_prefix("gitdir:")?.trim()),
+        Err(_) => dot_git,
+    };
+    // Refs are shared by every worktree, in the common dir.
+    let common_dir = match fs::read_to_string(git_dir.join("commondir")) {
+        Ok(common) => git_dir.join(common.trim()),
+        Err(_) => git_dir.clone(),
+    };
+    let head = fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    let Some(reference) = head.trim().strip_prefix("ref:").map(str::trim) else {
+        // A detached `HEAD` is the commit itself.
+        return is_commit(head.trim()).then(|| head.trim().into());
+    };
+    if let Ok(commit) = fs::read_to_string(common_dir.join(reference)) {
+        return is_commit(commit.trim()).then(|| commit.trim().into());
+    }
+    let packed = fs::read_to_string(common_dir.join("packed-refs")).ok()?;
+    packed.lines().find_map(|line| {
+        let (commit, name) = line.split_once(' ')?;
+        (name == reference && is_commit(commit)).then(|| commit.into())
+    })
+}
+fn is_commit(s: &str) -> bool {
+    matches!(s.len(), 40 | 64) && s.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// A binding which a reference resolved to.
+#[derive(Debug, Clone, Copy)]
+pub struct Definition<'a> {
+    pub file: &'a FileKey,
+    pub path: &'a AstPath,
+    pub fi: &'a FileIndex,
+}
+impl<'a> Definition<'a> {
+    pub fn range(&self) -> Option<&'a PosRange> {
+        self.fi.range(self.path)
+    }
+    pub fn doc(&self) -> Option<&'a Doc> {
+        self.fi.path_doc(self.path)
+    }
+    pub fn lambda(&self) -> Option<&'a Lambda> {
+        self.fi.lambda(self.path)
+    }
+}
+impl TancIndex {
+    /// Index the given sources as every Nix file of the library, replacing any previous index of
+    /// it as [`crate::server`] does once it has read them.
+    #[cfg(test)]
+    pub fn insert_library_files(&mut self, library: &Library, files: Vec<(PathBuf, String)>) {
+        let root = library.root.to_str().expect("library root is UTF-8");
+        let commit = library.commit.as_deref();
+        let mut library_index = self.fork();
+        for (path, src) in files {
+            library_index.insert_at(commit, path.to_str().expect("path is UTF-8"), &src);
+        }
+        self.replace_library(root, commit, library_index);
+    }
+    /// Resolve a reference into a library laid out like nixpkgs, such as `["lib", "strings",
+    /// "concatStrings"]`, `["pkgs", "hello"]` or `["builtins", "attrNames"]`.
+    ///
+    /// Builtins are resolved to the bindings of `lib` which re-export them, as they're not
+    /// defined by any Nix source.
+    pub fn resolve_library(&self, idents: &[&str]) -> Option<Definition<'_>> {
+        self.libraries().find_map(|(root, commit)| {
+            let library = LibraryFiles {
+                index: self,
+                root,
+                commit,
+            };
+            match idents {
+                ["lib", attrs @ ..] | ["pkgs", "lib", attrs @ ..] => library.lib(attrs),
+                ["builtins", name] => library.lib(&[name]),
+                ["pkgs", name, ..] => library.package(name),
+                _ => None,
+            }
+        })
+    }
+    /// Resolve a reference into the file or directory at the given path, such as `["hello"]`
+    /// of what `import <nixpkgs> { }` is bound to.
+    ///
+    /// A directory is imported by its `default.nix`, except for the root of a library and its
+    /// `lib` directory which are resolved like `pkgs` and `lib` respectively.
+    pub fn resolve_import(&self, file_path: &str, attrs: &[&str]) -> Option<Definition<'_>> {
+        let library = self.libraries().find_map(|(root, commit)| {
+            let rest = file_path.strip_prefix(root)?;
+            (rest.is_empty() || rest.starts_with('/')).then_some((root, commit, rest))
+        });
+        let commit = library.and_then(|(_, commit, _)| commit);
+        if let Some((root, commit, rest)) = library {
+            let library = LibraryFiles {
+                index: self,
+                root,
+                commit,
+            };
+            match (rest, attrs) {
+                ("", ["lib", attrs @ ..]) | ("/lib", attrs) => return library.lib(attrs),
+                ("", [name, ..]) => return library.package(name),
+                _ => {},
+            }
+        }
+        let file = |file_path: String| {
+            self.file_entry(&FileKey {
+                commit: commit.map(Into::into),
+                file_path: file_path.into(),
+            })
+        };
+        let (key, fi) =
+            file(file_path.to_owned()).or_else(|| file(format!("{file_path}/default.nix")))?;
+        LibraryFiles::binding(key, fi, attrs)
+    }
+}
+/// The files of a single library.
+struct LibraryFiles<'a> {
+    index: &'a TancIndex,
+    root: &'a str,
+    commit: Option<&'a str>,
+}
+impl<'a> LibraryFiles<'a> {
+    /// The file at the given path, relative to the root of the library.
+    fn file(&self, file_path: &str) -> Option<(&'a FileKey, &'a FileIndex)> {
+        let key = FileKey {
+            commit: self.commit.map(Into::into),
+            file_path: format!("{}/{file_path}", self.root).into(),
+        };
+        self.index.file_entry(&key)
+    }
+    /// The public binding of the given file with exactly the given idents.
+    fn binding(file: &'a FileKey, fi: &'a FileIndex, attrs: &[&str]) -> Option<Definition<'a>> {
+        let (path, _) = fi
+            .bindings()
+            .find(|(path, _)| path.is_public() && path.idents().eq(attrs.iter().copied()))?;
+        Some(Definition { file, path, fi })
+    }
+    /// A binding of `lib`, either of the file it's within such as `strings.concatStrings` or any
+    /// of the files of `lib` for those re-exported at the top such as `concatStrings`.
+    fn lib(&self, attrs: &[&str]) -> Option<Definition<'a>> {
+        if let Some((file, rest)) = attrs.split_first() {
+            if let Some((key, fi)) = self.file(&format!("lib/{file}.nix")) {
+                if let Some(definition) = Self::binding(key, fi, rest) {
+                    return Some(definition);
+                }
+            }
+        }
+        let dir = format!("{}/lib/", self.root);
+        self.index
+            .files_within(self.commit, &dir)
+            .filter_map(|(key, fi)| Self::binding(key, fi, attrs))
+            // Prefer documented definitions to the bindings re-exporting them, such as those
+            // of `lib/default.nix`.
+            .max_by_key(|definition| {
+                (
+                    definition.doc().is_some(),
+                    definition.lambda().is_some(),
+                    !definition.file.file_path.ends_with("/default.nix"),
+                )
+            })
+    }
+    /// A package of `pkgs`, either of `pkgs/by-name` or `pkgs/top-level/all-packages.nix`.
+    fn package(&self, name: &str) -> Option<Definition<'a>> {
+        let shard: String = name.chars().take(2).collect::<String>().to_lowercase();
+        if let Some((key, fi)) = self.file(&format!("pkgs/by-name/{shard}/{name}/package.nix")) {
+            if let Some(definition) = Self::binding(key, fi, &[]) {
+                return Some(definition);
+            }
+        }
+        let (key, fi) = self.file("pkgs/top-level/all-packages.nix")?;
+        Self::binding(key, fi, &[name])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+
+    /// A fresh temporary directory, removed when dropped.
+    struct TempDir(PathBuf);
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let root = env::temp_dir().join(format!("tanc-library-{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&root);
+            fs::create_dir_all(&root).unwrap();
+            Self(root)
+        }
+        fn write(&self, path: &str, contents: &str) {
+            let path = self.0.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+    }
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    const COMMIT: &str = "0123456789abcdef0123456789abcdef01234567";
+
+    #[test]
+    fn git_heads() {
+        let dir = TempDir::new("heads");
+        assert_eq!(git_head(&dir.0), None);
+        dir.write(".git/HEAD", "ref: refs/heads/main\n");
+        dir.write(
+            ".git/packed-refs",
+            &format!("# pack-refs\n{COMMIT} refs/heads/main\n"),
+        );
+        assert_eq!(git_head(&dir.0).as_deref(), Some(COMMIT));
+        let loose = COMMIT.replace('0', "f");
+        dir.write(".git/refs/heads/main", &format!("{loose}\n"));
+        assert_eq!(git_head(&dir.0), Some(loose.into()));
+        dir.write(".git/HEAD", &format!("{COMMIT}\n"));
+        assert_eq!(git_head(&dir.0).as_deref(), Some(COMMIT));
+
+        // A linked worktree, with refs of the common dir.
+        let worktree = TempDir::new("worktree");
+        let git_dir = dir.0.join(".git/worktrees/wt");
+        worktree.write(".git", &format!("gitdir: {}\n", git_dir.display()));
+        dir.write(".git/worktrees/wt/HEAD", "ref: refs/heads/main\n");
+        dir.write(".git/worktrees/wt/commondir", "../..\n");
+        assert_eq!(git_head(&worktree.0), Some(COMMIT.replace('0', "f").into()));
+    }
+    #[test]
+    fn resolve_nixpkgs() {
+        let library = Library {
+            root: "/nixpkgs".into(),
+            commit: Some(COMMIT.into()),
+        };
+        let file = |path: &str, src: &str| (PathBuf::from(format!("/nixpkgs/{path}")), src.into());
+        let mut index = TancIndex::default();
+        index.insert_library_files(
+            &library,
+            vec![
+                file(
+                    "lib/strings.nix",
+                    "{ lib }: rec {\n  # Concatenate strings.\n  concatStrings = list: list;\n}",
+                ),
+                file(
+                    "lib/attrsets.nix",
+                    "{ lib }: { inherit (builtins) attrNames; }",
+                ),
+                file(
+                    "lib/default.nix",
+                    "{ inherit (strings) concatStrings; strings = import ./strings.nix; }",
+                ),
+                file(
+                    "pkgs/by-name/he/hello/package.nix",
+                    "{ stdenv }: stdenv.mkDerivation { }",
+                ),
+                file(
+                    "pkgs/top-level/all-packages.nix",
+                    "{ lib }: self: with self; { foo = callPackage ./foo { }; }",
+                ),
+            ],
+        );
+        let resolve = |idents: &[&str]| {
+            let definition = index.resolve_library(idents)?;
+            assert_eq!(definition.file.commit.as_deref(), Some(COMMIT));
+            Some(format!(
+                "{}::{}",
+                definition.file.file_path, definition.path
+            ))
+        };
+        let concat_strings = Some("/nixpkgs/lib/strings.nix::concatStrings".into());
+        assert_eq!(
+            resolve(&["lib", "strings", "concatStrings"]),
+            concat_strings
+        );
+        assert_eq!(resolve(&["lib", "concatStrings"]), concat_strings);
+        assert_eq!(resolve(&["pkgs", "lib", "concatStrings"]), concat_strings);
+        assert_eq!(
+            resolve(&["lib", "strings"]),
+            Some("/nixpkgs/lib/strings.nix::".into())
+        );
+        assert_eq!(
+            resolve(&["builtins", "attrNames"]),
+            Some("/nixpkgs/lib/attrsets.nix::attrNames".into())
+        );
+        assert_eq!(
+            resolve(&["pkgs", "hello"]),
+            Some("/nixpkgs/pkgs/by-name/he/hello/package.nix::".into())
+        );
+        assert_eq!(
+            resolve(&["pkgs", "foo"]),
+            Some("/nixpkgs/pkgs/top-level/all-packages.nix::foo".into())
+        );
+        assert_eq!(resolve(&["pkgs", "missing"]), None);
+
+        // Through imports, such as of `import <nixpkgs> { }` or `import <nixpkgs/lib>`.
+        let import = |file_path: &str, attrs: &[&str]| {
+            let definition = index.resolve_import(file_path, attrs)?;
+            Some(format!(
+                "{}::{}",
+                definition.file.file_path, definition.path
+            ))
+        };
+        assert_eq!(
+            import("/nixpkgs", &["hello"]),
+            Some("/nixpkgs/pkgs/by-name/he/hello/package.nix::".into())
+        );
+        assert_eq!(
+            import("/nixpkgs", &["lib", "concatStrings"]),
+            concat_strings
+        );
+        assert_eq!(import("/nixpkgs/lib", &["concatStrings"]), concat_strings);
+        assert_eq!(
+            import("/nixpkgs/lib/strings.nix", &["concatStrings"]),
+            concat_strings
+        );
+        assert_eq!(
+            import("/nixpkgs/lib/default.nix", &["strings"]),
+            Some("/nixpkgs/lib/default.nix::strings".into())
+        );
+        assert_eq!(import("/nixpkgs/lib/missing.nix", &["x"]), None);
+
+        // Re-indexing at another commit replaces the files of the previous one.
+        let library = Library {
+            commit: None,
+            ..library
+        };
+        index.insert_library_files(&library, vec![file("lib/trivial.nix", "{ id = x: x; }")]);
+        assert_eq!(index.files().count(), 1);
+        assert!(index.resolve_library(&["lib", "concatStrings"]).is_none());
+        assert!(index.resolve_library(&["lib", "trivial", "id"]).is_some());
+    }
+}
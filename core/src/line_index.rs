@@ -0,0 +1,180 @@
+//! Conversion between byte offsets of a source and line and column positions, where columns may
+//! count UTF-8, UTF-16 or UTF-32 code units.
+//!
+//! The index works in bytes, such that [`Pos::char`] is a byte offset into the line, while LSP
+//! clients count UTF-16 code units unless another encoding is negotiated.
+//!
+//! Lines end with any of `\n`, `\r\n` or a lone `\r`, as per LSP. Every position of a file is
+//! derived from its [`LineIndex`], so that they agree on where lines are.
+use crate::index::Pos;
+
+/// What the column of a position counts.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// Bytes, as used by the index.
+    Utf8,
+    /// The default of LSP clients.
+    #[default]
+    Utf16,
+    /// Chars, ie Unicode code points.
+    Utf32,
+}
+impl Encoding {
+    /// The length of the given char, in code units of this encoding.
+    fn len(self, c: char) -> usize {
+        match self {
+            Self::Utf8 => c.len_utf8(),
+            Self::Utf16 => c.len_utf16(),
+            Self::Utf32 => 1,
+        }
+    }
+}
+/// The start of every line of a source.
+#[derive(Debug, Clone)]
+pub struct LineIndex<'a> {
+    src: &'a str,
+    /// The byte offset of the start of each line.
+    line_starts: Vec<usize>,
+}
+impl<'a> LineIndex<'a> {
+    pub fn new(src: &'a str) -> Self {
+        let bytes = src.as_bytes();
+        let line_starts = std::iter::once(0)
+            .chain(bytes.iter().enumerate().filter_map(|(i, &b)| {
+                let line_end = b == b'\n' || (b == b'\r' && bytes.get(i + 1) != Some(&b'\n'));
+                line_end.then_some(i + 1)
+            }))
+            .collect();
+        Self { src, line_starts }
+    }
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+    /// The text of the given line, without its line ending.
+    pub fn line(&self, line: usize) -> Option<&'a str> {
+        let start = *self.line_starts.get(line)?;
+        let Some(&next) = self.line_starts.get(line + 1) else {
+            return Some(&self.src[start..]);
+        };
+        let text = &self.src[start..next];
+        let text = text.strip_suffix('\n').unwrap_or(text);
+        Some(text.strip_suffix('\r').unwrap_or(text))
+    }
+    /// The text of every line, without line endings.
+    pub fn lines(&self) -> impl Iterator<Item = &'a str> + '_ {
+        (0..self.line_count()).filter_map(|line| self.line(line))
+    }
+    /// The position of the given byte offset, with the column in the given encoding. Offsets past
+    /// the end of the source are the end of it, and offsets within a char are the start of it.
+    pub fn position(&self, offset: usize, encoding: Encoding) -> Pos {
+        let offset = offset.min(self.src.len());
+        let line = self
+            .line_starts
+            .partition_point(|&start| start <= offset)
+            .saturating_sub(1);
+        let text = self.line(line).unwrap_or_default();
+        // Offsets within a line ending are the end of the line.
+        let byte_col = (offset - self.line_starts[line]).min(text.len());
+        if encoding == Encoding::Utf8 && text.is_char_boundary(byte_col) {
+            return Pos {
+                line,
+                char: byte_col,
+            };
+        }
+        let char = text
+            .char_indices()
+            .take_while(|&(i, c)| i + c.len_utf8() <= byte_col)
+            .map(|(_, c)| encoding.len(c))
+            .sum();
+        Pos { line, char }
+    }
+    /// The byte offset of the given position, with the column in the given encoding.
+    ///
+    /// As per LSP, a column past the end of its line is the end of the line, and a line past the
+    /// end of the source is the end of the source. Columns within a char are the start of it.
+    pub fn offset(&self, pos: Pos, encoding: Encoding) -> usize {
+        let Some(text) = self.line(pos.line) else {
+            return self.src.len();
+        };
+        let mut units = 0;
+        let byte_col = text
+            .char_indices()
+            .find(|&(_, c)| {
+                units += encoding.len(c);
+                units > pos.char
+            })
+            .map_or(text.len(), |(i, _)| i);
+        self.line_starts[pos.line] + byte_col
+    }
+    /// Convert the column of a position from one encoding to another.
+    pub fn convert(&self, pos: Pos, from: Encoding, to: Encoding) -> Pos {
+        if from == to {
+            return pos;
+        }
+        self.position(self.offset(pos, from), to)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // `ë` is 2 bytes and 1 UTF-16 unit, `𝔸` is 4 bytes and 2 UTF-16 units.
+    const SRC: &str = "{\n  name = \"Zoë 𝔸\"; x = 1;\n}";
+
+    #[test]
+    fn position_of_offset() {
+        let li = LineIndex::new(SRC);
+        let x = SRC.find('x').unwrap();
+        let pos = |line, char| Pos { line, char };
+        assert_eq!(li.position(x, Encoding::Utf8), pos(1, 22));
+        assert_eq!(li.position(x, Encoding::Utf16), pos(1, 19));
+        assert_eq!(li.position(x, Encoding::Utf32), pos(1, 18));
+        assert_eq!(li.position(0, Encoding::Utf16), pos(0, 0));
+        assert_eq!(li.position(SRC.len(), Encoding::Utf16), pos(2, 1));
+        assert_eq!(li.position(SRC.len() + 10, Encoding::Utf16), pos(2, 1));
+        // Within `𝔸`.
+        let a = SRC.find('𝔸').unwrap();
+        assert_eq!(li.position(a + 2, Encoding::Utf16), pos(1, 14));
+    }
+    #[test]
+    fn offset_of_position() {
+        let li = LineIndex::new(SRC);
+        let x = SRC.find('x').unwrap();
+        let pos = |line, char| Pos { line, char };
+        assert_eq!(li.offset(pos(1, 22), Encoding::Utf8), x);
+        assert_eq!(li.offset(pos(1, 19), Encoding::Utf16), x);
+        assert_eq!(li.offset(pos(1, 18), Encoding::Utf32), x);
+        // Past the end of a line, and of the source.
+        assert_eq!(li.offset(pos(0, 9), Encoding::Utf16), 1);
+        assert_eq!(li.offset(pos(5, 0), Encoding::Utf16), SRC.len());
+        // Within `𝔸`.
+        let a = SRC.find('𝔸').unwrap();
+        assert_eq!(li.offset(pos(1, 15), Encoding::Utf16), a);
+    }
+    #[test]
+    fn line_endings() {
+        let li = LineIndex::new("a\nbb\r\nccc\rd\r\r\n");
+        let lines: Vec<_> = li.lines().collect();
+        assert_eq!(lines, vec!["a", "bb", "ccc", "d", "", ""]);
+        let pos = |line, char| Pos { line, char };
+        // The `\n` of `\r\n` is within the line ending.
+        assert_eq!(li.position(5, Encoding::Utf16), pos(1, 2));
+        assert_eq!(li.position(6, Encoding::Utf16), pos(2, 0));
+        assert_eq!(li.position(10, Encoding::Utf16), pos(3, 0));
+        assert_eq!(li.offset(pos(1, 9), Encoding::Utf16), 4);
+        assert_eq!(li.offset(pos(3, 0), Encoding::Utf16), 10);
+    }
+    #[test]
+    fn convert_roundtrip() {
+        let li = LineIndex::new(SRC);
+        for (offset, _) in SRC.char_indices() {
+            for encoding in [Encoding::Utf8, Encoding::Utf16, Encoding::Utf32] {
+                let pos = li.position(offset, encoding);
+                assert_eq!(li.offset(pos, encoding), offset);
+                let bytes = li.convert(pos, encoding, Encoding::Utf8);
+                assert_eq!(li.convert(bytes, Encoding::Utf8, encoding), pos);
+            }
+        }
+    }
+}
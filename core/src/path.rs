@@ -1,7 +1,8 @@
 use core::fmt;
-use std::{fmt::Display, str::FromStr};
+use std::str::FromStr;
 
 use compact_str::CompactString;
+use thiserror::Error;
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Remote {
@@ -12,8 +13,41 @@ pub enum Remote {
     },
 }
 
+impl FromStr for Remote {
+    type Err = ParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('/');
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(host), Some(user), Some(repo), None)
+                if !host.is_empty() && !user.is_empty() && !repo.is_empty() =>
+            {
+                Ok(Self::GitRepo {
+                    host: host.into(),
+                    user: user.into(),
+                    repo: repo.into(),
+                })
+            },
+            _ => Err(ParseError::Remote(s.into())),
+        }
+    }
+}
+impl fmt::Display for Remote {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::GitRepo { host, user, repo } => write!(f, "{host}/{user}/{repo}"),
+        }
+    }
+}
+
 // NIT: Might be useful to store this in a single string, rather than parsing. I suspect i'll
 // refactor this entirely once i know how AST lookup will peform on requests.
+/// A path to a file, or a binding within a file, optionally of a specific commit of a remote.
+///
+/// The string form is `[[remote][@commit]:][file_path][::syntax_path]`, such as
+/// `github.com/NixOS/nixpkgs@a1b2c3:lib/strings.nix::concatStrings`. The syntax path is the
+/// dotted attr path of the binding within the file, as displayed by [`AstPath`].
+///
+/// [`AstPath`]: crate::index::AstPath
 #[derive(Debug, Default, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Path {
     pub remote: Option<Remote>,
@@ -22,16 +56,63 @@ pub struct Path {
     pub syntax_path: Option<CompactString>,
 }
 impl FromStr for Path {
-    type Err = ();
+    type Err = ParseError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(Default::default())
+        let non_empty = |s: &str| (!s.is_empty()).then(|| CompactString::from(s));
+        let (head, syntax_path) = match s.split_once("::") {
+            Some((head, syntax_path)) => (head, non_empty(syntax_path)),
+            None => (s, None),
+        };
+        let (location, file_path) = match head.split_once(':') {
+            Some((location, file_path)) => (Some(location), file_path),
+            None => (None, head),
+        };
+        let (remote, commit) = match location.map(|l| l.split_once('@').unwrap_or((l, ""))) {
+            Some((remote, commit)) => (
+                non_empty(remote).map(|r| r.parse()).transpose()?,
+                non_empty(commit),
+            ),
+            None => (None, None),
+        };
+        let path = Self {
+            remote,
+            commit,
+            file_path: non_empty(file_path),
+            syntax_path,
+        };
+        if path.file_path.is_none() && path.syntax_path.is_none() {
+            return Err(ParseError::Empty);
+        }
+        Ok(path)
     }
 }
 impl fmt::Display for Path {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        todo!()
+        if self.remote.is_some() || self.commit.is_some() {
+            if let Some(remote) = &self.remote {
+                write!(f, "{remote}")?;
+            }
+            if let Some(commit) = &self.commit {
+                write!(f, "@{commit}")?;
+            }
+            f.write_str(":")?;
+        }
+        if let Some(file_path) = &self.file_path {
+            f.write_str(file_path)?;
+        }
+        if let Some(syntax_path) = &self.syntax_path {
+            write!(f, "::{syntax_path}")?;
+        }
+        Ok(())
     }
 }
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ParseError {
+    #[error("expected a file path or a syntax path")]
+    Empty,
+    #[error("invalid remote `{0}`, expected `host/user/repo`")]
+    Remote(CompactString),
+}
 
 #[cfg(test)]
 macro_rules! assert_parse_and_display_eq {
@@ -42,3 +123,66 @@ macro_rules! assert_parse_and_display_eq {
         assert_eq!($str, format!("{expect}"));
     };
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn file_and_syntax_paths() {
+        assert_parse_and_display_eq!(
+            "lib/strings.nix",
+            Path {
+                file_path: Some("lib/strings.nix".into()),
+                ..Default::default()
+            }
+        );
+        assert_parse_and_display_eq!(
+            "lib/strings.nix::escape.shell",
+            Path {
+                file_path: Some("lib/strings.nix".into()),
+                syntax_path: Some("escape.shell".into()),
+                ..Default::default()
+            }
+        );
+        assert_parse_and_display_eq!(
+            "::concatStrings",
+            Path {
+                syntax_path: Some("concatStrings".into()),
+                ..Default::default()
+            }
+        );
+    }
+    #[test]
+    fn remotes_and_commits() {
+        assert_parse_and_display_eq!(
+            "github.com/NixOS/nixpkgs@a1b2c3:lib/strings.nix::concatStrings",
+            Path {
+                remote: Some(Remote::GitRepo {
+                    host: "github.com".into(),
+                    user: "NixOS".into(),
+                    repo: "nixpkgs".into(),
+                }),
+                commit: Some("a1b2c3".into()),
+                file_path: Some("lib/strings.nix".into()),
+                syntax_path: Some("concatStrings".into()),
+            }
+        );
+        assert_parse_and_display_eq!(
+            "@a1b2c3:default.nix",
+            Path {
+                commit: Some("a1b2c3".into()),
+                file_path: Some("default.nix".into()),
+                ..Default::default()
+            }
+        );
+    }
+    #[test]
+    fn invalid() {
+        assert_eq!("".parse::<Path>(), Err(ParseError::Empty));
+        assert_eq!(
+            "github.com/NixOS:default.nix".parse::<Path>(),
+            Err(ParseError::Remote("github.com/NixOS".into()))
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use crate::index::TancIndex;
+use compact_str::CompactString;
+use ignore::WalkBuilder;
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// A directory of Nix sources, indexed along with the sources themselves.
+///
+/// Files are keyed by their path relative to [`Self::root`], with `/` separators regardless of
+/// platform, so that output derived from a project is stable across machines.
+#[derive(Debug, Default, Clone)]
+pub struct Project {
+    root: PathBuf,
+    index: TancIndex,
+    sources: BTreeMap<CompactString, String>,
+}
+impl Project {
+    /// Index every Nix file under the root, reusing the user's on-disk cache.
+    pub fn load(root: impl Into<PathBuf>) -> io::Result<Self> {
+        let root = root.into();
+        let mut project = Self {
+            root,
+            index: TancIndex::with_user_cache(),
+            ..Default::default()
+        };
+        project.sources = read_sources(&project.root)?;
+        project.index.par_insert(
+            None,
+            &project.sources,
+            |(file_path, src)| Some((file_path.clone(), src.clone())),
+            || {},
+        );
+        Ok(project)
+    }
+    pub fn insert(&mut self, file_path: impl Into<CompactString>, src: String) {
+        let file_path = file_path.into();
+        self.index.insert(file_path.clone(), &src);
+        self.sources.insert(file_path, src);
+    }
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+    pub fn index(&self) -> &TancIndex {
+        &self.index
+    }
+    pub fn source(&self, file_path: &str) -> Option<&str> {
+        self.sources.get(file_path).map(String::as_str)
+    }
+}
+/// Recursively find every `*.nix` file under `root`, skipping hidden files and anything ignored
+/// by `.gitignore` or `.ignore` files.
+pub fn nix_files(root: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    // Respect `.gitignore` even outside of a git repository, such as an extracted tarball.
+    for entry in WalkBuilder::new(root).require_git(false).build() {
+        let entry = entry.map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        let path = entry.path();
+        let is_file = entry.file_type().map_or(false, |ty| ty.is_file());
+        if is_file && path.extension().map_or(false, |ext| ext == "nix") {
+            files.push(entry.into_path());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+/// The source of every `*.nix` file under `root` keyed by its relative path, skipping those
+/// which can't be read such as those which aren't UTF-8.
+fn read_sources(root: &Path) -> io::Result<BTreeMap<CompactString, String>> {
+    let mut sources = BTreeMap::new();
+    for path in nix_files(root)? {
+        match fs::read_to_string(&path) {
+            Ok(src) => {
+                sources.insert(relative_file_path(root, &path), src);
+            },
+            Err(err) => tracing::warn!("failed to read {}: {err}", path.display()),
+        }
+    }
+    Ok(sources)
+}
+fn relative_file_path(root: &Path, path: &Path) -> CompactString {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    let segs: Vec<_> = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect();
+    segs.join("/").into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn skip_unreadable_sources() {
+        let root = std::env::temp_dir().join(format!("tanc-project-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("lib")).unwrap();
+        fs::write(root.join("lib/a.nix"), "{ }").unwrap();
+        fs::write(root.join("latin1.nix"), b"# caf\xe9\n{ }").unwrap();
+        let sources = read_sources(&root).unwrap();
+        let paths: Vec<_> = sources.keys().map(CompactString::as_str).collect();
+        assert_eq!(paths, ["lib/a.nix"]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
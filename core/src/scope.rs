@@ -0,0 +1,463 @@
+//! The lexical scopes of a Nix source, resolving each identifier to the binding it refers to.
+//!
+//! Bindings of `let` blocks, `rec` attrsets and lambdas are lexical, and take precedence over the
+//! builtins in scope everywhere such as `map`. Identifiers bound by neither are looked up in the
+//! namespaces of the enclosing `with` expressions, which are only known once evaluated.
+use crate::builtins;
+use rnix::{
+    ast::{self, HasEntry},
+    SyntaxKind, SyntaxNode, TextRange,
+};
+use rowan::ast::AstNode;
+use std::collections::HashMap;
+
+/// Names in scope everywhere which aren't in the table of builtins.
+const CONSTANTS: &[&str] = &["builtins", "true", "false", "null"];
+
+/// Every binding, reference and `with` of a source.
+#[derive(Debug, Default, Clone)]
+pub struct Scopes {
+    defs: Vec<Def>,
+    refs: Vec<Ref>,
+    withs: Vec<ast::With>,
+    /// The index of the binding of each name's range.
+    def_ranges: HashMap<TextRange, usize>,
+    /// The index of the reference of each identifier's range.
+    ref_ranges: HashMap<TextRange, usize>,
+}
+/// A name bound lexically.
+#[derive(Debug, Clone)]
+pub struct Def {
+    pub name: String,
+    pub kind: DefKind,
+    /// The range of the name.
+    pub range: TextRange,
+    /// The node binding the name, such as the `AttrpathValue` of a `let` binding, the `PatEntry`
+    /// of a formal or the `Ident` of an inherited name.
+    pub node: SyntaxNode,
+    /// The value bound, for bindings of a single attr such as `a = 1;` but not `a.b = 1;`.
+    pub value: Option<ast::Expr>,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefKind {
+    /// A binding of a `let` block, including those inherited.
+    Let,
+    /// An attr of a `rec` attrset.
+    Rec,
+    /// A plain lambda param, `x: ...`.
+    Param,
+    /// A formal of an attrset pattern, `{ x }: ...`.
+    Formal,
+    /// The name of the whole attrset of a pattern, `args` of `{ ... }@args: ...`.
+    PatBind,
+}
+/// An identifier referring to a name, either an expression or a name inherited from the
+/// enclosing scope such as of `inherit hello;`.
+#[derive(Debug, Clone)]
+pub struct Ref {
+    pub name: String,
+    pub range: TextRange,
+    pub resolution: Resolution,
+    /// The indexes of the `with` expressions the reference is within, innermost first. For a
+    /// reference resolved to a binding, only those within the scope of the binding, whose
+    /// namespaces the binding takes precedence over.
+    pub withs: Vec<usize>,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// The binding of the given index of [`Scopes::defs`].
+    Def(usize),
+    /// A builtin in scope everywhere, such as `map` or `true`.
+    Builtin,
+    /// Not lexically bound, and so possibly from the namespace of an enclosing `with`.
+    With,
+    /// Not bound at all.
+    Free,
+}
+impl Scopes {
+    pub fn analyze(root: &SyntaxNode) -> Self {
+        let mut analyzer = Analyzer::default();
+        analyzer.node(root);
+        let mut scopes = analyzer.scopes;
+        for (i, def) in scopes.defs.iter().enumerate() {
+            scopes.def_ranges.entry(def.range).or_insert(i);
+        }
+        for (i, r) in scopes.refs.iter().enumerate() {
+            scopes.ref_ranges.entry(r.range).or_insert(i);
+        }
+        scopes
+    }
+    pub fn defs(&self) -> &[Def] {
+        &self.defs
+    }
+    /// The index of the binding of the name at exactly the given range, if it's a binding.
+    pub fn def(&self, range: TextRange) -> Option<usize> {
+        self.def_ranges.get(&range).copied()
+    }
+    pub fn refs(&self) -> &[Ref] {
+        &self.refs
+    }
+    pub fn withs(&self) -> &[ast::With] {
+        &self.withs
+    }
+    /// The reference of the identifier at exactly the given range, if it's a reference.
+    pub fn reference(&self, range: TextRange) -> Option<&Ref> {
+        self.ref_ranges.get(&range).map(|&r| &self.refs[r])
+    }
+}
+/// A scope entered while walking a source.
+#[derive(Debug)]
+enum Frame {
+    Defs(HashMap<String, usize>),
+    With(usize),
+}
+#[derive(Debug, Default)]
+struct Analyzer {
+    scopes: Scopes,
+    frames: Vec<Frame>,
+}
+impl Analyzer {
+    fn node(&mut self, node: &SyntaxNode) {
+        match node.kind() {
+            SyntaxKind::NODE_IDENT => self.reference(node),
+            SyntaxKind::NODE_LET_IN => {
+                let Some(let_in) = ast::LetIn::cast(node.clone()) else {
+                    return;
+                };
+                self.bindings(&let_in, DefKind::Let);
+                if let Some(body) = let_in.body() {
+                    self.node(body.syntax());
+                }
+                self.frames.pop();
+            },
+            SyntaxKind::NODE_LEGACY_LET => {
+                // `let { body = ...; }`, whose body is one of its bindings.
+                if let Some(legacy) = ast::LegacyLet::cast(node.clone()) {
+                    self.bindings(&legacy, DefKind::Let);
+                    self.frames.pop();
+                }
+            },
+            SyntaxKind::NODE_ATTR_SET => {
+                let Some(attr_set) = ast::AttrSet::cast(node.clone()) else {
+                    return;
+                };
+                if attr_set.rec_token().is_some() {
+                    self.bindings(&attr_set, DefKind::Rec);
+                    self.frames.pop();
+                } else {
+                    self.entries(&attr_set, true);
+                }
+            },
+            SyntaxKind::NODE_LAMBDA => {
+                if let Some(lambda) = ast::Lambda::cast(node.clone()) {
+                    self.lambda(&lambda);
+                }
+            },
+            SyntaxKind::NODE_WITH => {
+                let Some(with) = ast::With::cast(node.clone()) else {
+                    return;
+                };
+                if let Some(namespace) = with.namespace() {
+                    self.node(namespace.syntax());
+                }
+                self.frames.push(Frame::With(self.scopes.withs.len()));
+                self.scopes.withs.push(with.clone());
+                if let Some(body) = with.body() {
+                    self.node(body.syntax());
+                }
+                self.frames.pop();
+            },
+            SyntaxKind::NODE_ATTRPATH => {
+                if let Some(attrpath) = ast::Attrpath::cast(node.clone()) {
+                    self.attrpath(&attrpath);
+                }
+            },
+            _ => {
+                for child in node.children() {
+                    self.node(&child);
+                }
+            },
+        }
+    }
+    /// Define the bindings of a `let` block or `rec` attrset, and walk their values within the
+    /// scope of them, which is left on the stack of frames.
+    fn bindings(&mut self, entries: &impl HasEntry, kind: DefKind) {
+        // Names inherited without a source refer to the enclosing scope, as `inherit x;` of a
+        // `let` block would otherwise be `x = x;`.
+        for inherit in entries
+            .inherits()
+            .filter(|inherit| inherit.from().is_none())
+        {
+            for attr in inherit.attrs() {
+                if let ast::Attr::Ident(ident) = attr {
+                    self.reference(ident.syntax());
+                }
+            }
+        }
+        let mut defs = HashMap::new();
+        for entry in entries.entries() {
+            match entry {
+                ast::Entry::AttrpathValue(apv) => {
+                    let Some(attrpath) = apv.attrpath() else {
+                        continue;
+                    };
+                    let mut attrs = attrpath.attrs();
+                    let Some(ast::Attr::Ident(ident)) = attrs.next() else {
+                        continue;
+                    };
+                    let value = if attrs.next().is_none() {
+                        apv.value()
+                    } else {
+                        None
+                    };
+                    self.define(&mut defs, kind, &ident, apv.syntax(), value);
+                },
+                ast::Entry::Inherit(inherit) => {
+                    for attr in inherit.attrs() {
+                        if let ast::Attr::Ident(ident) = attr {
+                            self.define(&mut defs, kind, &ident, ident.syntax(), None);
+                        }
+                    }
+                },
+            }
+        }
+        self.frames.push(Frame::Defs(defs));
+        self.entries(entries, false);
+    }
+    /// Walk the values of bindings, along with the names they inherit if `inherits` is true.
+    fn entries(&mut self, entries: &impl HasEntry, inherits: bool) {
+        for entry in entries.entries() {
+            match entry {
+                ast::Entry::AttrpathValue(apv) => {
+                    if let Some(attrpath) = apv.attrpath() {
+                        self.attrpath(&attrpath);
+                    }
+                    if let Some(value) = apv.value() {
+                        self.node(value.syntax());
+                    }
+                },
+                ast::Entry::Inherit(inherit) => match inherit.from() {
+                    Some(from) => {
+                        if let Some(expr) = from.expr() {
+                            self.node(expr.syntax());
+                        }
+                    },
+                    None if inherits => {
+                        for attr in inherit.attrs() {
+                            if let ast::Attr::Ident(ident) = attr {
+                                self.reference(ident.syntax());
+                            }
+                        }
+                    },
+                    None => {},
+                },
+            }
+        }
+    }
+    fn lambda(&mut self, lambda: &ast::Lambda) {
+        let mut defs = HashMap::new();
+        let mut defaults = Vec::new();
+        match lambda.param() {
+            Some(ast::Param::IdentParam(param)) => {
+                if let Some(ident) = param.ident() {
+                    self.define(&mut defs, DefKind::Param, &ident, ident.syntax(), None);
+                }
+            },
+            Some(ast::Param::Pattern(pattern)) => {
+                for entry in pattern.pat_entries() {
+                    if let Some(ident) = entry.ident() {
+                        self.define(&mut defs, DefKind::Formal, &ident, entry.syntax(), None);
+                    }
+                    defaults.extend(entry.default());
+                }
+                if let Some(bind) = pattern.pat_bind() {
+                    if let Some(ident) = bind.ident() {
+                        self.define(&mut defs, DefKind::PatBind, &ident, bind.syntax(), None);
+                    }
+                }
+            },
+            None => {},
+        }
+        // Defaults may refer to the other formals.
+        self.frames.push(Frame::Defs(defs));
+        for default in defaults {
+            self.node(default.syntax());
+        }
+        if let Some(body) = lambda.body() {
+            self.node(body.syntax());
+        }
+        self.frames.pop();
+    }
+    /// Walk the dynamic attrs of an attrpath, such as `${x}` of `a.${x}`, whose idents are names
+    /// rather than references.
+    fn attrpath(&mut self, attrpath: &ast::Attrpath) {
+        for attr in attrpath.attrs() {
+            match attr {
+                ast::Attr::Ident(_) => {},
+                ast::Attr::Dynamic(dynamic) => {
+                    if let Some(expr) = dynamic.expr() {
+                        self.node(expr.syntax());
+                    }
+                },
+                ast::Attr::Str(s) => self.node(s.syntax()),
+            }
+        }
+    }
+    fn define(
+        &mut self,
+        defs: &mut HashMap<String, usize>,
+        kind: DefKind,
+        ident: &ast::Ident,
+        node: &SyntaxNode,
+        value: Option<ast::Expr>,
+    ) {
+        let name = ident.syntax().text().to_string();
+        // Bindings such as `a.b = 1; a.c = 2;` bind `a` once.
+        if defs.contains_key(&name) {
+            return;
+        }
+        defs.insert(name.clone(), self.scopes.defs.len());
+        self.scopes.defs.push(Def {
+            name,
+            kind,
+            range: ident.syntax().text_range(),
+            node: node.clone(),
+            value,
+        });
+    }
+    fn reference(&mut self, ident: &SyntaxNode) {
+        let name = ident.text().to_string();
+        let mut withs = Vec::new();
+        let mut resolution = None;
+        for frame in self.frames.iter().rev() {
+            match frame {
+                Frame::Defs(defs) => {
+                    if let Some(&def) = defs.get(&name) {
+                        resolution = Some(Resolution::Def(def));
+                        break;
+                    }
+                },
+                Frame::With(with) => withs.push(*with),
+            }
+        }
+        let resolution = resolution.unwrap_or_else(|| {
+            // Builtins are bound lexically at the root, so take precedence over any `with`.
+            if CONSTANTS.contains(&name.as_str()) || builtins::resolve(&[&name]).is_some() {
+                Resolution::Builtin
+            } else if !withs.is_empty() {
+                Resolution::With
+            } else {
+                Resolution::Free
+            }
+        });
+        self.scopes.refs.push(Ref {
+            name,
+            range: ident.text_range(),
+            resolution,
+            withs,
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The resolution of every reference of `src`, rendered as the name along with the kind and
+    /// offset of the binding it resolves to, and the number of `with`s it's within.
+    fn resolve(src: &str) -> Vec<String> {
+        let root = rnix::Root::parse(src).syntax();
+        let scopes = Scopes::analyze(&root);
+        scopes
+            .refs()
+            .iter()
+            .map(|r| {
+                let resolution = match r.resolution {
+                    Resolution::Def(def) => {
+                        let def = &scopes.defs()[def];
+                        format!("{:?}@{}", def.kind, u32::from(def.range.start()))
+                    },
+                    resolution => format!("{resolution:?}"),
+                };
+                format!("{} {resolution} {}", r.name, r.withs.len())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn lexical() {
+        assert_eq!(
+            resolve("let a = 1; b = a; in { x }: y: { c = a + b + x + y + z; }"),
+            [
+                "a Let@4 0",
+                "a Let@4 0",
+                "b Let@11 0",
+                "x Formal@23 0",
+                "y Param@28 0",
+                "z Free 0"
+            ]
+        );
+        // Defaults of formals may refer to other formals, while attrs are only bound by `rec`.
+        assert_eq!(
+            resolve("{ a, b ? a }@args: [ args rec { c = d; d = 1; } { e = f; f = 1; } ]"),
+            [
+                "a Formal@2 0",
+                "args PatBind@13 0",
+                "d Rec@39 0",
+                "f Free 0"
+            ]
+        );
+    }
+    #[test]
+    fn inherits() {
+        // Names inherited without a source are of the enclosing scope.
+        assert_eq!(
+            resolve("x: let inherit x; inherit (y) z; in [ x z ]"),
+            ["x Param@0 0", "y Free 0", "x Let@15 0", "z Let@30 0"]
+        );
+        assert_eq!(resolve("x: { inherit x; }"), ["x Param@0 0"]);
+    }
+    #[test]
+    fn withs() {
+        assert_eq!(
+            resolve("{ pkgs, git }: with pkgs; [ hello git map true ]"),
+            [
+                "pkgs Formal@2 0",
+                "hello With 1",
+                "git Formal@8 1",
+                "map Builtin 1",
+                "true Builtin 1"
+            ]
+        );
+        // Only the `with`s within the scope of a binding are shadowed by it.
+        assert_eq!(
+            resolve("with a; let b = 1; in with c; b"),
+            ["a Free 0", "c With 1", "b Let@12 1"]
+        );
+        let root = rnix::Root::parse("with a; with b; x").syntax();
+        let scopes = Scopes::analyze(&root);
+        let x = &scopes.refs()[2];
+        assert_eq!(x.resolution, Resolution::With);
+        let namespaces: Vec<String> = x
+            .withs
+            .iter()
+            .map(|&with| scopes.withs()[with].namespace().unwrap().to_string())
+            .collect();
+        assert_eq!(namespaces, ["b", "a"]);
+    }
+    #[test]
+    fn ranges() {
+        let root = rnix::Root::parse("x: let inherit x; in x").syntax();
+        let scopes = Scopes::analyze(&root);
+        let range = |start: u32, end: u32| TextRange::new(start.into(), end.into());
+        assert_eq!(scopes.def(range(0, 1)), Some(0));
+        // The name of `inherit x;` is both a binding and a reference to the param.
+        assert_eq!(scopes.def(range(15, 16)), Some(1));
+        let inherited = scopes.reference(range(15, 16)).unwrap();
+        assert_eq!(inherited.resolution, Resolution::Def(0));
+        let x = scopes.reference(range(21, 22)).unwrap();
+        assert_eq!(x.resolution, Resolution::Def(1));
+        assert!(scopes.def(range(21, 22)).is_none());
+        assert!(scopes.reference(range(0, 1)).is_none());
+    }
+}
@@ -0,0 +1,163 @@
+//! The search path of Nix, such as from `NIX_PATH`, which resolves angle-bracket paths like
+//! `<nixpkgs>` and `<nixpkgs/lib>`.
+use std::path::{Path, PathBuf};
+
+/// Schemes of entries which Nix downloads rather than reads, and which tanc thus ignores to stay
+/// offline.
+const URL_SCHEMES: &[&str] = &[
+    "channel:", "flake:", "http://", "https://", "file://", "git://", "s3://", "ssh://",
+];
+
+/// The entries searched in order for an angle-bracket path.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SearchPath {
+    entries: Vec<SearchPathEntry>,
+}
+/// An entry of a search path, either `name=path` or a plain directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchPathEntry {
+    /// The name looked up, such as `nixpkgs` of `nixpkgs=/path/to/nixpkgs`. `None` for a plain
+    /// directory, within which any name is looked up.
+    pub prefix: Option<String>,
+    pub path: PathBuf,
+}
+impl SearchPath {
+    /// An empty search path, resolving nothing.
+    pub const fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+    /// Parse a search path in the format of `NIX_PATH`, entries separated by `:`. Entries of URLs
+    /// are skipped.
+    pub fn parse(nix_path: &str) -> Self {
+        let mut entries = Vec::new();
+        let mut rest = nix_path;
+        while !rest.is_empty() {
+            // The value may be a URL, whose `:` doesn't separate entries.
+            let value_start = match rest.find('=') {
+                Some(i) if !rest[..i].contains(['/', ':']) => i + 1,
+                _ => 0,
+            };
+            let value = &rest[value_start..];
+            let scheme = URL_SCHEMES
+                .iter()
+                .find(|scheme| value.starts_with(*scheme))
+                .map_or(0, |scheme| scheme.len());
+            let end = value[scheme..]
+                .find(':')
+                .map_or(rest.len(), |i| value_start + scheme + i);
+            let entry = &rest[..end];
+            rest = rest.get(end + 1..).unwrap_or_default();
+            if entry.is_empty() {
+                continue;
+            }
+            if scheme > 0 {
+                tracing::debug!("ignoring search path entry of a URL: {entry}");
+                continue;
+            }
+            entries.push(match entry.split_once('=') {
+                Some((prefix, path)) if value_start > 0 => SearchPathEntry {
+                    prefix: Some(prefix.to_owned()),
+                    path: path.into(),
+                },
+                _ => SearchPathEntry {
+                    prefix: None,
+                    path: entry.into(),
+                },
+            });
+        }
+        Self { entries }
+    }
+    pub fn entries(&self) -> &[SearchPathEntry] {
+        &self.entries
+    }
+    /// The canonical path of the first existing file or directory of the lookup of an
+    /// angle-bracket path, such as `nixpkgs/lib` of `<nixpkgs/lib>`.
+    pub fn resolve(&self, lookup: &str) -> Option<PathBuf> {
+        self.entries.iter().find_map(|entry| {
+            let suffix = match &entry.prefix {
+                Some(prefix) => match lookup.strip_prefix(prefix.as_str())? {
+                    "" => "",
+                    suffix => suffix.strip_prefix('/')?,
+                },
+                None => lookup,
+            };
+            let path = match suffix {
+                "" => entry.path.clone(),
+                suffix => entry.path.join(suffix),
+            };
+            path.canonicalize().ok()
+        })
+    }
+    /// The directories of the entries with a name, such as that of `nixpkgs=/path/to/nixpkgs`.
+    ///
+    /// Plain directories may hold anything, such as every channel of a user, so only named
+    /// directories are worth indexing as libraries.
+    pub fn named_directories(&self) -> impl Iterator<Item = &Path> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.prefix.is_some() && entry.path.is_dir())
+            .map(|entry| entry.path.as_path())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{env, fs};
+
+    fn entry(prefix: Option<&str>, path: &str) -> SearchPathEntry {
+        SearchPathEntry {
+            prefix: prefix.map(Into::into),
+            path: path.into(),
+        }
+    }
+
+    #[test]
+    fn parse() {
+        let search_path = SearchPath::parse(
+            "nixpkgs=/nix/nixpkgs:nixos-config=/etc/nixos/configuration.nix::/nix/channels:\
+             unstable=https://example.com/nixpkgs.tar.gz:flake:nixpkgs:/srv/a=b",
+        );
+        assert_eq!(
+            search_path.entries(),
+            &[
+                entry(Some("nixpkgs"), "/nix/nixpkgs"),
+                entry(Some("nixos-config"), "/etc/nixos/configuration.nix"),
+                entry(None, "/nix/channels"),
+                entry(None, "/srv/a=b"),
+            ]
+        );
+        assert_eq!(SearchPath::parse(""), SearchPath::default());
+    }
+    #[test]
+    fn resolve() {
+        let root = env::temp_dir().join(format!("tanc-search-path-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("nixpkgs/lib")).unwrap();
+        fs::create_dir_all(root.join("channels/other")).unwrap();
+        let root = root.canonicalize().unwrap();
+        let search_path = SearchPath::parse(&format!(
+            "nixpkgs={0}/nixpkgs:missing={0}/missing:{0}/channels",
+            root.display()
+        ));
+        assert_eq!(search_path.resolve("nixpkgs"), Some(root.join("nixpkgs")));
+        assert_eq!(
+            search_path.resolve("nixpkgs/lib"),
+            Some(root.join("nixpkgs/lib"))
+        );
+        assert_eq!(search_path.resolve("nixpkgs/missing"), None);
+        assert_eq!(search_path.resolve("nixpkgslib"), None);
+        assert_eq!(
+            search_path.resolve("other"),
+            Some(root.join("channels/other"))
+        );
+        assert_eq!(search_path.resolve("missing"), None);
+        assert_eq!(
+            search_path.named_directories().collect::<Vec<_>>(),
+            vec![root.join("nixpkgs")]
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
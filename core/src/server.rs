@@ -1,25 +1,373 @@
+use crate::{
+    index::{Edit, FileIndex, Pos, TancIndex},
+    line_index::{Encoding, LineIndex},
+    search_path::SearchPath,
+};
+use arc_swap::ArcSwap;
+use compact_str::CompactString;
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::PathBuf,
+    sync::{Arc, Mutex, OnceLock, RwLock},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, ToSocketAddrs},
+};
 use tower_lsp::{jsonrpc::Result, lsp_types::*, Client, LanguageServer, LspService, Server};
 
+mod code_action;
+mod completion;
+mod definition;
+mod diagnostics;
+mod hover;
+mod semantic_tokens;
+mod signature_help;
+mod unused;
+mod workspace;
+
+/// The index shared by every client of a server, such that it stays warm between them.
+///
+/// Requests read a snapshot of the index without locking, while updates index a file without
+/// holding on to the index and then swap in a copy with the new file. Readers never block on an
+/// update, and always see a consistent index.
+pub type SharedIndex = Arc<ArcSwap<TancIndex>>;
+
+/// Settings of the server, given on the command line and extended by the
+/// `initializationOptions` of each client.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Config {
+    /// Directories to index as read-only libraries, such as a local checkout of nixpkgs.
+    pub libraries: Vec<PathBuf>,
+    /// The search path resolving angle-bracket paths such as `<nixpkgs>`, in the format of
+    /// `NIX_PATH`. Named directories of it are indexed as libraries too.
+    pub nix_path: Option<String>,
+    /// Warn of identifiers bound lexically within a `with` whose namespace also provides them,
+    /// such as `hello` of `let hello = 1; in with pkgs; hello`, which isn't `pkgs.hello`.
+    pub with_shadowing: bool,
+}
+impl Config {
+    /// This config extended by the `initializationOptions` of a client, which are ignored if
+    /// invalid. A search path of the client replaces that of the command line, while lints
+    /// enabled by either are enabled.
+    fn with_options(&self, options: Option<serde_json::Value>) -> Self {
+        let mut config = self.clone();
+        let Some(options) = options else {
+            return config;
+        };
+        match serde_json::from_value::<Config>(options) {
+            Ok(options) => {
+                for library in options.libraries {
+                    if !config.libraries.contains(&library) {
+                        config.libraries.push(library);
+                    }
+                }
+                if options.nix_path.is_some() {
+                    config.nix_path = options.nix_path;
+                }
+                config.with_shadowing |= options.with_shadowing;
+            },
+            Err(err) => tracing::warn!("ignoring invalid initialization options: {err}"),
+        }
+        config
+    }
+}
+
 #[derive(Debug)]
 pub struct Backend {
     client: Client,
+    index: SharedIndex,
+    config: Config,
+    /// The latest text of every document opened by this client, shared so that requests can
+    /// release the lock before reading it.
+    documents: RwLock<HashMap<Url, Arc<str>>>,
+    /// Held while a document or an edit of one is indexed, rather than `documents`.
+    edits: Mutex<()>,
+    workspace: OnceLock<workspace::Workspace>,
+    /// The encoding of the columns of positions, as negotiated with the client.
+    position_encoding: OnceLock<Encoding>,
+    /// The latest semantic tokens sent of each document, to send later requests as edits.
+    semantic_tokens: semantic_tokens::TokenCache,
 }
 impl Backend {
-    pub async fn new() {
-        let stdin = tokio::io::stdin();
-        let stdout = tokio::io::stdout();
-
-        let (service, socket) = LspService::new(|client| Backend { client });
-        Server::new(stdin, stdout, socket).serve(service).await;
+    /// Serve a single client over stdin and stdout.
+    pub async fn serve_stdio(config: Config) {
+        Self::serve(
+            user_index(),
+            config,
+            tokio::io::stdin(),
+            tokio::io::stdout(),
+        )
+        .await;
+    }
+    /// Listen for clients over TCP, serving each in turn until the listener fails.
+    pub async fn listen_tcp(addr: impl ToSocketAddrs, config: Config) -> io::Result<()> {
+        Self::serve_tcp(TcpListener::bind(addr).await?, user_index(), config).await
+    }
+    pub async fn serve_tcp(
+        listener: TcpListener,
+        index: SharedIndex,
+        config: Config,
+    ) -> io::Result<()> {
+        tracing::info!("listening on {}", listener.local_addr()?);
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            tracing::info!("serving client {peer}");
+            let (read, write) = stream.into_split();
+            Self::serve(index.clone(), config.clone(), read, write).await;
+            tracing::info!("client {peer} disconnected");
+        }
+    }
+    /// Listen for clients over a Unix domain socket, serving each in turn until the listener
+    /// fails.
+    ///
+    /// An existing socket at the path is replaced, as it's assumed to be the stale socket of a
+    /// previous server. Any other file is left alone, failing with
+    /// [`io::ErrorKind::AlreadyExists`].
+    #[cfg(unix)]
+    pub async fn listen_unix(path: &std::path::Path, config: Config) -> io::Result<()> {
+        use std::os::unix::fs::FileTypeExt;
+        match std::fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)?,
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} exists and is not a socket", path.display()),
+                ))
+            },
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {},
+            Err(err) => return Err(err),
+        }
+        let listener = tokio::net::UnixListener::bind(path)?;
+        tracing::info!("listening on {}", path.display());
+        let index = user_index();
+        loop {
+            let (stream, _) = listener.accept().await?;
+            tracing::info!("serving client");
+            let (read, write) = stream.into_split();
+            Self::serve(index.clone(), config.clone(), read, write).await;
+            tracing::info!("client disconnected");
+        }
+    }
+    /// Serve a single client until it exits or disconnects.
+    pub async fn serve<I, O>(index: SharedIndex, config: Config, input: I, output: O)
+    where
+        I: AsyncRead + Unpin,
+        O: AsyncWrite,
+    {
+        let (service, socket) = LspService::new(|client| Backend {
+            client,
+            index,
+            config,
+            documents: Default::default(),
+            edits: Default::default(),
+            workspace: Default::default(),
+            position_encoding: Default::default(),
+            semantic_tokens: Default::default(),
+        });
+        Server::new(input, output, socket).serve(service).await;
+    }
+    fn update_document(&self, uri: Url, text: String, saved: bool) {
+        let file_path = file_path(&uri);
+        // Edits are indexed on top of the index of the document, which mustn't change meanwhile.
+        let _indexing = self.edits.lock().expect("edits lock poisoned");
+        // Record the document before indexing it, so that workspace indexing sees it as open and
+        // doesn't replace it. See [`workspace::Workspace::index`].
+        let text: Arc<str> = text.into();
+        self.documents
+            .write()
+            .expect("documents lock poisoned")
+            .insert(uri, text.clone());
+        let fi = Arc::new(if saved {
+            self.index.load().index_file(None, &text)
+        } else {
+            FileIndex::new(&text)
+        });
+        self.index.rcu(|index| {
+            let mut index = TancIndex::clone(index);
+            index.insert_file(file_path.clone(), fi.clone());
+            index
+        });
+    }
+    /// Apply incremental changes to an open document, re-parsing only the bindings they're within
+    /// to update its index.
+    fn edit_document(&self, uri: Url, changes: Vec<TextDocumentContentChangeEvent>) {
+        let file_path = file_path(&uri);
+        // Each edit is indexed on top of the previous, so edits are indexed one at a time.
+        let _indexing = self.edits.lock().expect("edits lock poisoned");
+        let mut documents = self.documents.write().expect("documents lock poisoned");
+        let Some(text) = documents.get(&uri) else {
+            tracing::warn!("ignoring changes to unopened document {uri}");
+            return;
+        };
+        let mut text = text.to_string();
+        let encoding = self.position_encoding();
+        // The text after each change since the last replacing the entire document, along with
+        // the edit of the change if it didn't replace it.
+        let mut changed: Vec<(Option<Edit>, String)> = Vec::new();
+        for change in changes {
+            let Some(range) = change.range else {
+                // A change without a range replaces the entire document.
+                changed.clear();
+                changed.push((None, change.text.clone()));
+                text = change.text;
+                continue;
+            };
+            let line_index = LineIndex::new(&text);
+            let start = line_index.convert(pos(range.start), encoding, Encoding::Utf8);
+            let end = line_index.convert(pos(range.end), encoding, Encoding::Utf8);
+            let edit = Edit {
+                range: (start.line, start.char, end.line, end.char).into(),
+                text: change.text,
+            };
+            edit.apply(&mut text);
+            changed.push((Some(edit), text.clone()));
+        }
+        documents.insert(uri, text.into());
+        // Readers of documents needn't wait on re-indexing.
+        drop(documents);
+        let snapshot = self.index.load_full();
+        let mut fi: Option<FileIndex> = None;
+        for (edit, text) in changed {
+            let prev = fi.as_ref().or_else(|| snapshot.file(file_path.clone()));
+            fi = Some(match (edit, prev) {
+                (Some(edit), Some(prev)) => prev.edit(&text, &edit),
+                _ => FileIndex::new(&text),
+            });
+        }
+        let Some(fi) = fi.map(Arc::new) else {
+            return;
+        };
+        self.index.rcu(|index| {
+            let mut index = TancIndex::clone(index);
+            index.insert_file(file_path.clone(), fi.clone());
+            index
+        });
+    }
+    /// Lint the given document, publishing the diagnostics to the client.
+    async fn publish_diagnostics(&self, uri: Url) {
+        let Some(src) = self.document(&uri) else {
+            return;
+        };
+        let diagnostics = diagnostics::diagnostics(
+            &self.index.load(),
+            self.search_path(),
+            &src,
+            self.position_encoding(),
+            self.with_shadowing(),
+        );
+        self.client
+            .publish_diagnostics(uri, diagnostics, None)
+            .await;
+    }
+    /// The latest text of the given document, if open.
+    fn document(&self, uri: &Url) -> Option<Arc<str>> {
+        self.documents
+            .read()
+            .expect("documents lock poisoned")
+            .get(uri)
+            .cloned()
+    }
+    /// The semantic tokens of the given document, if open.
+    fn semantic_tokens_data(&self, uri: &Url) -> Option<Vec<SemanticToken>> {
+        let src = self.document(uri)?;
+        Some(semantic_tokens::semantic_tokens(
+            &self.index.load(),
+            &file_path(uri),
+            &src,
+            self.position_encoding(),
+        ))
+    }
+    /// The search path of the client, empty until it's initialized.
+    fn search_path(&self) -> &SearchPath {
+        static EMPTY: SearchPath = SearchPath::new();
+        self.workspace
+            .get()
+            .map_or(&EMPTY, |workspace| &workspace.search_path)
+    }
+    /// Whether the `with-shadowing` lint is enabled, by the command line or the client.
+    fn with_shadowing(&self) -> bool {
+        self.workspace
+            .get()
+            .map_or(self.config.with_shadowing, |workspace| {
+                workspace.with_shadowing
+            })
+    }
+    fn position_encoding(&self) -> Encoding {
+        self.position_encoding.get().copied().unwrap_or_default()
+    }
+    /// The paths of every open document.
+    fn open_files(&self) -> HashSet<CompactString> {
+        self.documents
+            .read()
+            .expect("documents lock poisoned")
+            .keys()
+            .map(file_path)
+            .collect()
     }
 }
 #[tower_lsp::async_trait]
 impl LanguageServer for Backend {
-    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        let config = self
+            .config
+            .with_options(params.initialization_options.clone());
+        let _ = self
+            .workspace
+            .set(workspace::Workspace::new(&params, &config));
+        // The index counts bytes, so UTF-8 is preferred when the client supports it. Otherwise
+        // UTF-16, which every client supports.
+        let offered = params
+            .capabilities
+            .general
+            .as_ref()
+            .and_then(|general| general.position_encodings.as_ref());
+        let (encoding, kind) = match offered {
+            Some(offered) if offered.contains(&PositionEncodingKind::UTF8) => {
+                (Encoding::Utf8, PositionEncodingKind::UTF8)
+            },
+            _ => (Encoding::Utf16, PositionEncodingKind::UTF16),
+        };
+        let _ = self.position_encoding.set(encoding);
         Ok(InitializeResult {
             capabilities: ServerCapabilities {
+                position_encoding: Some(kind),
+                text_document_sync: Some(TextDocumentSyncCapability::Kind(
+                    TextDocumentSyncKind::INCREMENTAL,
+                )),
                 hover_provider: Some(HoverProviderCapability::Simple(true)),
-                completion_provider: Some(CompletionOptions::default()),
+                definition_provider: Some(OneOf::Left(true)),
+                completion_provider: Some(CompletionOptions {
+                    trigger_characters: Some(vec![".".into()]),
+                    ..Default::default()
+                }),
+                signature_help_provider: Some(SignatureHelpOptions {
+                    trigger_characters: Some(vec![" ".into(), "{".into()]),
+                    retrigger_characters: Some(vec![";".into()]),
+                    work_done_progress_options: Default::default(),
+                }),
+                code_action_provider: Some(CodeActionProviderCapability::Options(
+                    CodeActionOptions {
+                        code_action_kinds: Some(vec![
+                            CodeActionKind::QUICKFIX,
+                            CodeActionKind::REFACTOR,
+                        ]),
+                        work_done_progress_options: Default::default(),
+                        resolve_provider: None,
+                    },
+                )),
+                semantic_tokens_provider: Some(
+                    SemanticTokensOptions {
+                        legend: semantic_tokens::legend(),
+                        full: Some(SemanticTokensFullOptions::Delta { delta: Some(true) }),
+                        range: None,
+                        work_done_progress_options: Default::default(),
+                    }
+                    .into(),
+                ),
                 ..Default::default()
             },
             ..Default::default()
@@ -30,23 +378,608 @@ impl LanguageServer for Backend {
         self.client
             .log_message(MessageType::INFO, "server initialized!")
             .await;
+        let Some(workspace) = self.workspace.get() else {
+            return;
+        };
+        workspace
+            .index(&self.client, &self.index, || self.open_files())
+            .await;
+        // Options declared by the workspace may have changed what's declared.
+        let open: Vec<Url> = self
+            .documents
+            .read()
+            .expect("documents lock poisoned")
+            .keys()
+            .cloned()
+            .collect();
+        for uri in open {
+            self.publish_diagnostics(uri).await;
+        }
     }
 
     async fn shutdown(&self) -> Result<()> {
         Ok(())
     }
 
-    async fn completion(&self, _: CompletionParams) -> Result<Option<CompletionResponse>> {
-        Ok(Some(CompletionResponse::Array(vec![
-            CompletionItem::new_simple("Hello".to_string(), "Some detail".to_string()),
-            CompletionItem::new_simple("Bye".to_string(), "More detail".to_string()),
-        ])))
+    async fn did_open(&self, params: DidOpenTextDocumentParams) {
+        // Documents are usually opened unmodified, so worth caching.
+        let uri = params.text_document.uri;
+        self.update_document(uri.clone(), params.text_document.text, true);
+        self.publish_diagnostics(uri).await;
+    }
+
+    async fn did_change(&self, params: DidChangeTextDocumentParams) {
+        let uri = params.text_document.uri;
+        self.edit_document(uri.clone(), params.content_changes);
+        self.publish_diagnostics(uri).await;
+    }
+
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        self.documents
+            .write()
+            .expect("documents lock poisoned")
+            .remove(&params.text_document.uri);
+        self.semantic_tokens.remove(&params.text_document.uri);
     }
 
-    async fn hover(&self, _: HoverParams) -> Result<Option<Hover>> {
-        Ok(Some(Hover {
-            contents: HoverContents::Scalar(MarkedString::String("You're hovering!".to_string())),
-            range: None,
-        }))
+    async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
+        let TextDocumentPositionParams {
+            text_document,
+            position,
+        } = params.text_document_position;
+        let Some(src) = self.document(&text_document.uri) else {
+            return Ok(None);
+        };
+        let offset = LineIndex::new(&src).offset(pos(position), self.position_encoding());
+        Ok(completion::completion(&self.index.load(), &src, offset).map(CompletionResponse::Array))
+    }
+
+    async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+        let TextDocumentPositionParams {
+            text_document,
+            position,
+        } = params.text_document_position_params;
+        let Some(src) = self.document(&text_document.uri) else {
+            return Ok(None);
+        };
+        let encoding = self.position_encoding();
+        Ok(hover::hover(
+            &self.index.load(),
+            self.search_path(),
+            &file_path(&text_document.uri),
+            &src,
+            LineIndex::new(&src).offset(pos(position), encoding),
+            encoding,
+        ))
+    }
+
+    async fn goto_definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> Result<Option<GotoDefinitionResponse>> {
+        let TextDocumentPositionParams {
+            text_document,
+            position,
+        } = params.text_document_position_params;
+        let Some(src) = self.document(&text_document.uri) else {
+            return Ok(None);
+        };
+        let encoding = self.position_encoding();
+        let index = self.index.load();
+        let Some((target, range)) = definition::definition(
+            &index,
+            self.search_path(),
+            &file_path(&text_document.uri),
+            &src,
+            LineIndex::new(&src).offset(pos(position), encoding),
+        ) else {
+            return Ok(None);
+        };
+        let Some(uri) = file_uri(&target) else {
+            return Ok(None);
+        };
+        // Ranges of the index are in UTF-8, and need the source of the target to convert.
+        let target_src = self
+            .document(&uri)
+            .or_else(|| fs::read_to_string(target.as_str()).ok().map(Arc::from));
+        let position = |pos: Pos| {
+            let pos = match &target_src {
+                Some(src) => LineIndex::new(src).convert(pos, Encoding::Utf8, encoding),
+                None => pos,
+            };
+            Position::new(pos.line as u32, pos.char as u32)
+        };
+        Ok(Some(GotoDefinitionResponse::Scalar(Location {
+            uri,
+            range: Range::new(position(range.start()), position(range.end())),
+        })))
+    }
+
+    async fn signature_help(&self, params: SignatureHelpParams) -> Result<Option<SignatureHelp>> {
+        let TextDocumentPositionParams {
+            text_document,
+            position,
+        } = params.text_document_position_params;
+        let Some(src) = self.document(&text_document.uri) else {
+            return Ok(None);
+        };
+        Ok(signature_help::signature_help(
+            &self.index.load(),
+            &file_path(&text_document.uri),
+            &src,
+            LineIndex::new(&src).offset(pos(position), self.position_encoding()),
+        ))
+    }
+
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        let Some(src) = self.document(&params.text_document.uri) else {
+            return Ok(None);
+        };
+        let encoding = self.position_encoding();
+        let line_index = LineIndex::new(&src);
+        let index = self.index.load();
+        let actions = code_action::code_actions(
+            &params.text_document.uri,
+            &src,
+            index.file(file_path(&params.text_document.uri)),
+            line_index.offset(pos(params.range.start), encoding),
+            line_index.offset(pos(params.range.end), encoding),
+            encoding,
+        );
+        Ok(Some(actions))
+    }
+
+    async fn semantic_tokens_full(
+        &self,
+        params: SemanticTokensParams,
+    ) -> Result<Option<SemanticTokensResult>> {
+        let uri = params.text_document.uri;
+        let Some(data) = self.semantic_tokens_data(&uri) else {
+            return Ok(None);
+        };
+        Ok(Some(self.semantic_tokens.full(uri, data).into()))
+    }
+
+    async fn semantic_tokens_full_delta(
+        &self,
+        params: SemanticTokensDeltaParams,
+    ) -> Result<Option<SemanticTokensFullDeltaResult>> {
+        let uri = params.text_document.uri;
+        let Some(data) = self.semantic_tokens_data(&uri) else {
+            return Ok(None);
+        };
+        Ok(Some(self.semantic_tokens.delta(
+            uri,
+            &params.previous_result_id,
+            data,
+        )))
+    }
+}
+/// A new index, backed by the user's on-disk cache.
+fn user_index() -> SharedIndex {
+    Arc::new(ArcSwap::from_pointee(TancIndex::with_user_cache()))
+}
+/// The path used to key the given document in the index.
+fn file_path(uri: &Url) -> CompactString {
+    uri.to_file_path()
+        .ok()
+        .and_then(|path| path.to_str().map(CompactString::from))
+        .unwrap_or_else(|| uri.as_str().into())
+}
+/// The URI of a document keyed by the given path in the index, the inverse of [`file_path`].
+fn file_uri(file_path: &str) -> Option<Url> {
+    Url::from_file_path(file_path)
+        .ok()
+        .or_else(|| Url::parse(file_path).ok())
+}
+/// The position of an LSP position, with the column in the negotiated encoding.
+fn pos(position: Position) -> Pos {
+    Pos {
+        line: position.line as usize,
+        char: position.character as usize,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::{json, Value};
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+        net::TcpStream,
+    };
+
+    /// A minimal client, speaking just enough of the protocol to drive the server.
+    struct TestClient {
+        stream: BufReader<TcpStream>,
+        next_id: u64,
+        /// Notifications from the server, not yet waited for.
+        notifications: Vec<Value>,
+        /// The result of `initialize`.
+        initialized: Value,
+    }
+    impl TestClient {
+        async fn connect(addr: std::net::SocketAddr, initialize: Value) -> Self {
+            let mut client = Self {
+                stream: BufReader::new(TcpStream::connect(addr).await.unwrap()),
+                next_id: 0,
+                notifications: Vec::new(),
+                initialized: Value::Null,
+            };
+            client.initialized = client.request("initialize", initialize).await;
+            client.notify("initialized", json!({})).await;
+            client
+        }
+        async fn send(&mut self, message: Value) {
+            let body = message.to_string();
+            let msg = format!("Content-Length: {}\r\n\r\n{body}", body.len());
+            self.stream
+                .get_mut()
+                .write_all(msg.as_bytes())
+                .await
+                .unwrap();
+        }
+        async fn notify(&mut self, method: &str, params: Value) {
+            self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }))
+                .await;
+        }
+        /// Receive the next message from the server, replying to any request with `null`.
+        async fn recv(&mut self) -> Value {
+            let mut len = 0;
+            loop {
+                let mut line = String::new();
+                self.stream.read_line(&mut line).await.unwrap();
+                match line.trim_end() {
+                    "" => break,
+                    header => {
+                        if let Some(n) = header.strip_prefix("Content-Length: ") {
+                            len = n.parse().unwrap();
+                        }
+                    },
+                }
+            }
+            let mut body = vec![0; len];
+            self.stream.read_exact(&mut body).await.unwrap();
+            let message: Value = serde_json::from_slice(&body).unwrap();
+            if message.get("method").is_some() && message.get("id").is_some() {
+                let id = message["id"].clone();
+                self.send(json!({ "jsonrpc": "2.0", "id": id, "result": null }))
+                    .await;
+            }
+            message
+        }
+        /// Send a request and wait for its result.
+        async fn request(&mut self, method: &str, params: Value) -> Value {
+            self.next_id += 1;
+            let id = self.next_id;
+            self.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }))
+                .await;
+            loop {
+                let message = self.recv().await;
+                if message.get("method").is_none() && message["id"] == id {
+                    return message["result"].clone();
+                }
+                if message.get("id").is_none() {
+                    self.notifications.push(message);
+                }
+            }
+        }
+        /// Wait for a notification from the server matching the predicate.
+        async fn notification(&mut self, pred: impl Fn(&Value) -> bool) -> Value {
+            if let Some(i) = self.notifications.iter().position(&pred) {
+                return self.notifications.remove(i);
+            }
+            loop {
+                let message = self.recv().await;
+                if message.get("id").is_none() {
+                    if pred(&message) {
+                        return message;
+                    }
+                    self.notifications.push(message);
+                }
+            }
+        }
+        async fn open(&mut self, uri: &str, text: &str) {
+            self.notify(
+                "textDocument/didOpen",
+                json!({ "textDocument": {
+                    "uri": uri, "languageId": "nix", "version": 0, "text": text,
+                } }),
+            )
+            .await;
+        }
+        /// The label of the signature active at the end of the source of the given document.
+        async fn signature_label(&mut self, uri: &str, src: &str) -> Value {
+            self.open(uri, src).await;
+            let help = self
+                .request(
+                    "textDocument/signatureHelp",
+                    json!({
+                        "textDocument": { "uri": uri },
+                        "position": { "line": 0, "character": src.len() },
+                    }),
+                )
+                .await;
+            help["signatures"][0]["label"].clone()
+        }
+        async fn exit(mut self) {
+            self.request("shutdown", Value::Null).await;
+            self.notify("exit", Value::Null).await;
+        }
+    }
+    async fn serve() -> std::net::SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(Backend::serve_tcp(
+            listener,
+            Default::default(),
+            Default::default(),
+        ));
+        addr
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn listen_unix_keeps_files() {
+        let path = std::env::temp_dir().join(format!("tanc-not-a-socket-{}", std::process::id()));
+        fs::write(&path, "keep").unwrap();
+        let err = Backend::listen_unix(&path, Default::default())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "keep");
+        fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn reads_see_consistent_snapshots() {
+        let (service, _) = LspService::new(|client| Backend {
+            client,
+            index: Default::default(),
+            config: Default::default(),
+            documents: Default::default(),
+            edits: Default::default(),
+            workspace: Default::default(),
+            position_encoding: Default::default(),
+            semantic_tokens: Default::default(),
+        });
+        let backend = service.inner();
+        let uri = Url::parse("file:///project/lib.nix").unwrap();
+        let paths = |index: &TancIndex| -> Vec<String> {
+            let fi = index.file("/project/lib.nix").unwrap();
+            fi.bindings().map(|(path, _)| path.to_string()).collect()
+        };
+        backend.update_document(uri.clone(), "{ a = 1; }".into(), false);
+        let snapshot = backend.index.load_full();
+        backend.update_document(uri, "{ b = 1; }".into(), false);
+        // A snapshot taken before an update is unaffected by it.
+        assert_eq!(paths(&snapshot), vec!["", "a"]);
+        assert_eq!(paths(&backend.index.load()), vec!["", "b"]);
+    }
+    #[tokio::test]
+    async fn sequential_tcp_clients_share_index() {
+        let addr = serve().await;
+        let mut first = TestClient::connect(addr, json!({ "capabilities": {} })).await;
+        first
+            .open("file:///project/lib.nix", "{ apply = { f, x }: f x; }")
+            .await;
+        first.exit().await;
+
+        // The second client never opens `lib.nix`, but it's still indexed.
+        let mut second = TestClient::connect(addr, json!({ "capabilities": {} })).await;
+        let label = second
+            .signature_label("file:///project/use.nix", "apply { f = 1; ")
+            .await;
+        assert_eq!(label, "apply { f, x }");
+        second.exit().await;
+    }
+    #[tokio::test]
+    async fn incremental_changes() {
+        let addr = serve().await;
+        let mut client = TestClient::connect(addr, json!({ "capabilities": {} })).await;
+        let uri = "file:///project/lib.nix";
+        client.open(uri, "{\n  apply = { f }: f;\n}").await;
+        client
+            .notify(
+                "textDocument/didChange",
+                json!({
+                    "textDocument": { "uri": uri, "version": 1 },
+                    "contentChanges": [
+                        { "range": {
+                            "start": { "line": 1, "character": 13 },
+                            "end": { "line": 1, "character": 13 },
+                        }, "text": ", x" },
+                        { "range": {
+                            "start": { "line": 1, "character": 3 },
+                            "end": { "line": 1, "character": 7 },
+                        }, "text": "pply2" },
+                    ],
+                }),
+            )
+            .await;
+        let label = client
+            .signature_label("file:///project/use.nix", "apply2 { ")
+            .await;
+        assert_eq!(label, "apply2 { f, x }");
+        client.exit().await;
+    }
+    #[tokio::test]
+    async fn publish_undeclared_options() {
+        // Options are only known to be undeclared once the NixOS modules of nixpkgs are indexed.
+        let library = std::env::temp_dir().join(format!("tanc-nixos-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&library);
+        fs::create_dir_all(library.join("nixos/modules")).unwrap();
+        fs::write(
+            library.join("nixos/modules/module-list.nix"),
+            "[ ./foo.nix ]",
+        )
+        .unwrap();
+        let module = "{ lib, ... }: { options.services.foo.enable = lib.mkEnableOption \"foo\"; }";
+        fs::write(library.join("nixos/modules/foo.nix"), module).unwrap();
+
+        let addr = serve().await;
+        let mut client = TestClient::connect(
+            addr,
+            json!({
+                "capabilities": { "window": { "workDoneProgress": true } },
+                "initializationOptions": { "libraries": [library] },
+            }),
+        )
+        .await;
+        let is_end = |message: &Value| {
+            message["method"] == "$/progress" && message["params"]["value"]["kind"] == "end"
+        };
+        // The workspace, without any roots, and then the library.
+        client.notification(is_end).await;
+        client.notification(is_end).await;
+        let uri = "file:///project/configuration.nix";
+        client.open(uri, "{\n  services.fo.enable = true;\n}").await;
+        let published = client
+            .notification(|n| {
+                n["method"] == "textDocument/publishDiagnostics" && n["params"]["uri"] == uri
+            })
+            .await;
+        let diagnostic = &published["params"]["diagnostics"][0];
+        assert_eq!(
+            diagnostic["message"],
+            "no option `services.fo` is declared, did you mean `services.foo`?"
+        );
+        assert_eq!(
+            diagnostic["range"],
+            json!({
+                "start": { "line": 1, "character": 11 },
+                "end": { "line": 1, "character": 13 },
+            })
+        );
+        client.exit().await;
+        fs::remove_dir_all(&library).unwrap();
+    }
+    #[tokio::test]
+    async fn negotiate_position_encoding() {
+        let addr = serve().await;
+        let encoding =
+            |client: &TestClient| client.initialized["capabilities"]["positionEncoding"].clone();
+        let client = TestClient::connect(addr, json!({ "capabilities": {} })).await;
+        assert_eq!(encoding(&client), "utf-16");
+        client.exit().await;
+        let init = json!({ "capabilities": { "general": {
+            "positionEncodings": ["utf-32", "utf-8", "utf-16"],
+        } } });
+        let client = TestClient::connect(addr, init).await;
+        assert_eq!(encoding(&client), "utf-8");
+        client.exit().await;
+
+        // Columns count UTF-16 code units by default, where `𝔸` is 2 units and 4 bytes.
+        let mut client = TestClient::connect(addr, json!({ "capabilities": {} })).await;
+        let uri = "file:///project/lib.nix";
+        client.open(uri, "{ \"𝔸\" = 1; apply = { f }: f; }").await;
+        client
+            .notify(
+                "textDocument/didChange",
+                json!({
+                    "textDocument": { "uri": uri, "version": 1 },
+                    "contentChanges": [{ "range": {
+                        "start": { "line": 0, "character": 23 },
+                        "end": { "line": 0, "character": 23 },
+                    }, "text": ", x" }],
+                }),
+            )
+            .await;
+        let label = client
+            .signature_label("file:///project/use.nix", "apply { ")
+            .await;
+        assert_eq!(label, "apply { f, x }");
+        client.exit().await;
+    }
+    #[tokio::test]
+    async fn index_workspace_with_progress() {
+        let root = std::env::temp_dir().join(format!("tanc-workspace-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("lib")).unwrap();
+        fs::create_dir_all(root.join("result")).unwrap();
+        fs::write(root.join(".gitignore"), "result/\n").unwrap();
+        fs::write(root.join("lib/apply.nix"), "{ apply = { f, x }: f x; }").unwrap();
+        fs::write(root.join("result/ignored.nix"), "{ ignored = y: y; }").unwrap();
+
+        let addr = serve().await;
+        let root_uri = Url::from_directory_path(&root).unwrap();
+        let mut client = TestClient::connect(
+            addr,
+            json!({
+                "rootUri": root_uri,
+                "capabilities": { "window": { "workDoneProgress": true } },
+            }),
+        )
+        .await;
+        let is_progress = |kind: &'static str| {
+            move |message: &Value| {
+                message["method"] == "$/progress" && message["params"]["value"]["kind"] == kind
+            }
+        };
+        let begin = client.notification(is_progress("begin")).await;
+        assert_eq!(begin["params"]["value"]["title"], "Indexing");
+        assert_eq!(begin["params"]["value"]["message"], "0/1 files");
+        let end = client.notification(is_progress("end")).await;
+        assert_eq!(end["params"]["value"]["message"], "Indexed 1 files");
+
+        let uri = |file: &str| Url::from_file_path(root.join(file)).unwrap().to_string();
+        let label = client.signature_label(&uri("use.nix"), "apply { ").await;
+        assert_eq!(label, "apply { f, x }");
+        let label = client.signature_label(&uri("ignore.nix"), "ignored ").await;
+        assert_eq!(label, Value::Null);
+        client.exit().await;
+        fs::remove_dir_all(&root).unwrap();
+    }
+    #[tokio::test]
+    async fn library_definitions() {
+        let library = std::env::temp_dir().join(format!("tanc-nixpkgs-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&library);
+        fs::create_dir_all(library.join("lib")).unwrap();
+        fs::write(
+            library.join("lib/trivial.nix"),
+            "{\n  # The identity function.\n  id = x: x;\n}",
+        )
+        .unwrap();
+
+        let addr = serve().await;
+        let mut client = TestClient::connect(
+            addr,
+            json!({
+                "capabilities": { "window": { "workDoneProgress": true } },
+                "initializationOptions": { "libraries": [library] },
+            }),
+        )
+        .await;
+        let is_end = |message: &Value| {
+            message["method"] == "$/progress" && message["params"]["value"]["kind"] == "end"
+        };
+        // The workspace, without any roots, and then the library.
+        client.notification(is_end).await;
+        let end = client.notification(is_end).await;
+        assert_eq!(end["params"]["value"]["message"], "Indexed 1 files");
+
+        let uri = "file:///project/main.nix";
+        let src = "{ lib }: lib.id";
+        client.open(uri, src).await;
+        let position = json!({
+            "textDocument": { "uri": uri },
+            "position": { "line": 0, "character": src.len() - 1 },
+        });
+        let hover = client.request("textDocument/hover", position.clone()).await;
+        let hover = hover["contents"]["value"].as_str().unwrap();
+        assert!(hover.contains("The identity function."), "{hover}");
+        let location = client.request("textDocument/definition", position).await;
+        let trivial = library.canonicalize().unwrap().join("lib/trivial.nix");
+        assert_eq!(
+            location,
+            json!({
+                "uri": Url::from_file_path(trivial).unwrap(),
+                "range": {
+                    "start": { "line": 2, "character": 2 },
+                    "end": { "line": 2, "character": 12 },
+                },
+            })
+        );
+        client.exit().await;
+        fs::remove_dir_all(&library).unwrap();
     }
 }
@@ -0,0 +1,292 @@
+//! Code actions of open documents, such as quick fixes of diagnostics.
+use super::unused::unused;
+use crate::{
+    index::{FileIndex, Lambda, Param},
+    line_index::{Encoding, LineIndex},
+    scope::Scopes,
+};
+use rnix::{ast, SyntaxKind, SyntaxNode, TextRange, TextSize};
+use rowan::ast::AstNode;
+use std::{collections::HashMap, fmt::Write as _};
+use tower_lsp::lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, Position, Range, TextEdit, Url, WorkspaceEdit,
+};
+
+/// The code actions of the document `uri` with source `src` which apply between the offsets
+/// `start` and `end`, with positions in the given encoding. Actions generating docs need the
+/// document's index `fi`.
+pub fn code_actions(
+    uri: &Url,
+    src: &str,
+    fi: Option<&FileIndex>,
+    start: usize,
+    end: usize,
+    encoding: Encoding,
+) -> Vec<CodeActionOrCommand> {
+    let root = rnix::Root::parse(src).syntax();
+    let line_index = LineIndex::new(src);
+    let range = |range: TextRange| {
+        let position = |offset: usize| {
+            let pos = line_index.position(offset, encoding);
+            Position::new(pos.line as u32, pos.char as u32)
+        };
+        Range::new(position(range.start().into()), position(range.end().into()))
+    };
+    let action = |title: String, kind: CodeActionKind, edits: Vec<(TextRange, String)>| {
+        let edits = edits
+            .into_iter()
+            .map(|(edit, new_text)| TextEdit {
+                range: range(edit),
+                new_text,
+            })
+            .collect();
+        let is_preferred = kind == CodeActionKind::QUICKFIX;
+        CodeActionOrCommand::CodeAction(CodeAction {
+            title,
+            kind: Some(kind),
+            edit: Some(WorkspaceEdit {
+                changes: Some(HashMap::from([(uri.clone(), edits)])),
+                ..Default::default()
+            }),
+            is_preferred: is_preferred.then_some(true),
+            ..Default::default()
+        })
+    };
+    let (Ok(start), Ok(end)) = (TextSize::try_from(start), TextSize::try_from(end)) else {
+        return Vec::new();
+    };
+    let requested = TextRange::new(start, end.max(start));
+    let mut actions: Vec<_> = unused(&Scopes::analyze(&root))
+        .into_iter()
+        .filter(|unused| unused.range.intersect(requested).is_some())
+        .map(|unused| action(unused.fix, CodeActionKind::QUICKFIX, unused.edits))
+        .collect();
+    let skeleton = fi.and_then(|fi| doc_skeleton(&root, fi, &line_index, requested));
+    if let Some((name, edit)) = skeleton {
+        let title = format!("Generate doc comment for `{name}`");
+        actions.push(action(title, CodeActionKind::REFACTOR, vec![edit]));
+    }
+    actions
+}
+/// The name of the undocumented binding whose attrpath is within `requested`, along with the
+/// edit inserting a skeleton of its doc comment above it.
+///
+/// Bindings sharing their line with another, such as `b` of `{ a = 1; b = 2; }`, are skipped
+/// since their doc comments would also be.
+fn doc_skeleton(
+    root: &SyntaxNode,
+    fi: &FileIndex,
+    line_index: &LineIndex,
+    requested: TextRange,
+) -> Option<(String, (TextRange, String))> {
+    // A cursor between whitespace and a name is on the name.
+    let element = if requested.is_empty() {
+        root.token_at_offset(requested.start())
+            .find(|token| token.kind() != SyntaxKind::TOKEN_WHITESPACE)?
+            .into()
+    } else {
+        root.covering_element(requested)
+    };
+    let apv = element.ancestors().find_map(ast::AttrpathValue::cast)?;
+    if !apv
+        .attrpath()?
+        .syntax()
+        .text_range()
+        .contains_range(requested)
+    {
+        return None;
+    }
+    let offset = apv.syntax().text_range().start();
+    let start = line_index.position(offset.into(), Encoding::Utf8);
+    let path = fi.path(start.line, start.char)?;
+    if fi.range(path)?.start() != start || fi.path_doc(path).is_some() {
+        return None;
+    }
+    let indent = line_index.line(start.line)?.get(..start.char)?;
+    if !indent.trim().is_empty() {
+        return None;
+    }
+    let name = path.name()?.to_owned();
+    let text = doc_comment(&name, fi.lambda(path), indent);
+    Some((name, (TextRange::empty(offset), text)))
+}
+/// A doc comment in the style of RFC 145 for the binding `name`, with placeholders for the
+/// summary, each argument of the lambda if it's a function, and its type.
+fn doc_comment(name: &str, lambda: Option<&Lambda>, indent: &str) -> String {
+    let mut lines = vec![format!("A summary of `{name}`.")];
+    let args: Vec<_> = lambda
+        .iter()
+        .flat_map(|lambda| &lambda.params)
+        .flat_map(|param| match param {
+            Param::Ident(ident) => vec![(ident.as_str(), None)],
+            Param::Pattern { formals, .. } => formals
+                .iter()
+                .map(|formal| (formal.name.as_str(), formal.default.as_deref()))
+                .collect(),
+        })
+        .collect();
+    if !args.is_empty() {
+        lines.extend(["".into(), "# Arguments".into()]);
+        for (arg, default) in args {
+            let mut description = format!(": Description of `{arg}`.");
+            if let Some(default) = default {
+                let _ = write!(description, " Defaults to `{default}`.");
+            }
+            lines.extend(["".into(), format!("`{arg}`"), description]);
+        }
+    }
+    lines.extend([
+        "".into(),
+        "# Type".into(),
+        "".into(),
+        "```".into(),
+        format!("{name} :: {}", type_signature(lambda)),
+        "```".into(),
+    ]);
+    let mut comment = String::from("/**\n");
+    for line in lines {
+        if line.is_empty() {
+            comment.push('\n');
+        } else {
+            let _ = writeln!(comment, "{indent}  {line}");
+        }
+    }
+    let _ = write!(comment, "{indent}*/\n{indent}");
+    comment
+}
+/// A placeholder type of a binding, with a type variable of each plain param and the result, and
+/// `AttrSet` of each pattern. Such as `AttrSet -> a -> b` of `{ x }: y: ...`.
+fn type_signature(lambda: Option<&Lambda>) -> String {
+    let mut vars = ('a'..='z').map(String::from);
+    let mut types: Vec<String> = lambda
+        .iter()
+        .flat_map(|lambda| &lambda.params)
+        .map(|param| match param {
+            Param::Ident(_) => vars.next().unwrap_or_else(|| "a".into()),
+            Param::Pattern { .. } => "AttrSet".into(),
+        })
+        .collect();
+    types.push(vars.next().unwrap_or_else(|| "a".into()));
+    types.join(" -> ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The titles of the code actions of `kind` at `offset` of `src`, along with `src` once
+    /// applied.
+    fn actions(src: &str, offset: usize, kind: CodeActionKind) -> Vec<(String, String)> {
+        let uri = Url::parse("file:///test.nix").unwrap();
+        let line_index = LineIndex::new(src);
+        let fi = FileIndex::new(src);
+        code_actions(&uri, src, Some(&fi), offset, offset, Encoding::Utf16)
+            .into_iter()
+            .filter_map(|action| {
+                let CodeActionOrCommand::CodeAction(action) = action else {
+                    panic!("expected a code action");
+                };
+                (action.kind.clone()? == kind).then_some(action)
+            })
+            .map(|action| {
+                let mut edits = action.edit.unwrap().changes.unwrap().remove(&uri).unwrap();
+                edits.sort_by_key(|edit| std::cmp::Reverse(edit.range.start));
+                let mut applied = src.to_owned();
+                for edit in edits {
+                    let offset = |position: Position| {
+                        let pos = (position.line as usize, position.character as usize).into();
+                        line_index.offset(pos, Encoding::Utf16)
+                    };
+                    applied.replace_range(
+                        offset(edit.range.start)..offset(edit.range.end),
+                        &edit.new_text,
+                    );
+                }
+                (action.title, applied)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn remove_unused() {
+        let src = "{ pkgs, ... }:\nlet\n  a = \"ü\";\n  b = 2;\nin b";
+        assert_eq!(
+            actions(src, 2, CodeActionKind::QUICKFIX),
+            [(
+                "Remove unused argument `pkgs`".to_owned(),
+                "{ ... }:\nlet\n  a = \"ü\";\n  b = 2;\nin b".to_owned()
+            )]
+        );
+        assert_eq!(
+            actions(src, 22, CodeActionKind::QUICKFIX),
+            [(
+                "Remove unused `let` binding `a`".to_owned(),
+                "{ pkgs, ... }:\nlet\n  b = 2;\nin b".to_owned()
+            )]
+        );
+        assert_eq!(actions(src, 34, CodeActionKind::QUICKFIX), []);
+    }
+    #[test]
+    fn doc_skeleton() {
+        let src = "{\n  /** Documented. */\n  a = 1;\n  f = { x, y ? 1, ... }: z: x;\n  b = { c = 1; };\n}";
+        assert_eq!(actions(src, 26, CodeActionKind::REFACTOR), []);
+        assert_eq!(
+            actions(src, 34, CodeActionKind::REFACTOR),
+            [(
+                "Generate doc comment for `f`".to_owned(),
+                r#"{
+  /** Documented. */
+  a = 1;
+  /**
+    A summary of `f`.
+
+    # Arguments
+
+    `x`
+    : Description of `x`.
+
+    `y`
+    : Description of `y`. Defaults to `1`.
+
+    `z`
+    : Description of `z`.
+
+    # Type
+
+    ```
+    f :: AttrSet -> a -> b
+    ```
+  */
+  f = { x, y ? 1, ... }: z: x;
+  b = { c = 1; };
+}"#
+                .to_owned()
+            )]
+        );
+        // Only bindings alone on their line, and only on their attrpath.
+        assert_eq!(actions(src, 73, CodeActionKind::REFACTOR), []);
+        assert_eq!(actions(src, 71, CodeActionKind::REFACTOR), []);
+        assert_eq!(
+            actions(src, 65, CodeActionKind::REFACTOR),
+            [(
+                "Generate doc comment for `b`".to_owned(),
+                r#"{
+  /** Documented. */
+  a = 1;
+  f = { x, y ? 1, ... }: z: x;
+  /**
+    A summary of `b`.
+
+    # Type
+
+    ```
+    b :: a
+    ```
+  */
+  b = { c = 1; };
+}"#
+                .to_owned()
+            )]
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use super::hover::binding_idents;
+use crate::{
+    builtins::{self, Builtin},
+    index::{AstPath, DeclaredOption, TancIndex},
+};
+use rnix::{ast, TextSize};
+use rowan::ast::AstNode;
+use tower_lsp::lsp_types::{
+    CompletionItem, CompletionItemKind, Documentation, MarkupContent, MarkupKind,
+};
+
+/// Complete the names of the options beneath the attrpath being written at `offset` in `src`,
+/// such as `services.nginx.` within a `config` attrset or `config.services.nginx.` within an
+/// expression, or the names of builtins after `builtins.`.
+pub fn completion(index: &TancIndex, src: &str, offset: usize) -> Option<Vec<CompletionItem>> {
+    let before = src.get(..offset)?;
+    let chain_len: usize = before
+        .chars()
+        .rev()
+        .take_while(|&c| is_attr_char(c) || c == '.')
+        .map(char::len_utf8)
+        .sum();
+    let chain_start = offset - chain_len;
+    let mut attrs: Vec<&str> = before[chain_start..].split('.').collect();
+    // The attr being written is filtered by the client.
+    attrs.pop();
+    if attrs.iter().any(|attr| attr.is_empty()) {
+        return None;
+    }
+    if attrs == ["builtins"] {
+        return Some(builtins::all().iter().map(builtin_item).collect());
+    }
+    let option_path: AstPath = match binding_scopes(src, chain_start) {
+        Some(mut idents) => {
+            idents.extend(attrs.iter().map(|attr| attr.to_string()));
+            match idents.split_first() {
+                Some((config, rest)) if config == "config" => {
+                    rest.iter().map(String::as_str).collect()
+                },
+                // Options are being declared rather than set.
+                _ if idents.iter().any(|ident| ident == "options") => return None,
+                // A shorthand module, which sets options without a `config` attr.
+                _ => idents.iter().map(String::as_str).collect(),
+            }
+        },
+        None => match attrs.split_first() {
+            Some((&"config", rest)) => rest.iter().copied().collect(),
+            _ => return None,
+        },
+    };
+    let tree = index.option_tree();
+    let items = tree
+        .children(&option_path)
+        .map(|(seg, declared)| match declared {
+            Some(declared) => option_item(seg.to_string(), declared),
+            None => CompletionItem {
+                label: seg.to_string(),
+                kind: Some(CompletionItemKind::MODULE),
+                ..Default::default()
+            },
+        })
+        .collect();
+    Some(items)
+}
+fn is_attr_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '\'' | '-')
+}
+/// The idents of the attrsets enclosing a binding starting at `offset`, `None` if `offset` is
+/// not where a binding could be or is within a `let` block.
+///
+/// The attrpath being written rarely parses, so a complete binding is inserted before it.
+fn binding_scopes(src: &str, offset: usize) -> Option<Vec<String>> {
+    const PLACEHOLDER: &str = "__tanc_completion";
+    let patched = format!("{}{PLACEHOLDER} = null;{}", &src[..offset], &src[offset..]);
+    let root = rnix::Root::parse(&patched).syntax();
+    let token = root
+        .token_at_offset(TextSize::try_from(offset).ok()?)
+        .right_biased()?;
+    if token.text() != PLACEHOLDER {
+        return None;
+    }
+    let attrpath = token.parent()?.parent().and_then(ast::Attrpath::cast)?;
+    let apv = attrpath
+        .syntax()
+        .parent()
+        .and_then(ast::AttrpathValue::cast)?;
+    binding_idents(&apv)
+}
+/// A completion of an option, detailing its type and default.
+fn option_item(label: String, declared: &DeclaredOption) -> CompletionItem {
+    let option = &declared.option;
+    let single_line = |value: &Option<String>| {
+        let value = value.as_deref()?.trim();
+        Some(if value.contains('\n') { "…" } else { value }.to_owned())
+    };
+    let detail = match (single_line(&option.ty), single_line(&option.default)) {
+        (Some(ty), Some(default)) => Some(format!("{ty} (default: {default})")),
+        (Some(ty), None) => Some(ty),
+        (None, Some(default)) => Some(format!("default: {default}")),
+        (None, None) => None,
+    };
+    CompletionItem {
+        label,
+        kind: Some(CompletionItemKind::PROPERTY),
+        detail,
+        documentation: option.description.as_ref().map(|description| {
+            Documentation::MarkupContent(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: description.trim().to_owned(),
+            })
+        }),
+        ..Default::default()
+    }
+}
+/// A completion of a builtin, detailing its type.
+fn builtin_item(builtin: &Builtin) -> CompletionItem {
+    CompletionItem {
+        label: builtin.name.clone(),
+        kind: Some(if builtin.arity == 0 {
+            CompletionItemKind::CONSTANT
+        } else {
+            CompletionItemKind::FUNCTION
+        }),
+        detail: builtin.ty.clone(),
+        documentation: Some(Documentation::MarkupContent(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: builtin.description.clone(),
+        })),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MODULE: &str = r#"{ lib, ... }: {
+  options.services.foo = {
+    enable = lib.mkEnableOption "the foo service";
+    settings.port = lib.mkOption {
+      type = lib.types.port;
+      default = 8080;
+    };
+  };
+}"#;
+
+    /// The labels and details of completing at the end of `src`, with the module declaring
+    /// `services.foo` indexed.
+    fn complete(src: &str) -> Option<Vec<(String, Option<String>)>> {
+        let mut index = TancIndex::default();
+        index.insert("foo.nix", MODULE);
+        let items = completion(&index, src, src.len())?;
+        Some(
+            items
+                .into_iter()
+                .map(|item| (item.label, item.detail))
+                .collect(),
+        )
+    }
+    fn labels(src: &str) -> Vec<String> {
+        complete(src)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(label, _)| label)
+            .collect()
+    }
+
+    #[test]
+    fn config_bindings() {
+        let src = "{ config, lib, ... }: {\n  config = lib.mkIf true {\n    services.foo.";
+        assert_eq!(
+            complete(src).unwrap(),
+            vec![
+                (
+                    "enable".to_owned(),
+                    Some("types.bool (default: false)".to_owned())
+                ),
+                ("settings".to_owned(), None),
+            ]
+        );
+        assert_eq!(labels("{ config.services.foo.settings.p"), vec!["port"]);
+        // Shorthand modules set options without `config`.
+        assert_eq!(labels("{\n  services."), vec!["foo"]);
+    }
+    #[test]
+    fn config_select() {
+        let src = "{ config, ... }: { x = config.services.foo.";
+        assert_eq!(labels(src), vec!["enable", "settings"]);
+        assert_eq!(labels("{ x = cfg.services."), Vec::<String>::new());
+    }
+    #[test]
+    fn builtins() {
+        let items = complete("{ x = builtins.").unwrap();
+        assert!(items.contains(&(
+            "attrNames".to_owned(),
+            Some("AttrSet -> [String]".to_owned())
+        )));
+        assert_eq!(items.len(), builtins::all().len());
+        assert!(labels("{ config, ... }: builtins.attrN").contains(&"attrNames".to_owned()));
+    }
+    #[test]
+    fn not_options() {
+        assert_eq!(complete("let services.foo."), None);
+        assert_eq!(complete("{ options.services.foo."), None);
+    }
+}
@@ -0,0 +1,189 @@
+use super::hover::{
+    ident_at, idents_at, option_path, resolve_search_path_import, resolve_withs, search_path_at,
+    WithResolution, WithTarget,
+};
+use crate::{
+    index::{PosRange, TancIndex},
+    line_index::{Encoding, LineIndex},
+    search_path::SearchPath,
+};
+use compact_str::CompactString;
+use rnix::TextSize;
+use rowan::ast::AstNode;
+
+/// The binding referred to by the identifier at `offset` in `src`, as the path of the file it's
+/// within and its range. Either the declaration of an option such as that of
+/// `config.services.foo.enable`, a binding of a library such as `lib.strings.concatStrings`, a
+/// binding of a file imported from the search path, an attr of the namespace of a `with`, or a
+/// lambda.
+///
+/// The definition of a search path such as `<nixpkgs>` is the start of the file it resolves to.
+pub fn definition(
+    index: &TancIndex,
+    search_path: &SearchPath,
+    file_path: &str,
+    src: &str,
+    offset: usize,
+) -> Option<(CompactString, PosRange)> {
+    let root = rnix::Root::parse(src).syntax();
+    let offset = TextSize::try_from(offset).ok()?;
+    if let Some((_, lookup)) = search_path_at(&root, offset) {
+        let mut path = search_path.resolve(&lookup)?;
+        if path.is_dir() {
+            path.push("default.nix");
+        }
+        if !path.is_file() {
+            return None;
+        }
+        return Some((path.to_str()?.into(), (0, 0, 0, 0).into()));
+    }
+    let token = ident_at(&root, offset)?;
+    let (idents, is_binding) = idents_at(&token)?;
+    let idents: Vec<&str> = idents.iter().map(String::as_str).collect();
+    if let Some(path) = option_path(&idents, is_binding) {
+        let tree = index.option_tree();
+        if let Some(declared) = tree.get(&path.into_iter().collect()) {
+            let (file, fi) = index.file_entry(&declared.file)?;
+            return Some((file.file_path.clone(), *fi.range(&declared.binding)?));
+        }
+    }
+    match resolve_withs(index, search_path, &root, &token, &idents) {
+        Some(WithResolution::Resolved(_, WithTarget::Indexed(definition))) => {
+            return Some((definition.file.file_path.clone(), *definition.range()?));
+        },
+        Some(WithResolution::Resolved(_, WithTarget::Local(apv))) => {
+            let line_index = LineIndex::new(src);
+            let range = apv.syntax().text_range();
+            let start = line_index.position(range.start().into(), Encoding::Utf8);
+            let end = line_index.position(range.end().into(), Encoding::Utf8);
+            return Some((
+                file_path.into(),
+                (start.line, start.char, end.line, end.char).into(),
+            ));
+        },
+        Some(_) => return None,
+        None => {},
+    }
+    let definition = index
+        .resolve_library(&idents)
+        .or_else(|| resolve_search_path_import(index, search_path, &root, &idents));
+    if let Some(definition) = definition {
+        return Some((definition.file.file_path.clone(), *definition.range()?));
+    }
+    let (file, path, _, _) = index.resolve_lambda(file_path, &idents)?;
+    let (file, fi) = index.file_entry(file)?;
+    Some((file.file_path.clone(), *fi.range(path)?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::library::Library;
+
+    /// The definition of the identifier at `offset` in `src`.
+    fn definition_at(index: &TancIndex, src: &str, offset: usize) -> Option<(String, PosRange)> {
+        let search_path = SearchPath::default();
+        let (file_path, range) = definition(index, &search_path, "/project/main.nix", src, offset)?;
+        Some((file_path.into(), range))
+    }
+
+    #[test]
+    fn definitions() {
+        let mut index = TancIndex::default();
+        index.insert(
+            "/project/foo.nix",
+            "{ lib, ... }: {\n  options.services.foo.enable = lib.mkEnableOption \"foo\";\n}",
+        );
+        let library = Library {
+            root: "/nixpkgs".into(),
+            commit: None,
+        };
+        let trivial = (
+            "/nixpkgs/lib/trivial.nix".into(),
+            "{\n  id = x: x;\n}".into(),
+        );
+        index.insert_library_files(&library, vec![trivial]);
+        let src = "{ config, lib, ... }: {\n  x = config.services.foo.enable;\n  y = lib.id;\n  \
+                   z = let f = x: x; in f;\n}";
+        index.insert("/project/main.nix", src);
+
+        let at = |needle: &str| definition_at(&index, src, src.rfind(needle).unwrap());
+        assert_eq!(
+            at("enable"),
+            Some(("/project/foo.nix".into(), (1, 2, 1, 57).into()))
+        );
+        assert_eq!(
+            at("id"),
+            Some(("/nixpkgs/lib/trivial.nix".into(), (1, 2, 1, 12).into()))
+        );
+        assert_eq!(
+            at("f;"),
+            Some(("/project/main.nix".into(), (3, 10, 3, 19).into()))
+        );
+        assert_eq!(at("config."), None);
+    }
+    #[test]
+    fn withs() {
+        let mut index = TancIndex::default();
+        let library = Library {
+            root: "/nixpkgs".into(),
+            commit: None,
+        };
+        let trivial = (
+            "/nixpkgs/lib/trivial.nix".into(),
+            "{\n  id = x: x;\n}".into(),
+        );
+        index.insert_library_files(&library, vec![trivial]);
+        let src = "{ lib }: let\n  s = { a = 1; };\nin with lib; with s; [ id a b ]";
+        let at = |needle: &str| definition_at(&index, src, src.rfind(needle).unwrap());
+        assert_eq!(
+            at("id"),
+            Some(("/nixpkgs/lib/trivial.nix".into(), (1, 2, 1, 12).into()))
+        );
+        assert_eq!(
+            at("a b"),
+            Some(("/project/main.nix".into(), (1, 8, 1, 14).into()))
+        );
+        assert_eq!(at("b ]"), None);
+    }
+    #[test]
+    fn search_paths() {
+        let root = std::env::temp_dir().join(format!("tanc-definition-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("nixpkgs/pkgs/top-level")).unwrap();
+        std::fs::write(root.join("nixpkgs/default.nix"), "{ }: { }").unwrap();
+        let root = root.canonicalize().unwrap();
+        let nixpkgs = root.join("nixpkgs");
+        let search_path = SearchPath::parse(&format!("nixpkgs={}", nixpkgs.display()));
+        let library = Library {
+            root: nixpkgs.clone(),
+            commit: None,
+        };
+        let all_packages = (
+            nixpkgs.join("pkgs/top-level/all-packages.nix"),
+            "{ }: {\n  hello = callPackage ./hello { };\n}".into(),
+        );
+        let mut index = TancIndex::default();
+        index.insert_library_files(&library, vec![all_packages]);
+
+        let src = "{ nixpkgs ? import <nixpkgs> { } }: nixpkgs.hello";
+        let at = |needle: &str| {
+            let offset = src.rfind(needle).unwrap();
+            let (file_path, range) = definition(&index, &search_path, "main.nix", src, offset)?;
+            Some((file_path.to_string(), range))
+        };
+        let file = |path: &str| nixpkgs.join(path).to_str().unwrap().to_owned();
+        assert_eq!(
+            at("hello"),
+            Some((
+                file("pkgs/top-level/all-packages.nix"),
+                (1, 2, 1, 34).into()
+            ))
+        );
+        assert_eq!(
+            at("<nixpkgs>"),
+            Some((file("default.nix"), (0, 0, 0, 0).into()))
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
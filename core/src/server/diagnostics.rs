@@ -0,0 +1,399 @@
+//! Lints of open documents, published to the client as diagnostics.
+use super::{
+    hover::{binding_idents, resolve_with},
+    unused::unused,
+};
+use crate::{
+    index::{AstPath, OptionTree, TancIndex},
+    line_index::{Encoding, LineIndex},
+    scope::{DefKind, Resolution, Scopes},
+    search_path::SearchPath,
+};
+use rnix::{ast, SyntaxNode, TextRange};
+use rowan::ast::AstNode;
+use tower_lsp::lsp_types::{
+    Diagnostic, DiagnosticSeverity, DiagnosticTag, NumberOrString, Position, Range,
+};
+
+/// Every diagnostic of `src`, with positions in the given encoding. Identifiers bound lexically
+/// which a `with` also provides are only reported if `with_shadowing` is true.
+pub fn diagnostics(
+    index: &TancIndex,
+    search_path: &SearchPath,
+    src: &str,
+    encoding: Encoding,
+    with_shadowing: bool,
+) -> Vec<Diagnostic> {
+    let root = rnix::Root::parse(src).syntax();
+    let line_index = LineIndex::new(src);
+    let range = |range: TextRange| {
+        let position = |offset: usize| {
+            let pos = line_index.position(offset, encoding);
+            Position::new(pos.line as u32, pos.char as u32)
+        };
+        Range::new(position(range.start().into()), position(range.end().into()))
+    };
+    let mut diagnostics: Vec<Diagnostic> = undeclared_options(&index.option_tree(), &root)
+        .into_iter()
+        .map(|undeclared| {
+            let path = undeclared.path.join(".");
+            let mut message = format!("no option `{path}` is declared");
+            if let Some(suggestion) = &undeclared.suggestion {
+                let mut suggested = undeclared.path.clone();
+                suggested.pop();
+                suggested.push(suggestion.clone());
+                message.push_str(&format!(", did you mean `{}`?", suggested.join(".")));
+            }
+            Diagnostic {
+                range: range(undeclared.range),
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(NumberOrString::String("undeclared-option".into())),
+                source: Some("tanc".into()),
+                message,
+                ..Default::default()
+            }
+        })
+        .collect();
+    let scopes = Scopes::analyze(&root);
+    diagnostics.extend(unused(&scopes).into_iter().map(|unused| Diagnostic {
+        range: range(unused.range),
+        severity: Some(DiagnosticSeverity::WARNING),
+        code: Some(NumberOrString::String(unused.kind.code().into())),
+        source: Some("tanc".into()),
+        message: unused.message(),
+        tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+        ..Default::default()
+    }));
+    if with_shadowing {
+        let shadowed = shadowed_withs(index, search_path, &root, &scopes);
+        diagnostics.extend(shadowed.into_iter().map(|shadowed| {
+            let bound_by = match shadowed.kind {
+                DefKind::Let => "a `let` binding",
+                DefKind::Rec => "an attr of a `rec` attrset",
+                DefKind::Param | DefKind::Formal | DefKind::PatBind => "a lambda argument",
+            };
+            Diagnostic {
+                range: range(shadowed.range),
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(NumberOrString::String("with-shadowing".into())),
+                source: Some("tanc".into()),
+                message: format!(
+                    "`{0}` refers to {bound_by} rather than `{0}` of `with {1}`",
+                    shadowed.name, shadowed.namespace
+                ),
+                ..Default::default()
+            }
+        }));
+    }
+    diagnostics
+}
+/// A reference to a lexical binding within a `with` whose namespace also provides the name,
+/// such as `hello` of `let hello = 1; in with pkgs; hello`, which isn't `pkgs.hello`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ShadowedWith {
+    range: TextRange,
+    name: String,
+    kind: DefKind,
+    /// The namespace of the `with`, as written.
+    namespace: String,
+}
+/// Find the references of `root`, with the given scopes, to lexical bindings which the namespace of a `with` between
+/// them also provides. Namespaces which aren't indexed, and so whose attrs are unknown, are
+/// assumed not to.
+fn shadowed_withs(
+    index: &TancIndex,
+    search_path: &SearchPath,
+    root: &SyntaxNode,
+    scopes: &Scopes,
+) -> Vec<ShadowedWith> {
+    scopes
+        .refs()
+        .iter()
+        .filter_map(|reference| {
+            let Resolution::Def(def) = reference.resolution else {
+                return None;
+            };
+            let with = reference
+                .withs
+                .iter()
+                .map(|&with| &scopes.withs()[with])
+                .find(|with| {
+                    resolve_with(index, search_path, root, scopes, with, &[&reference.name])
+                        .is_some()
+                })?;
+            let namespace = with.namespace()?.syntax().text().to_string();
+            Some(ShadowedWith {
+                range: reference.range,
+                name: reference.name.clone(),
+                kind: scopes.defs()[def].kind,
+                namespace: namespace.split_whitespace().collect::<Vec<_>>().join(" "),
+            })
+        })
+        .collect()
+}
+/// An attr of a module setting an option which no indexed module declares.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct UndeclaredOption {
+    /// The range of the first undeclared attr.
+    range: TextRange,
+    /// The option path up to and including the first undeclared attr.
+    path: Vec<String>,
+    /// The declared sibling closest to the undeclared attr, if any is close.
+    suggestion: Option<String>,
+}
+/// Find the options set by the module of `root` which are not declared in the tree, either in its
+/// `config` attr or at the top of a shorthand module without `config` or `options` attrs.
+///
+/// Nothing is reported unless the tree is complete, since options of modules which aren't
+/// indexed, such as those of nixpkgs, would otherwise all be undeclared. An attr is then only
+/// undeclared if its parent is an attrset of declared options, such as `ngnix` of
+/// `services.ngnix`, rather than the value of an option such as of `attrsOf submodule`.
+fn undeclared_options(tree: &OptionTree, root: &SyntaxNode) -> Vec<UndeclaredOption> {
+    if !tree.is_complete() {
+        return Vec::new();
+    }
+    let bindings: Vec<_> = root
+        .descendants()
+        .filter_map(ast::AttrpathValue::cast)
+        .filter_map(|apv| Some((binding_idents(&apv)?, apv)))
+        .collect();
+    let first_attr = |apv: &ast::AttrpathValue| {
+        let attr = apv.attrpath()?.attrs().next()?;
+        Some(attr.syntax().text().to_string())
+    };
+    let shorthand = !bindings.iter().any(|(scope, apv)| {
+        scope.is_empty() && matches!(first_attr(apv).as_deref(), Some("config" | "options"))
+    });
+    let mut undeclared = Vec::new();
+    for (scope, apv) in bindings {
+        let Some(attrpath) = apv.attrpath() else {
+            continue;
+        };
+        // The idents of the binding, stopping at the first dynamic attr.
+        let attrs: Vec<(String, TextRange)> = attrpath
+            .attrs()
+            .map_while(|attr| match attr {
+                ast::Attr::Ident(ident) => Some((
+                    ident.syntax().text().to_string(),
+                    ident.syntax().text_range(),
+                )),
+                _ => None,
+            })
+            .collect();
+        let idents: Vec<&str> = scope
+            .iter()
+            .map(String::as_str)
+            .chain(attrs.iter().map(|(ident, _)| ident.as_str()))
+            .collect();
+        let option_start = match idents.first() {
+            _ if shorthand => 0,
+            Some(&"config") => 1,
+            _ => continue,
+        };
+        for i in option_start..idents.len() {
+            let path: AstPath = idents[option_start..=i].iter().copied().collect();
+            if tree.get(&path).is_some() {
+                // Beneath an option is its value, which is not made of options.
+                break;
+            }
+            if tree.children(&path).next().is_some() {
+                continue;
+            }
+            if i == option_start {
+                break;
+            }
+            // Attrs of the scope are reported by the binding of the scope.
+            let Some((ident, range)) = i.checked_sub(scope.len()).and_then(|i| attrs.get(i))
+            else {
+                break;
+            };
+            let parent = path.parent().unwrap_or_default();
+            let suggestion = tree
+                .children(&parent)
+                .map(|(seg, _)| seg.to_string())
+                .map(|sibling| (edit_distance(ident, &sibling), sibling))
+                .filter(|(distance, _)| *distance <= (ident.chars().count() / 3).max(1))
+                .min_by_key(|(distance, _)| *distance)
+                .map(|(_, sibling)| sibling);
+            undeclared.push(UndeclaredOption {
+                range: *range,
+                path: idents[option_start..=i]
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect(),
+                suggestion,
+            });
+            break;
+        }
+    }
+    undeclared
+}
+/// The number of insertions, deletions, substitutions and transpositions of adjacent chars to
+/// turn `a` into `b`, such that typos like `ngnix` are close to `nginx`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MODULE: &str = r#"{ lib, ... }: {
+  options.services = {
+    nginx.enable = lib.mkEnableOption "nginx";
+    nginx.virtualHosts = lib.mkOption { };
+    foo.enable = lib.mkEnableOption "foo";
+  };
+}"#;
+
+    /// An index of nixpkgs whose only NixOS module is [`MODULE`], such that its options are
+    /// every option.
+    fn nixpkgs() -> TancIndex {
+        let mut index = TancIndex::default();
+        index.insert_library("/nixpkgs", None);
+        index.insert("/nixpkgs/nixos/modules/module-list.nix", "[ ./module.nix ]");
+        index.insert("/nixpkgs/nixos/modules/module.nix", MODULE);
+        index
+    }
+    /// The messages of the diagnostics of `src`, along with the text of their ranges, with the
+    /// module declaring `services.nginx` indexed as a NixOS module of nixpkgs.
+    fn messages(src: &str) -> Vec<(String, String)> {
+        messages_of(&nixpkgs(), src)
+    }
+    fn messages_of(index: &TancIndex, src: &str) -> Vec<(String, String)> {
+        let line_index = LineIndex::new(src);
+        diagnostics(index, &SearchPath::default(), src, Encoding::Utf8, true)
+            .into_iter()
+            .map(|diagnostic| {
+                let offset = |position: Position| {
+                    let pos = (position.line as usize, position.character as usize).into();
+                    line_index.offset(pos, Encoding::Utf8)
+                };
+                let text = &src[offset(diagnostic.range.start)..offset(diagnostic.range.end)];
+                (diagnostic.message, text.to_owned())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn config_typos() {
+        let src = r#"{ lib, ... }: {
+  imports = [ ];
+  config = lib.mkIf true {
+    services.ngnix.enable = true;
+    services.nginx = {
+      enabled = true;
+      virtualHosts.x.anything = 1;
+    };
+    services.unrelated = 1;
+    networking.hostName = "x";
+  };
+}"#;
+        assert_eq!(
+            messages(src),
+            vec![
+                (
+                    "no option `services.ngnix` is declared, did you mean `services.nginx`?"
+                        .to_owned(),
+                    "ngnix".to_owned()
+                ),
+                (
+                    "no option `services.nginx.enabled` is declared, did you mean \
+                     `services.nginx.enable`?"
+                        .to_owned(),
+                    "enabled".to_owned()
+                ),
+                (
+                    "no option `services.unrelated` is declared".to_owned(),
+                    "unrelated".to_owned()
+                ),
+            ]
+        );
+    }
+    #[test]
+    fn incomplete_options() {
+        // Without the NixOS modules of nixpkgs, options such as `services.unrelated` may be
+        // declared by modules which aren't indexed.
+        let mut index = TancIndex::default();
+        index.insert("/project/module.nix", MODULE);
+        let src = "{\n  services.ngnix.enable = true;\n  services.unrelated = 1;\n}";
+        assert_eq!(messages_of(&index, src), Vec::new());
+        assert_eq!(messages(src).len(), 2);
+    }
+    #[test]
+    fn shorthand_module() {
+        let src = "{\n  imports = [ ];\n  services.fooo.enable = true;\n}";
+        assert_eq!(
+            messages(src),
+            vec![(
+                "no option `services.fooo` is declared, did you mean `services.foo`?".to_owned(),
+                "fooo".to_owned()
+            )]
+        );
+        // Modules declaring options only check their `config`.
+        assert_eq!(messages(MODULE), Vec::new());
+        let src = "let services.ngnix = 1; in services";
+        assert_eq!(messages(src), Vec::new());
+    }
+    #[test]
+    fn with_shadowing() {
+        let src = "let\n  s = { a = 1; b = 2; };\n  a = 3;\nin { c }: with s; [ a b c (with { c = 1; }; c) ]";
+        assert_eq!(
+            messages(src),
+            vec![
+                (
+                    "`a` refers to a `let` binding rather than `a` of `with s`".to_owned(),
+                    "a".to_owned()
+                ),
+                (
+                    "`c` refers to a lambda argument rather than `c` of `with { c = 1; }`"
+                        .to_owned(),
+                    "c".to_owned()
+                ),
+            ]
+        );
+        let index = TancIndex::default();
+        let diagnostics = diagnostics(&index, &SearchPath::default(), src, Encoding::Utf8, false);
+        assert_eq!(diagnostics, Vec::new());
+        // Namespaces which aren't indexed are unknown.
+        let src = "{ pkgs, hello }: with pkgs; hello";
+        assert_eq!(messages(src), Vec::new());
+    }
+    #[test]
+    fn unused_bindings() {
+        let src = "{ pkgs, ... }: let\n  a = 1;\n  inherit (pkgs) hello;\nin x: a";
+        assert_eq!(
+            messages(src),
+            vec![
+                ("unused inherit `hello`".to_owned(), "hello".to_owned()),
+                ("unused argument `x`".to_owned(), "x".to_owned()),
+            ]
+        );
+    }
+    #[test]
+    fn transpositions() {
+        assert_eq!(edit_distance("ngnix", "nginx"), 1);
+        assert_eq!(edit_distance("enabled", "enable"), 1);
+        assert_eq!(edit_distance("foo", "bar"), 3);
+        assert_eq!(edit_distance("", "ab"), 2);
+    }
+}
@@ -0,0 +1,711 @@
+use super::signature_help::{callee_idents, resolve_callee, Callee};
+use crate::{
+    builtins::{self, Builtin},
+    index::{Doc, Lambda, NixOption, TancIndex},
+    library::Definition,
+    line_index::{Encoding, LineIndex},
+    scope::{Resolution, Scopes},
+    search_path::SearchPath,
+};
+use rnix::{
+    ast::{self, HasEntry},
+    SyntaxKind, SyntaxNode, SyntaxToken, TextSize,
+};
+use rowan::ast::AstNode;
+use tower_lsp::lsp_types::{Hover, HoverContents, MarkupContent, MarkupKind, Position, Range};
+
+/// Describe the identifier at `offset` in `src`, such as the option of `config.services.foo.enable`,
+/// a binding of a library such as `lib.strings.concatStrings`, a builtin such as `map`, an attr of
+/// the namespace of a `with`, the doc and signature of a function, or the path a search path such
+/// as `<nixpkgs>` resolves to.
+pub fn hover(
+    index: &TancIndex,
+    search_path: &SearchPath,
+    file_path: &str,
+    src: &str,
+    offset: usize,
+    encoding: Encoding,
+) -> Option<Hover> {
+    let root = rnix::Root::parse(src).syntax();
+    let line_index = LineIndex::new(src);
+    let offset = TextSize::try_from(offset).ok()?;
+    let (range, value) = match search_path_at(&root, offset) {
+        Some((token, lookup)) => {
+            let path = search_path.resolve(&lookup)?;
+            let value = format!("```nix\n<{lookup}>\n```\n\n`{}`", path.display());
+            (token.text_range(), value)
+        },
+        None => {
+            let token = ident_at(&root, offset)?;
+            let value = describe_ident(index, search_path, file_path, &line_index, &root, &token)?;
+            (token.text_range(), value)
+        },
+    };
+    let position = |offset: TextSize| {
+        let pos = line_index.position(offset.into(), encoding);
+        Position::new(pos.line as u32, pos.char as u32)
+    };
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: Some(Range::new(position(range.start()), position(range.end()))),
+    })
+}
+/// Render what the given identifier token refers to.
+fn describe_ident(
+    index: &TancIndex,
+    search_path: &SearchPath,
+    file_path: &str,
+    line_index: &LineIndex,
+    root: &SyntaxNode,
+    token: &SyntaxToken,
+) -> Option<String> {
+    let (idents, is_binding) = idents_at(token)?;
+    let idents: Vec<&str> = idents.iter().map(String::as_str).collect();
+    if let Some(path) = option_path(&idents, is_binding) {
+        if let Some(declared) = index.option_tree().get(&path.into_iter().collect()) {
+            return Some(render_option(
+                &option_name(&idents, is_binding),
+                &declared.option,
+            ));
+        }
+    }
+    let name = idents.join(".");
+    // Libraries re-export builtins without docs, such as `inherit (builtins) attrNames;`.
+    if let ["builtins", _] = idents.as_slice() {
+        if let Some(builtin) = builtins::resolve(&idents) {
+            return Some(render_builtin(&name, builtin));
+        }
+    }
+    match resolve_withs(index, search_path, root, token, &idents) {
+        Some(WithResolution::Resolved(namespace, target)) => {
+            let name = format!("{namespace}.{name}");
+            return Some(match target {
+                WithTarget::Indexed(definition) => {
+                    render_binding(&name, definition.lambda(), definition.doc())
+                },
+                WithTarget::Local(apv) => {
+                    let fi = index.file(file_path);
+                    let start = apv.syntax().text_range().start().into();
+                    let start = line_index.position(start, Encoding::Utf8);
+                    let path = fi.and_then(|fi| fi.path(start.line, start.char));
+                    let (lambda, doc) = match (fi, path) {
+                        (Some(fi), Some(path)) => (fi.lambda(path), fi.path_doc(path)),
+                        _ => (None, None),
+                    };
+                    render_binding(&name, lambda, doc)
+                },
+                WithTarget::Builtin(builtin) => render_builtin(&name, builtin),
+            });
+        },
+        Some(WithResolution::Unresolved(namespaces)) => {
+            let namespaces: Vec<String> = namespaces
+                .iter()
+                .map(|namespace| format!("`with {namespace}`"))
+                .collect();
+            return Some(format!(
+                "```nix\n{name}\n```\n\nPossibly from {}.",
+                namespaces.join(" or ")
+            ));
+        },
+        None => {},
+    }
+    let definition = index
+        .resolve_library(&idents)
+        .or_else(|| resolve_search_path_import(index, search_path, root, &idents));
+    let (lambda, doc) = match definition {
+        Some(definition) => (definition.lambda(), definition.doc()),
+        None => match resolve_callee(index, file_path, &idents)? {
+            Callee::Lambda(lambda, doc) => (Some(lambda), doc),
+            Callee::Builtin(_) if is_binding => return None,
+            Callee::Builtin(builtin) => return Some(render_builtin(&name, builtin)),
+        },
+    };
+    Some(render_binding(&name, lambda, doc))
+}
+/// The identifier token touching `offset`, preferring the one after it.
+pub(super) fn ident_at(root: &SyntaxNode, offset: TextSize) -> Option<SyntaxToken> {
+    root.token_at_offset(offset)
+        .filter(|token| token.kind() == SyntaxKind::TOKEN_IDENT)
+        .last()
+}
+/// The idents up to and including the given token, such as `["config", "services"]` for
+/// `services` of `config.services.foo`, and whether they're the attrpath of a binding which may
+/// set an option, ie one not of a `let` block.
+pub(super) fn idents_at(token: &SyntaxToken) -> Option<(Vec<String>, bool)> {
+    let ident = token.parent()?;
+    let Some(attrpath) = ident.parent().and_then(ast::Attrpath::cast) else {
+        return Some((vec![token.text().to_string()], false));
+    };
+    let mut attrs = Vec::new();
+    for attr in attrpath.attrs() {
+        let ast::Attr::Ident(attr_ident) = attr else {
+            return None;
+        };
+        attrs.push(attr_ident.syntax().text().to_string());
+        if attr_ident.syntax() == &ident {
+            break;
+        }
+    }
+    let parent = attrpath.syntax().parent()?;
+    if let Some(select) = ast::Select::cast(parent.clone()) {
+        let mut idents = callee_idents(&select.expr()?)?;
+        idents.extend(attrs);
+        return Some((idents, false));
+    }
+    match binding_idents(&ast::AttrpathValue::cast(parent)?) {
+        Some(mut idents) => {
+            idents.extend(attrs);
+            Some((idents, true))
+        },
+        None => Some((attrs, false)),
+    }
+}
+/// The idents of the attrsets a binding is within, such as `["config", "services"]` for `foo` of
+/// `config = mkIf cfg.enable { services.foo = { }; };`. Lambdas and applications are passed
+/// through, while bindings within `let` blocks have none.
+pub(super) fn binding_idents(apv: &ast::AttrpathValue) -> Option<Vec<String>> {
+    let mut scopes = Vec::new();
+    let mut apv = apv.clone();
+    loop {
+        if apv.syntax().parent()?.kind() == SyntaxKind::NODE_LET_IN {
+            return None;
+        }
+        let Some(outer) = apv
+            .syntax()
+            .ancestors()
+            .skip(1)
+            .find_map(ast::AttrpathValue::cast)
+        else {
+            break;
+        };
+        scopes.push(attr_idents(&outer.attrpath()?)?);
+        apv = outer;
+    }
+    Some(scopes.into_iter().rev().flatten().collect())
+}
+/// The search path token touching `offset`, such as `<nixpkgs/lib>`, along with its lookup
+/// such as `nixpkgs/lib`.
+pub(super) fn search_path_at(root: &SyntaxNode, offset: TextSize) -> Option<(SyntaxToken, String)> {
+    root.token_at_offset(offset).find_map(|token| {
+        let lookup = search_path_lookup(&token)?;
+        Some((token, lookup))
+    })
+}
+fn search_path_lookup(token: &SyntaxToken) -> Option<String> {
+    if token.kind() != SyntaxKind::TOKEN_PATH {
+        return None;
+    }
+    let lookup = token.text().strip_prefix('<')?.strip_suffix('>')?;
+    Some(lookup.to_owned())
+}
+/// Resolve idents whose first is bound to an import from the search path, such as `pkgs.hello`
+/// of `pkgs = import <nixpkgs> { };` or `{ pkgs ? import <nixpkgs> { } }:`.
+///
+/// NIT: The binding is found anywhere within `root` rather than by scope.
+pub(super) fn resolve_search_path_import<'a>(
+    index: &'a TancIndex,
+    search_path: &SearchPath,
+    root: &SyntaxNode,
+    idents: &[&str],
+) -> Option<Definition<'a>> {
+    let (name, attrs) = idents.split_first()?;
+    let lookup = root.descendants().find_map(|node| {
+        let value = match ast::AttrpathValue::cast(node.clone()) {
+            Some(apv) if attr_idents(&apv.attrpath()?)? == [*name] => apv.value()?,
+            Some(_) => return None,
+            None => {
+                let entry = ast::PatEntry::cast(node)?;
+                if entry.ident()?.syntax().text() != *name {
+                    return None;
+                }
+                entry.default()?
+            },
+        };
+        imported_lookup(value)
+    })?;
+    let path = search_path.resolve(&lookup)?;
+    index.resolve_import(path.to_str()?, attrs)
+}
+/// The lookup of the search path imported by an expression, such as `nixpkgs` of
+/// `import <nixpkgs> { }`.
+fn imported_lookup(mut expr: ast::Expr) -> Option<String> {
+    loop {
+        expr = match expr {
+            ast::Expr::Paren(paren) => paren.expr()?,
+            ast::Expr::Apply(apply) => match apply.lambda()? {
+                ast::Expr::Ident(ident) if ident.syntax().text() == "import" => {
+                    let ast::Expr::Path(path) = apply.argument()? else {
+                        return None;
+                    };
+                    let token = path.syntax().first_token()?;
+                    return search_path_lookup(&token);
+                },
+                lambda => lambda,
+            },
+            _ => return None,
+        };
+    }
+}
+/// What the attrs of the namespace of a `with` resolve to.
+pub(super) enum WithTarget<'a> {
+    /// A binding of an indexed file, such as `hello` of `with pkgs;`.
+    Indexed(Definition<'a>),
+    /// A binding of an attrset of the source itself, such as `a` of
+    /// `let s = { a = 1; }; in with s;`.
+    Local(ast::AttrpathValue),
+    Builtin(&'static Builtin),
+}
+/// Idents resolved through the namespaces of the `with` expressions they're within.
+pub(super) enum WithResolution<'a> {
+    /// The namespace of the innermost `with` providing the idents, such as `pkgs` of
+    /// `with pkgs;`, along with what they resolve to.
+    Resolved(String, WithTarget<'a>),
+    /// No namespace is known to provide the idents, which are possibly of any of these.
+    Unresolved(Vec<String>),
+}
+/// Resolve idents whose first is not bound lexically, such as `hello` of
+/// `with pkgs; [ hello ]`, through the namespaces of the enclosing `with`s, innermost first.
+/// `None` if the first ident is bound lexically, or isn't within any `with`.
+pub(super) fn resolve_withs<'a>(
+    index: &'a TancIndex,
+    search_path: &SearchPath,
+    root: &SyntaxNode,
+    token: &SyntaxToken,
+    idents: &[&str],
+) -> Option<WithResolution<'a>> {
+    let head = head_ident(token)?;
+    let scopes = Scopes::analyze(root);
+    let reference = scopes.reference(head.text_range())?;
+    if reference.resolution != Resolution::With {
+        return None;
+    }
+    let withs = || reference.withs.iter().map(|&with| &scopes.withs()[with]);
+    for with in withs() {
+        if let Some(target) = resolve_with(index, search_path, root, &scopes, with, idents) {
+            return Some(WithResolution::Resolved(namespace_name(with), target));
+        }
+    }
+    Some(WithResolution::Unresolved(
+        withs().map(namespace_name).collect(),
+    ))
+}
+/// Resolve attrs of the namespace of a `with`, if the namespace is an indexed attrset such as
+/// `pkgs`, `lib.strings` or `import <nixpkgs> { }`, the builtins, or an attrset of the source.
+pub(super) fn resolve_with<'a>(
+    index: &'a TancIndex,
+    search_path: &SearchPath,
+    root: &SyntaxNode,
+    scopes: &Scopes,
+    with: &ast::With,
+    attrs: &[&str],
+) -> Option<WithTarget<'a>> {
+    let namespace = with.namespace()?;
+    if let Some(lookup) = imported_lookup(namespace.clone()) {
+        let path = search_path.resolve(&lookup)?;
+        return index
+            .resolve_import(path.to_str()?, attrs)
+            .map(WithTarget::Indexed);
+    }
+    if let Some(apv) = local_attr(scopes, &namespace, attrs) {
+        return Some(WithTarget::Local(apv));
+    }
+    let namespace_idents = callee_idents(&namespace)?;
+    if namespace_idents == ["builtins"] {
+        return match attrs {
+            [name] => builtins::resolve(&["builtins", name]).map(WithTarget::Builtin),
+            _ => None,
+        };
+    }
+    let mut idents: Vec<&str> = namespace_idents.iter().map(String::as_str).collect();
+    idents.extend(attrs);
+    index
+        .resolve_library(&idents)
+        .or_else(|| resolve_search_path_import(index, search_path, root, &idents))
+        .map(WithTarget::Indexed)
+}
+/// The binding of the given attrs within an attrset of the source, either written in place or
+/// bound to an identifier, such as `a` of `s` for `let s = { a = 1; };`.
+fn local_attr(scopes: &Scopes, expr: &ast::Expr, attrs: &[&str]) -> Option<ast::AttrpathValue> {
+    let mut value = match expr {
+        ast::Expr::Ident(ident) => {
+            let reference = scopes.reference(ident.syntax().text_range())?;
+            let Resolution::Def(def) = reference.resolution else {
+                return None;
+            };
+            scopes.defs()[def].value.clone()?
+        },
+        expr => expr.clone(),
+    };
+    for (i, attr) in attrs.iter().enumerate() {
+        while let ast::Expr::Paren(paren) = value {
+            value = paren.expr()?;
+        }
+        let ast::Expr::AttrSet(attr_set) = value else {
+            return None;
+        };
+        let apv = attr_set.attrpath_values().find(|apv| {
+            let idents = apv.attrpath().and_then(|attrpath| attr_idents(&attrpath));
+            idents.map_or(false, |idents| idents == [*attr])
+        })?;
+        if i + 1 == attrs.len() {
+            return Some(apv);
+        }
+        value = apv.value()?;
+    }
+    None
+}
+/// The identifier expression of the value the given token is an ident of, such as `hello` of
+/// `hello.meta.description`. `None` if the token is a name rather than an expression, such as of
+/// a binding.
+fn head_ident(token: &SyntaxToken) -> Option<SyntaxNode> {
+    let ident = token.parent()?;
+    if ident.kind() != SyntaxKind::NODE_IDENT {
+        return None;
+    }
+    let Some(parent) = ident.parent() else {
+        return Some(ident);
+    };
+    match parent.kind() {
+        SyntaxKind::NODE_ATTRPATH => {
+            let mut expr = ast::Select::cast(parent.parent()?)?.expr()?;
+            loop {
+                expr = match expr {
+                    ast::Expr::Select(select) => select.expr()?,
+                    ast::Expr::Ident(ident) => return Some(ident.syntax().clone()),
+                    _ => return None,
+                };
+            }
+        },
+        SyntaxKind::NODE_IDENT_PARAM | SyntaxKind::NODE_PAT_ENTRY | SyntaxKind::NODE_PAT_BIND => {
+            None
+        },
+        _ => Some(ident),
+    }
+}
+/// The namespace of a `with` as written, such as `pkgs` of `with pkgs;`.
+fn namespace_name(with: &ast::With) -> String {
+    let namespace = with.namespace().map(|n| n.syntax().text().to_string());
+    let namespace = namespace.unwrap_or_default();
+    namespace.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+/// The idents of an attrpath, `None` if any attr is dynamic.
+fn attr_idents(attrpath: &ast::Attrpath) -> Option<Vec<String>> {
+    attrpath
+        .attrs()
+        .map(|attr| match attr {
+            ast::Attr::Ident(ident) => Some(ident.syntax().text().to_string()),
+            _ => None,
+        })
+        .collect()
+}
+/// The path of the option referred to by the given idents, if any. `config.services.foo.enable`
+/// refers to an option, as do bindings declaring options such as `options.services.foo.enable`.
+///
+/// Bindings of neither are assumed to be of a shorthand module, which sets options without a
+/// `config` attr.
+pub(super) fn option_path<'a>(idents: &[&'a str], is_binding: bool) -> Option<Vec<&'a str>> {
+    match idents {
+        ["config", rest @ ..] if !rest.is_empty() => Some(rest.to_vec()),
+        _ if is_binding => {
+            let options = idents.iter().position(|ident| *ident == "options");
+            Some(idents[options.map_or(0, |i| i + 1)..].to_vec())
+        },
+        _ => None,
+    }
+}
+fn option_name(idents: &[&str], is_binding: bool) -> String {
+    option_path(idents, is_binding)
+        .unwrap_or_default()
+        .join(".")
+}
+/// Render an option, with its type, default and example.
+fn render_option(name: &str, option: &NixOption) -> String {
+    let mut md = format!("```nix\n{name}\n```");
+    if let Some(description) = &option.description {
+        md.push_str("\n\n");
+        md.push_str(description.trim());
+    }
+    let fields = [
+        ("Type", &option.ty),
+        ("Default", &option.default),
+        ("Example", &option.example),
+    ];
+    for (label, value) in fields {
+        let Some(value) = value else {
+            continue;
+        };
+        let value = value.trim();
+        if value.contains('\n') {
+            md.push_str(&format!("\n\n**{label}:**\n```nix\n{value}\n```"));
+        } else {
+            md.push_str(&format!("\n\n**{label}:** `{value}`"));
+        }
+    }
+    md
+}
+/// Render a builtin with its args, description, type and the version of Nix introducing it.
+fn render_builtin(name: &str, builtin: &Builtin) -> String {
+    let mut md = format!("```nix\n{name}");
+    for arg in &builtin.args {
+        md.push(' ');
+        md.push_str(arg);
+    }
+    md.push_str("\n```\n\n");
+    md.push_str(&builtin.description);
+    if let Some(ty) = &builtin.ty {
+        md.push_str(&format!("\n\n**Type:** `{ty}`"));
+    }
+    if let Some(since) = &builtin.since {
+        md.push_str(&format!("\n\n**Since:** Nix {since}"));
+    }
+    md
+}
+/// Render a binding along with its doc, with the signature of it if it's a lambda.
+fn render_binding(name: &str, lambda: Option<&Lambda>, doc: Option<&Doc>) -> String {
+    let mut md = match lambda {
+        Some(lambda) => {
+            let params: Vec<_> = lambda.params.iter().map(ToString::to_string).collect();
+            format!("```nix\n{name} = {}: …\n```", params.join(": "))
+        },
+        None => format!("```nix\n{name}\n```"),
+    };
+    if let Some(doc) = doc.and_then(|doc| doc.doc.as_deref()) {
+        md.push_str("\n\n");
+        md.push_str(doc);
+    }
+    md
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MODULE: &str = r#"{ lib, ... }: {
+  options.services.foo = {
+    enable = lib.mkEnableOption "the foo service";
+    port = lib.mkOption {
+      type = lib.types.port;
+      default = 8080;
+      description = "The port to listen on.";
+    };
+  };
+}"#;
+
+    /// The markdown of hovering the `n`th occurrence of `needle` in `src`, with the module
+    /// declaring `services.foo` indexed.
+    fn hover_at(src: &str, needle: &str, n: usize) -> Option<String> {
+        let mut index = TancIndex::default();
+        index.insert("foo.nix", MODULE);
+        index.insert("main.nix", src);
+        let offset = src.match_indices(needle).nth(n)?.0;
+        let hover = hover(
+            &index,
+            &SearchPath::default(),
+            "main.nix",
+            src,
+            offset,
+            Encoding::Utf16,
+        )?;
+        let HoverContents::Markup(markup) = hover.contents else {
+            return None;
+        };
+        Some(markup.value)
+    }
+
+    #[test]
+    fn config_usage() {
+        let src = "{ config, ... }: { x = config.services.foo.enable; }";
+        assert_eq!(
+            hover_at(src, "enable", 0).unwrap(),
+            "```nix\nservices.foo.enable\n```\n\nWhether to enable the foo service.\n\n\
+             **Type:** `types.bool`\n\n**Default:** `false`\n\n**Example:** `true`"
+        );
+        // The attrset of options is not itself an option.
+        assert_eq!(hover_at(src, "foo", 0), None);
+    }
+    #[test]
+    fn config_bindings() {
+        let src = "{ lib, cfg, ... }: {\n  config = lib.mkIf cfg.enable {\n    services.foo.port = 1;\n  };\n}";
+        let md = hover_at(src, "port", 0).unwrap();
+        assert!(md.starts_with("```nix\nservices.foo.port\n```\n\nThe port to listen on."));
+        assert!(md.contains("**Type:** `lib.types.port`"));
+        assert!(md.contains("**Default:** `8080`"));
+        // Shorthand modules set options without `config`.
+        let md = hover_at("{ services.foo.enable = true; }", "enable", 0).unwrap();
+        assert!(md.contains("Whether to enable the foo service."));
+        // Nor are bindings of `let` blocks options.
+        let src = "let services.foo.enable = 1; in 1";
+        assert_eq!(hover_at(src, "enable", 0), None);
+    }
+    #[test]
+    fn option_declaration() {
+        let md = hover_at(MODULE, "port", 0).unwrap();
+        assert!(md.starts_with("```nix\nservices.foo.port\n```"));
+    }
+    #[test]
+    fn library_usage() {
+        let mut index = TancIndex::default();
+        let library = crate::library::Library {
+            root: "/nixpkgs".into(),
+            commit: None,
+        };
+        let strings = "{ lib }: {\n  # Concatenate strings.\n  concatStrings = list: list;\n}";
+        index.insert_library_files(
+            &library,
+            vec![("/nixpkgs/lib/strings.nix".into(), strings.into())],
+        );
+        let src = "{ lib, ... }: lib.strings.concatStrings [ ]";
+        let offset = src.find("concatStrings").unwrap();
+        let Some(HoverContents::Markup(markup)) = hover(&index, &SearchPath::default(), "main.nix", src, offset, Encoding::Utf16)
+            .map(|hover| hover.contents)
+        else {
+            panic!("expected markup");
+        };
+        assert_eq!(
+            markup.value,
+            "```nix\nlib.strings.concatStrings = list: …\n```\n\nConcatenate strings."
+        );
+    }
+    #[test]
+    fn search_path_import() {
+        let root = std::env::temp_dir().join(format!("tanc-hover-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("nixpkgs/lib")).unwrap();
+        let root = root.canonicalize().unwrap();
+        let nixpkgs = root.join("nixpkgs");
+        let search_path = SearchPath::parse(&format!("nixpkgs={}", nixpkgs.display()));
+        let library = crate::library::Library {
+            root: nixpkgs.clone(),
+            commit: None,
+        };
+        let strings = "{ lib }: {\n  # Concatenate strings.\n  concatStrings = list: list;\n}";
+        let mut index = TancIndex::default();
+        index.insert_library_files(
+            &library,
+            vec![(nixpkgs.join("lib/strings.nix"), strings.into())],
+        );
+        let hover_at = |src: &str, needle: &str| {
+            let offset = src.rfind(needle).unwrap();
+            let hover = hover(
+                &index,
+                &search_path,
+                "main.nix",
+                src,
+                offset,
+                Encoding::Utf16,
+            )?;
+            let HoverContents::Markup(markup) = hover.contents else {
+                return None;
+            };
+            Some(markup.value)
+        };
+        let src = "let l = import <nixpkgs/lib>; in l.strings.concatStrings";
+        assert_eq!(
+            hover_at(src, "concatStrings").unwrap(),
+            "```nix\nl.strings.concatStrings = list: …\n```\n\nConcatenate strings."
+        );
+        assert_eq!(
+            hover_at(src, "nixpkgs/lib").unwrap(),
+            format!(
+                "```nix\n<nixpkgs/lib>\n```\n\n`{}`",
+                nixpkgs.join("lib").display()
+            )
+        );
+        let src = "{ s ? import <nixpkgs/lib/strings.nix> { } }: s.concatStrings";
+        assert!(hover_at(src, "concatStrings")
+            .unwrap()
+            .ends_with("Concatenate strings."));
+        assert_eq!(hover_at("let l = import <missing>; in l.x", "x"), None);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+    #[test]
+    fn builtin_usage() {
+        let src = "{ x }: builtins.attrNames x";
+        assert_eq!(
+            hover_at(src, "attrNames", 0).unwrap(),
+            "```nix\nbuiltins.attrNames set\n```\n\nReturn the names of the attributes in the set \
+             *set* in an alphabetically sorted list. For instance, `builtins.attrNames { y = 1; x = \
+             \"foo\"; }` evaluates to `[ \"x\" \"y\" ]`.\n\n**Type:** `AttrSet -> [String]`"
+        );
+        let md = hover_at("map toString [ 1 ]", "map", 0).unwrap();
+        assert!(md.starts_with("```nix\nmap f list\n```\n\nApply the function *f*"));
+        let md = hover_at("builtins.zipAttrsWith", "zip", 0).unwrap();
+        assert!(md.ends_with("**Since:** Nix 2.6"), "{md}");
+        // Builtins are only in scope without `builtins.` if global, and aren't bindings.
+        assert_eq!(hover_at("attrNames { }", "attrNames", 0), None);
+        assert_eq!(hover_at("{ map = 1; }", "map", 0), None);
+    }
+    #[test]
+    fn with_usage() {
+        let mut index = TancIndex::default();
+        let library = crate::library::Library {
+            root: "/nixpkgs".into(),
+            commit: None,
+        };
+        let all_packages = "{ }: {\n  # Say hello.\n  hello = callPackage ./hello { };\n}";
+        index.insert_library_files(
+            &library,
+            vec![(
+                "/nixpkgs/pkgs/top-level/all-packages.nix".into(),
+                all_packages.into(),
+            )],
+        );
+        let hover_at = |src: &str, needle: &str| {
+            let mut index = index.clone();
+            index.insert("main.nix", src);
+            let offset = src.rfind(needle).unwrap();
+            let hover = hover(
+                &index,
+                &SearchPath::default(),
+                "main.nix",
+                src,
+                offset,
+                Encoding::Utf16,
+            )?;
+            let HoverContents::Markup(markup) = hover.contents else {
+                return None;
+            };
+            Some(markup.value)
+        };
+        let src = "{ pkgs, stuff }: with stuff; with pkgs; [ hello hello.meta git ]";
+        assert_eq!(
+            hover_at(src, "hello ").unwrap(),
+            "```nix\npkgs.hello\n```\n\nSay hello."
+        );
+        assert_eq!(
+            hover_at(src, "hello.meta").unwrap(),
+            "```nix\npkgs.hello\n```\n\nSay hello."
+        );
+        assert_eq!(
+            hover_at(src, "git").unwrap(),
+            "```nix\ngit\n```\n\nPossibly from `with pkgs` or `with stuff`."
+        );
+        let src = "with builtins; attrNames { }";
+        assert!(hover_at(src, "attrNames")
+            .unwrap()
+            .starts_with("```nix\nbuiltins.attrNames set\n```"));
+        // Lexical bindings take precedence over any `with`.
+        let src = "{ pkgs }: let hello = x: x; in with pkgs; hello";
+        assert_eq!(hover_at(src, "hello").unwrap(), "```nix\nhello = x: …\n```");
+    }
+    #[test]
+    fn with_local_attrset() {
+        let src =
+            "let\n  s = {\n    # Double it.\n    double = x: x * 2;\n  };\nin with s; double 1";
+        assert_eq!(
+            hover_at(src, "double", 1).unwrap(),
+            "```nix\ns.double = x: …\n```\n\nDouble it."
+        );
+    }
+    #[test]
+    fn lambda_usage() {
+        let src = "let\n  # Double it.\n  double = x: x * 2;\nin double 1";
+        assert_eq!(
+            hover_at(src, "double", 1).unwrap(),
+            "```nix\ndouble = x: …\n```\n\nDouble it."
+        );
+    }
+}
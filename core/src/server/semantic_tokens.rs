@@ -0,0 +1,490 @@
+//! Semantic tokens of open documents, classifying identifiers by what they refer to. Syntax
+//! highlighting alone can't tell a lambda argument from an attr or a `let` binding, while the
+//! scopes of a source can.
+use super::signature_help::{resolve_callee, Callee};
+use crate::{
+    builtins::{self, Builtin},
+    index::TancIndex,
+    line_index::{Encoding, LineIndex},
+    scope::{Def, DefKind, Resolution, Scopes},
+};
+use rnix::{ast, NodeOrToken, SyntaxKind, SyntaxNode, SyntaxToken, TextRange};
+use rowan::ast::AstNode;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+use tower_lsp::lsp_types::{
+    SemanticToken, SemanticTokenModifier, SemanticTokenType, SemanticTokens, SemanticTokensDelta,
+    SemanticTokensEdit, SemanticTokensFullDeltaResult, SemanticTokensLegend, Url,
+};
+
+/// The types of tokens, as indexes into the types of [`legend`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TokenType {
+    /// A `let` binding, or a name of unknown origin such as of a `with`.
+    Variable,
+    Parameter,
+    Property,
+    /// A binding, attr or builtin whose value is a lambda.
+    Function,
+    /// A doc comment, along with the [`DOCUMENTATION`] modifier.
+    Comment,
+}
+const TOKEN_TYPES: [SemanticTokenType; 5] = [
+    SemanticTokenType::VARIABLE,
+    SemanticTokenType::PARAMETER,
+    SemanticTokenType::PROPERTY,
+    SemanticTokenType::FUNCTION,
+    SemanticTokenType::COMMENT,
+];
+/// The bits of the modifiers of tokens, in the order of the modifiers of [`legend`].
+const DECLARATION: u32 = 1 << 0;
+const DEFAULT_LIBRARY: u32 = 1 << 1;
+const DEPRECATED: u32 = 1 << 2;
+const DOCUMENTATION: u32 = 1 << 3;
+const READONLY: u32 = 1 << 4;
+const TOKEN_MODIFIERS: [SemanticTokenModifier; 5] = [
+    SemanticTokenModifier::DECLARATION,
+    SemanticTokenModifier::DEFAULT_LIBRARY,
+    SemanticTokenModifier::DEPRECATED,
+    SemanticTokenModifier::DOCUMENTATION,
+    SemanticTokenModifier::READONLY,
+];
+
+/// The types and modifiers of the tokens of [`semantic_tokens`].
+pub fn legend() -> SemanticTokensLegend {
+    SemanticTokensLegend {
+        token_types: TOKEN_TYPES.to_vec(),
+        token_modifiers: TOKEN_MODIFIERS.to_vec(),
+    }
+}
+/// The semantic tokens of `src`, with positions in the given encoding. Tokens of multiple lines,
+/// such as block comments, are split into a token of each line.
+pub fn semantic_tokens(
+    index: &TancIndex,
+    file_path: &str,
+    src: &str,
+    encoding: Encoding,
+) -> Vec<SemanticToken> {
+    let root = rnix::Root::parse(src).syntax();
+    let mut classifier = Classifier {
+        index,
+        file_path,
+        scopes: Scopes::analyze(&root),
+        callees: HashMap::new(),
+    };
+    let tokens = root
+        .descendants_with_tokens()
+        .filter_map(NodeOrToken::into_token)
+        .filter_map(|token| {
+            let (ty, modifiers) = match token.kind() {
+                SyntaxKind::TOKEN_COMMENT if is_doc_comment(&token) => {
+                    (TokenType::Comment, DOCUMENTATION)
+                },
+                SyntaxKind::TOKEN_IDENT => classifier.ident(&token)?,
+                _ => return None,
+            };
+            Some((token.text_range(), ty, modifiers))
+        });
+    encode(src, tokens, encoding)
+}
+struct Classifier<'a> {
+    index: &'a TancIndex,
+    file_path: &'a str,
+    scopes: Scopes,
+    /// The classes of the idents of selects resolved through the index, such as `lib.mkIf`.
+    callees: HashMap<Vec<String>, Option<(TokenType, u32)>>,
+}
+impl Classifier<'_> {
+    fn ident(&mut self, token: &SyntaxToken) -> Option<(TokenType, u32)> {
+        let range = token.text_range();
+        if let Some(def) = self.scopes.def(range) {
+            let (ty, modifiers) = def_class(&self.scopes.defs()[def]);
+            return Some((ty, modifiers | DECLARATION));
+        }
+        let ident = token.parent()?;
+        let parent = ident.parent()?;
+        match parent.kind() {
+            SyntaxKind::NODE_ATTRPATH => match parent.parent().map(|p| (p.kind(), p)) {
+                Some((SyntaxKind::NODE_SELECT, select)) => self.select(&select, &ident),
+                Some((SyntaxKind::NODE_ATTRPATH_VALUE, apv)) => {
+                    let is_last = parent.children().last().as_ref() == Some(&ident);
+                    let apv = ast::AttrpathValue::cast(apv)?;
+                    match apv.value() {
+                        Some(ast::Expr::Lambda(_)) if is_last => {
+                            Some((TokenType::Function, DECLARATION))
+                        },
+                        _ => Some((TokenType::Property, DECLARATION)),
+                    }
+                },
+                _ => Some((TokenType::Property, 0)),
+            },
+            // Names inherited into attrsets, those of `let` blocks are bindings.
+            SyntaxKind::NODE_INHERIT => Some((TokenType::Property, DECLARATION)),
+            _ => {
+                let reference = self.scopes.reference(range)?;
+                match reference.resolution {
+                    Resolution::Def(def) => Some(def_class(&self.scopes.defs()[def])),
+                    Resolution::Builtin => match builtins::resolve(&[&reference.name]) {
+                        Some(builtin) => Some(builtin_class(builtin)),
+                        // `builtins`, `true`, `false` and `null`.
+                        None => Some((TokenType::Variable, DEFAULT_LIBRARY | READONLY)),
+                    },
+                    Resolution::With | Resolution::Free => Some((TokenType::Variable, 0)),
+                }
+            },
+        }
+    }
+    /// The class of an attr of a select such as `lib.mkIf`, of which only the last attr is
+    /// resolved.
+    fn select(&mut self, select: &SyntaxNode, ident: &SyntaxNode) -> Option<(TokenType, u32)> {
+        let select = ast::Select::cast(select.clone())?;
+        let attrs: Vec<_> = select.attrpath()?.attrs().collect();
+        if attrs.last()?.syntax() != ident {
+            return Some((TokenType::Property, 0));
+        }
+        let ast::Expr::Ident(head) = select.expr()? else {
+            return Some((TokenType::Property, 0));
+        };
+        let idents: Option<Vec<String>> = std::iter::once(Some(head.syntax().text().to_string()))
+            .chain(attrs.iter().map(|attr| match attr {
+                ast::Attr::Ident(ident) => Some(ident.syntax().text().to_string()),
+                _ => None,
+            }))
+            .collect();
+        let Some(idents) = idents else {
+            return Some((TokenType::Property, 0));
+        };
+        let (index, file_path) = (self.index, self.file_path);
+        let class = self.callees.entry(idents).or_insert_with_key(|idents| {
+            let idents: Vec<&str> = idents.iter().map(String::as_str).collect();
+            match resolve_callee(index, file_path, &idents)? {
+                Callee::Builtin(builtin) => Some(builtin_class(builtin)),
+                Callee::Lambda(_, doc) => {
+                    let doc = doc.and_then(|doc| doc.doc.as_deref());
+                    let deprecated = doc.map_or(false, is_deprecated);
+                    Some((TokenType::Function, if deprecated { DEPRECATED } else { 0 }))
+                },
+            }
+        });
+        Some(class.unwrap_or((TokenType::Property, 0)))
+    }
+}
+fn def_class(def: &Def) -> (TokenType, u32) {
+    match (def.kind, &def.value) {
+        (DefKind::Let | DefKind::Rec, Some(ast::Expr::Lambda(_))) => (TokenType::Function, 0),
+        (DefKind::Let, _) => (TokenType::Variable, 0),
+        (DefKind::Rec, _) => (TokenType::Property, 0),
+        (DefKind::Param | DefKind::Formal | DefKind::PatBind, _) => (TokenType::Parameter, 0),
+    }
+}
+fn builtin_class(builtin: &Builtin) -> (TokenType, u32) {
+    let mut modifiers = DEFAULT_LIBRARY;
+    if builtin.deprecated() {
+        modifiers |= DEPRECATED;
+    }
+    match builtin.arity {
+        0 => (TokenType::Variable, modifiers | READONLY),
+        _ => (TokenType::Function, modifiers),
+    }
+}
+/// Whether a doc notes its binding as deprecated at the start of its summary, such as
+/// `Deprecated, use foo instead.` or `**DEPRECATED.** ...` as of builtins.
+fn is_deprecated(doc: &str) -> bool {
+    let summary = doc.trim_start();
+    let summary = summary.strip_prefix("**").unwrap_or(summary);
+    summary.starts_with("Deprecated") || summary.starts_with("DEPRECATED")
+}
+/// Whether the comment documents the binding following it, as either an RFC 145 `/** */`
+/// comment or a run of comments directly above a binding, as docs are indexed.
+fn is_doc_comment(comment: &SyntaxToken) -> bool {
+    let text = comment.text();
+    if text.starts_with("/**") && text != "/**/" {
+        return true;
+    }
+    let at_line_start = match comment.prev_token() {
+        Some(prev) => prev.kind() == SyntaxKind::TOKEN_WHITESPACE && line_breaks(prev.text()) > 0,
+        None => true,
+    };
+    if !at_line_start {
+        return false;
+    }
+    let mut next = comment.next_sibling_or_token();
+    while let Some(element) = next {
+        match &element {
+            NodeOrToken::Token(token) => match token.kind() {
+                // A blank line detaches comments from the next binding.
+                SyntaxKind::TOKEN_WHITESPACE if line_breaks(token.text()) > 1 => return false,
+                SyntaxKind::TOKEN_WHITESPACE | SyntaxKind::TOKEN_COMMENT => {},
+                _ => return false,
+            },
+            NodeOrToken::Node(node) => {
+                return ast::AttrpathValue::can_cast(node.kind())
+                    || ast::Inherit::can_cast(node.kind())
+            },
+        }
+        next = element.next_sibling_or_token();
+    }
+    false
+}
+/// The number of line breaks in whitespace, of any line ending.
+fn line_breaks(whitespace: &str) -> usize {
+    LineIndex::new(whitespace).line_count() - 1
+}
+/// Encode classified ranges of `src` relative to each previous token, as LSP expects.
+fn encode(
+    src: &str,
+    tokens: impl Iterator<Item = (TextRange, TokenType, u32)>,
+    encoding: Encoding,
+) -> Vec<SemanticToken> {
+    let line_index = LineIndex::new(src);
+    let mut encoded = Vec::new();
+    let (mut prev_line, mut prev_char) = (0, 0);
+    for (range, ty, modifiers) in tokens {
+        let (start, end) = (usize::from(range.start()), usize::from(range.end()));
+        let (first, last) = (
+            line_index.position(start, encoding).line,
+            line_index.position(end, encoding).line,
+        );
+        // Tokens spanning lines, such as block comments, are split into a token per line.
+        for line in first..=last {
+            let text = line_index.line(line).unwrap_or_default();
+            let line_start = line_index.offset((line, 0).into(), Encoding::Utf8);
+            let indent = text.len() - text.trim_start().len();
+            let (from, to) = (
+                line_index.position(start.max(line_start + indent), encoding),
+                line_index.position(end.min(line_start + text.len()), encoding),
+            );
+            if from.char >= to.char {
+                continue;
+            }
+            let delta_start = if from.line == prev_line {
+                from.char - prev_char
+            } else {
+                from.char
+            };
+            encoded.push(SemanticToken {
+                delta_line: (from.line - prev_line) as u32,
+                delta_start: delta_start as u32,
+                length: (to.char - from.char) as u32,
+                token_type: ty as u32,
+                token_modifiers_bitset: modifiers,
+            });
+            (prev_line, prev_char) = (from.line, from.char);
+        }
+    }
+    encoded
+}
+/// The edits of the tokens `old` making them `new`, as a single edit replacing the tokens
+/// between their common prefix and suffix.
+fn edits(old: &[SemanticToken], new: &[SemanticToken]) -> Vec<SemanticTokensEdit> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (deleted, inserted) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+    if deleted.is_empty() && inserted.is_empty() {
+        return Vec::new();
+    }
+    // Edits index the flattened integers of the tokens, five of each.
+    vec![SemanticTokensEdit {
+        start: (prefix * 5) as u32,
+        delete_count: (deleted.len() * 5) as u32,
+        data: Some(inserted.to_vec()),
+    }]
+}
+/// The latest tokens sent of each document, from which later requests send only the edits.
+#[derive(Debug, Default)]
+pub struct TokenCache {
+    next_id: AtomicU64,
+    results: Mutex<HashMap<Url, SemanticTokens>>,
+}
+impl TokenCache {
+    /// Record the tokens of the document, returning them with a new result id.
+    pub fn full(&self, uri: Url, data: Vec<SemanticToken>) -> SemanticTokens {
+        let tokens = SemanticTokens {
+            result_id: Some(self.next_id.fetch_add(1, Ordering::Relaxed).to_string()),
+            data,
+        };
+        self.results
+            .lock()
+            .expect("semantic tokens lock poisoned")
+            .insert(uri, tokens.clone());
+        tokens
+    }
+    /// Record the tokens of the document, returning the edits from those of
+    /// `previous_result_id`, or every token if they're no longer recorded.
+    pub fn delta(
+        &self,
+        uri: Url,
+        previous_result_id: &str,
+        data: Vec<SemanticToken>,
+    ) -> SemanticTokensFullDeltaResult {
+        let previous = self
+            .results
+            .lock()
+            .expect("semantic tokens lock poisoned")
+            .get(&uri)
+            .filter(|previous| previous.result_id.as_deref() == Some(previous_result_id))
+            .cloned();
+        let tokens = self.full(uri, data);
+        match previous {
+            Some(previous) => SemanticTokensFullDeltaResult::TokensDelta(SemanticTokensDelta {
+                edits: edits(&previous.data, &tokens.data),
+                result_id: tokens.result_id,
+            }),
+            None => SemanticTokensFullDeltaResult::Tokens(tokens),
+        }
+    }
+    pub fn remove(&self, uri: &Url) {
+        self.results
+            .lock()
+            .expect("semantic tokens lock poisoned")
+            .remove(uri);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The text of every token of `src`, along with its type and modifiers.
+    fn tokens(src: &str) -> Vec<String> {
+        let mut index = TancIndex::default();
+        index.insert(
+            "lib.nix",
+            "{\n  # Deprecated, use `new`.\n  old = x: x;\n  new = x: x;\n}",
+        );
+        let line_index = LineIndex::new(src);
+        let (mut line, mut char) = (0, 0);
+        semantic_tokens(&index, "test.nix", src, Encoding::Utf16)
+            .into_iter()
+            .map(|token| {
+                if token.delta_line > 0 {
+                    char = 0;
+                }
+                line += token.delta_line as usize;
+                char += token.delta_start as usize;
+                let start = line_index.offset((line, char).into(), Encoding::Utf16);
+                let end =
+                    line_index.offset((line, char + token.length as usize).into(), Encoding::Utf16);
+                let legend = legend();
+                let mut class = legend.token_types[token.token_type as usize]
+                    .as_str()
+                    .to_owned();
+                for (i, modifier) in legend.token_modifiers.iter().enumerate() {
+                    if token.token_modifiers_bitset & (1 << i) != 0 {
+                        class.push('.');
+                        class.push_str(modifier.as_str());
+                    }
+                }
+                format!("{} {class}", &src[start..end])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn classes() {
+        let src = r#"{ lib, ... }:
+let
+  f = x: x;
+  a = 1;
+in rec {
+  /**
+    Ü.
+  */
+  b.c = f a;
+  d = builtins.toPath (map lib.old [ lib.new lib.other ]);
+  e = y: b;
+}"#;
+        let expected = [
+            "lib parameter.declaration",
+            "f function.declaration",
+            "x parameter.declaration",
+            "x parameter",
+            "a variable.declaration",
+            "/** comment.documentation",
+            "Ü. comment.documentation",
+            "*/ comment.documentation",
+            "b property.declaration",
+            "c property.declaration",
+            "f function",
+            "a variable",
+            "d property.declaration",
+            "builtins variable.defaultLibrary.readonly",
+            "toPath function.defaultLibrary.deprecated",
+            "map function.defaultLibrary",
+            "lib parameter",
+            "old function.deprecated",
+            "lib parameter",
+            "new function",
+            "lib parameter",
+            "other property",
+            "e function.declaration",
+            "y parameter.declaration",
+            "b property",
+        ];
+        assert_eq!(tokens(src), expected);
+        // Block comments are split on every kind of line ending.
+        for ending in ["\r\n", "\r"] {
+            assert_eq!(tokens(&src.replace('\n', ending)), expected);
+        }
+    }
+    #[test]
+    fn doc_comments() {
+        let src = "{\n  # Doc.\n  a = 1; # Not.\n  # Detached.\n\n  b = 2;\n}";
+        assert_eq!(
+            tokens(src),
+            [
+                "# Doc. comment.documentation",
+                "a property.declaration",
+                "b property.declaration",
+            ]
+        );
+        assert_eq!(tokens(&src.replace('\n', "\r\n")), tokens(src));
+    }
+    #[test]
+    fn deltas() {
+        let cache = TokenCache::default();
+        let uri = Url::parse("file:///test.nix").unwrap();
+        let index = TancIndex::default();
+        let data = |src| semantic_tokens(&index, "test.nix", src, Encoding::Utf16);
+        let first = cache.full(uri.clone(), data("x: y: [ x y ]"));
+        let SemanticTokensFullDeltaResult::TokensDelta(delta) = cache.delta(
+            uri.clone(),
+            first.result_id.as_deref().unwrap(),
+            data("x: yy: [ x yy ]"),
+        ) else {
+            panic!("expected a delta");
+        };
+        // Every token after the first differs in its length or position.
+        assert_eq!(
+            delta.edits,
+            [SemanticTokensEdit {
+                start: 5,
+                delete_count: 15,
+                data: Some(data("x: yy: [ x yy ]")[1..4].to_vec()),
+            }]
+        );
+        // Unknown results are sent in full.
+        let result = cache.delta(uri, "unknown", data("x: x"));
+        assert!(matches!(result, SemanticTokensFullDeltaResult::Tokens(_)));
+    }
+    #[test]
+    fn deprecated_docs() {
+        assert!(is_deprecated("Deprecated, use `new`."));
+        assert!(is_deprecated("**DEPRECATED.** Use `new`."));
+        assert!(!is_deprecated("Replaces the deprecated `old`."));
+        assert!(!is_deprecated("A summary.\n\nDeprecated in 24.05."));
+    }
+}
@@ -0,0 +1,375 @@
+use crate::{
+    builtins::{self, Builtin},
+    index::{Doc, Lambda, Param, TancIndex},
+};
+use rnix::{
+    ast::{self, HasEntry},
+    SyntaxKind, SyntaxNode, SyntaxToken, TextSize,
+};
+use rowan::ast::AstNode;
+use tower_lsp::lsp_types::{
+    Documentation, MarkupContent, MarkupKind, ParameterInformation, ParameterLabel, SignatureHelp,
+    SignatureInformation,
+};
+
+/// Compute the signature of the function being applied at `offset` in `src`, with the argument
+/// under the cursor as the active parameter.
+pub fn signature_help(
+    index: &TancIndex,
+    file_path: &str,
+    src: &str,
+    offset: usize,
+) -> Option<SignatureHelp> {
+    let root = rnix::Root::parse(src).syntax();
+    let offset = TextSize::try_from(offset).ok()?;
+    let token = token_before(&root, offset)?;
+    let call = Call::at(&token, offset)?;
+    let idents: Vec<&str> = call.callee.iter().map(String::as_str).collect();
+    let (lambda, doc) = match resolve_callee(index, file_path, &idents)? {
+        Callee::Lambda(lambda, doc) => (lambda.clone(), doc.and_then(|doc| doc.doc.clone())),
+        Callee::Builtin(builtin) => (builtin.lambda(), Some(builtin.description.clone())),
+    };
+
+    let active_formal = call
+        .active_arg
+        .and_then(|i| call.args.get(i))
+        .and_then(|arg| ast::AttrSet::cast(arg.clone()))
+        .map(|attr_set| active_binding(&attr_set, &token, offset));
+    let (label, parameters, active_parameter) = signature_label(
+        &call.callee.join("."),
+        &lambda,
+        call.active_arg,
+        active_formal,
+    );
+    Some(SignatureHelp {
+        signatures: vec![SignatureInformation {
+            label,
+            documentation: doc.map(|value| {
+                Documentation::MarkupContent(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value,
+                })
+            }),
+            parameters: Some(parameters),
+            active_parameter,
+        }],
+        active_signature: Some(0),
+        active_parameter,
+    })
+}
+/// What the idents of a function refer to.
+pub(super) enum Callee<'a> {
+    Lambda(&'a Lambda, Option<&'a Doc>),
+    Builtin(&'static Builtin),
+}
+/// Resolve the idents of a function, such as `["lib", "foldl'"]` or `["builtins", "foldl'"]`.
+///
+/// Lambdas are matched by name alone, so a builtin such as `map` is preferred to lambdas of the
+/// same name in other files, which are unlikely to be what's referred to.
+pub(super) fn resolve_callee<'a>(
+    index: &'a TancIndex,
+    file_path: &str,
+    idents: &[&str],
+) -> Option<Callee<'a>> {
+    let builtin = builtins::resolve(idents);
+    match index.resolve_lambda(file_path, idents) {
+        Some((key, _, lambda, doc))
+            if builtin.is_none() || (idents[0] != "builtins" && key.file_path == file_path) =>
+        {
+            Some(Callee::Lambda(lambda, doc))
+        },
+        _ => builtin.map(Callee::Builtin),
+    }
+}
+/// The last non-trivia token starting before `offset`.
+fn token_before(root: &SyntaxNode, offset: TextSize) -> Option<SyntaxToken> {
+    let mut token = root.token_at_offset(offset).left_biased()?;
+    while token.kind() == SyntaxKind::TOKEN_WHITESPACE
+        || token.kind() == SyntaxKind::TOKEN_COMMENT
+        || token.text_range().start() >= offset
+    {
+        token = token.prev_token()?;
+    }
+    Some(token)
+}
+/// A function application surrounding the cursor, such as `lib.foldl' op nul`.
+#[derive(Debug)]
+struct Call {
+    /// The idents of the function being called, eg `["lib", "foldl'"]`.
+    callee: Vec<String>,
+    /// The arguments written so far, in application order.
+    args: Vec<SyntaxNode>,
+    /// The index of the argument under the cursor, which may be one past `args` if the cursor is
+    /// after all of them.
+    active_arg: Option<usize>,
+}
+impl Call {
+    fn at(token: &SyntaxToken, offset: TextSize) -> Option<Self> {
+        // Find the innermost application the token is an argument of, rather than the callee.
+        let mut apply = token.parent_ancestors().find(|node| {
+            ast::Apply::cast(node.clone())
+                .and_then(|apply| apply.argument())
+                .map_or(false, |arg| {
+                    arg.syntax().text_range().contains_range(token.text_range())
+                })
+        })?;
+        // Curried applications nest as `((f a) b) c`, so walk up to the outermost.
+        while let Some(parent) = apply
+            .parent()
+            .filter(|p| p.kind() == SyntaxKind::NODE_APPLY)
+        {
+            let is_lambda = ast::Apply::cast(parent.clone())
+                .and_then(|p| p.lambda())
+                .map_or(false, |l| l.syntax() == &apply);
+            if !is_lambda {
+                break;
+            }
+            apply = parent;
+        }
+
+        let mut args = Vec::new();
+        let mut func = ast::Expr::cast(apply)?;
+        while let ast::Expr::Apply(apply) = func {
+            args.extend(apply.argument().map(|arg| arg.syntax().clone()));
+            func = apply.lambda()?;
+        }
+        args.reverse();
+        let callee = callee_idents(&func)?;
+
+        let active_arg = args
+            .iter()
+            .position(|arg| {
+                let range = arg.text_range();
+                range.start() < offset && (offset <= range.end() || is_unclosed(arg))
+            })
+            .or_else(|| {
+                Some(
+                    args.iter()
+                        .filter(|a| a.text_range().end() < offset)
+                        .count(),
+                )
+            });
+        Some(Self {
+            callee,
+            args,
+            active_arg,
+        })
+    }
+}
+pub(super) fn callee_idents(expr: &ast::Expr) -> Option<Vec<String>> {
+    match expr {
+        ast::Expr::Ident(ident) => Some(vec![ident.syntax().text().to_string()]),
+        ast::Expr::Select(select) => {
+            let mut idents = callee_idents(&select.expr()?)?;
+            for attr in select.attrpath()?.attrs() {
+                match attr {
+                    ast::Attr::Ident(ident) => idents.push(ident.syntax().text().to_string()),
+                    _ => return None,
+                }
+            }
+            Some(idents)
+        },
+        ast::Expr::Paren(paren) => callee_idents(&paren.expr()?),
+        _ => None,
+    }
+}
+/// Whether the node is an attrset or list which is missing its closing delimiter, as is common
+/// while typing.
+fn is_unclosed(node: &SyntaxNode) -> bool {
+    let closing = match node.kind() {
+        SyntaxKind::NODE_ATTR_SET => SyntaxKind::TOKEN_R_BRACE,
+        SyntaxKind::NODE_LIST => SyntaxKind::TOKEN_R_BRACK,
+        _ => return false,
+    };
+    !node
+        .children_with_tokens()
+        .any(|child| child.kind() == closing)
+}
+/// The attrs of an attrset argument relevant to picking the active formal.
+#[derive(Debug, Default)]
+struct ActiveBinding {
+    /// The name of the binding under the cursor, if any.
+    current: Option<String>,
+    /// The names of all bindings in the attrset.
+    bound: Vec<String>,
+}
+fn active_binding(attr_set: &ast::AttrSet, token: &SyntaxToken, offset: TextSize) -> ActiveBinding {
+    let first_name = |apv: &ast::AttrpathValue| -> Option<String> {
+        Some(apv.attrpath()?.attrs().next()?.syntax().text().to_string())
+    };
+    let bound = attr_set
+        .attrpath_values()
+        .filter_map(|apv| first_name(&apv))
+        .collect();
+    let current = token
+        .parent_ancestors()
+        .take_while(|node| node != attr_set.syntax())
+        .filter_map(ast::AttrpathValue::cast)
+        .last()
+        .filter(|apv| {
+            // A binding which has been terminated is no longer being edited.
+            !(apv.syntax().last_token().map(|t| t.kind()) == Some(SyntaxKind::TOKEN_SEMICOLON)
+                && offset >= apv.syntax().text_range().end())
+        })
+        .and_then(|apv| first_name(&apv));
+    ActiveBinding { current, bound }
+}
+/// Render `name param1 param2` along with the label offsets of each parameter, flattening the
+/// formals of attrset patterns into individual parameters.
+fn signature_label(
+    name: &str,
+    lambda: &Lambda,
+    active_arg: Option<usize>,
+    active_binding: Option<ActiveBinding>,
+) -> (String, Vec<ParameterInformation>, Option<u32>) {
+    let mut label = String::from(name);
+    let mut parameters = Vec::new();
+    let mut active_parameter = None;
+    let mut push = |label: &mut String, text: &str| {
+        let start = label.encode_utf16().count() as u32;
+        label.push_str(text);
+        let end = label.encode_utf16().count() as u32;
+        parameters.push(ParameterInformation {
+            label: ParameterLabel::LabelOffsets([start, end]),
+            documentation: None,
+        });
+        parameters.len() as u32 - 1
+    };
+    for (i, param) in lambda.params.iter().enumerate() {
+        label.push(' ');
+        let is_active = active_arg == Some(i);
+        match param {
+            Param::Pattern {
+                formals,
+                ellipsis,
+                bind,
+            } if !formals.is_empty() => {
+                let binding = active_binding.as_ref().filter(|_| is_active);
+                // Prefer the formal being written, then the first required formal not yet given.
+                let active_name = binding.and_then(|b| {
+                    b.current.clone().or_else(|| {
+                        formals
+                            .iter()
+                            .filter(|f| !b.bound.iter().any(|name| name == f.name.as_str()))
+                            .min_by_key(|f| f.default.is_some())
+                            .map(|f| f.name.to_string())
+                    })
+                });
+                label.push_str("{ ");
+                for (j, formal) in formals.iter().enumerate() {
+                    if j != 0 {
+                        label.push_str(", ");
+                    }
+                    let idx = push(&mut label, &formal.to_string());
+                    if active_name.as_deref() == Some(formal.name.as_str()) {
+                        active_parameter = Some(idx);
+                    }
+                }
+                if *ellipsis {
+                    label.push_str(", ...");
+                }
+                label.push_str(" }");
+                if let Some(bind) = bind {
+                    label.push('@');
+                    label.push_str(bind);
+                }
+            },
+            param => {
+                let idx = push(&mut label, &param.to_string());
+                if is_active {
+                    active_parameter = Some(idx);
+                }
+            },
+        }
+    }
+    (label, parameters, active_parameter)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Signature help at the end of `src`, with `lib_src` indexed as `lib.nix`.
+    fn help_at_end(lib_src: &str, src: &str) -> Option<SignatureHelp> {
+        let mut index = TancIndex::default();
+        index.insert("lib.nix", lib_src);
+        index.insert("main.nix", src);
+        signature_help(&index, "main.nix", src, src.len())
+    }
+    fn active_label(help: &SignatureHelp) -> &str {
+        let sig = &help.signatures[0];
+        let Some(ParameterLabel::LabelOffsets([start, end])) = help
+            .active_parameter
+            .and_then(|i| sig.parameters.as_ref()?.get(i as usize))
+            .map(|p| &p.label)
+        else {
+            return "";
+        };
+        &sig.label[*start as usize..*end as usize]
+    }
+
+    #[test]
+    fn attrset_pattern_formals() {
+        let help = help_at_end(
+            "",
+            "let\n  # Make a thing.\n  mkThing = { a, b ? 1 }: a;\nin mkThing { ",
+        )
+        .unwrap();
+        assert_eq!(help.signatures[0].label, "mkThing { a, b ? 1 }");
+        assert_eq!(active_label(&help), "a");
+        assert_eq!(
+            help.signatures[0].documentation,
+            Some(Documentation::MarkupContent(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: "Make a thing.".into(),
+            }))
+        );
+    }
+    #[test]
+    fn attrset_pattern_current_binding() {
+        let src = "let mkThing = { a, b ? 1 }: a; in mkThing { a = 1; b";
+        assert_eq!(active_label(&help_at_end("", src).unwrap()), "b ? 1");
+        let src = "let mkThing = { a, b ? 1, c }: a; in mkThing { a = 1; ";
+        assert_eq!(active_label(&help_at_end("", src).unwrap()), "c");
+    }
+    #[test]
+    fn curried_positional() {
+        let lib = "{ foldl' = op: nul: list: nul; }";
+        let help = help_at_end(lib, "lib.foldl' op nul").unwrap();
+        assert_eq!(help.signatures[0].label, "lib.foldl' op nul list");
+        assert_eq!(active_label(&help), "nul");
+        assert_eq!(
+            active_label(&help_at_end(lib, "lib.foldl' op nul ").unwrap()),
+            "list"
+        );
+        assert_eq!(
+            active_label(&help_at_end(lib, "lib.foldl' (f x) ").unwrap()),
+            "nul"
+        );
+    }
+    #[test]
+    fn builtins() {
+        let help = help_at_end("", "builtins.foldl' op ").unwrap();
+        assert_eq!(help.signatures[0].label, "builtins.foldl' op nul list");
+        assert_eq!(active_label(&help), "nul");
+        let Some(Documentation::MarkupContent(doc)) = &help.signatures[0].documentation else {
+            panic!("expected docs");
+        };
+        assert!(doc.value.starts_with("Reduce a list"));
+        let help = help_at_end("", "map f ").unwrap();
+        assert_eq!(help.signatures[0].label, "map f list");
+        assert_eq!(active_label(&help), "list");
+        // Lambdas of other files don't shadow builtins, while those of the same file do.
+        let lib = "{ map = xs: xs; }";
+        assert_eq!(
+            help_at_end(lib, "map f ").unwrap().signatures[0].label,
+            "map f list"
+        );
+        let src = "let map = xs: xs; in map f";
+        assert_eq!(help_at_end("", src).unwrap().signatures[0].label, "map xs");
+    }
+    #[test]
+    fn unknown_callee() {
+        assert_eq!(help_at_end("", "nope { "), None);
+    }
+}
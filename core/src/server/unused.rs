@@ -0,0 +1,361 @@
+//! Bindings which are never referred to, such as of `let` blocks and lambda arguments, along with
+//! the edits removing them. Much like the lints of `deadnix`.
+use crate::scope::{Def, DefKind, Resolution, Scopes};
+use rnix::{
+    ast::{self, HasEntry},
+    SyntaxKind, SyntaxNode, SyntaxToken, TextRange, TextSize,
+};
+use rowan::ast::AstNode;
+
+/// A binding which is never referred to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unused {
+    /// The range of the name of the binding.
+    pub range: TextRange,
+    pub name: String,
+    pub kind: UnusedKind,
+    /// The title of [`Self::edits`], such as `Remove unused argument `x``.
+    pub fix: String,
+    /// The edits fixing the binding, as the ranges of the source replaced and their
+    /// replacements.
+    pub edits: Vec<(TextRange, String)>,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnusedKind {
+    /// A binding of a `let` block.
+    Binding,
+    /// An argument of a lambda, either a param, a formal or the name of the whole pattern.
+    Argument,
+    /// A name inherited by a `let` block.
+    Inherit,
+}
+impl UnusedKind {
+    /// The code of diagnostics of this kind.
+    pub fn code(self) -> &'static str {
+        match self {
+            Self::Binding => "unused-binding",
+            Self::Argument => "unused-argument",
+            Self::Inherit => "unused-inherit",
+        }
+    }
+    fn describe(self) -> &'static str {
+        match self {
+            Self::Binding => "`let` binding",
+            Self::Argument => "argument",
+            Self::Inherit => "inherit",
+        }
+    }
+}
+impl Unused {
+    pub fn message(&self) -> String {
+        format!("unused {} `{}`", self.kind.describe(), self.name)
+    }
+}
+/// Find the unused bindings of the analyzed scopes of a source. Names starting with `_` are assumed to be unused on
+/// purpose, while a binding referred to only by itself, such as `f = x: f x;`, is unused.
+///
+/// Formals of a pattern whose whole attrset is bound and used, such as `args` of
+/// `{ a, ... }@args: f args`, are never unused since they describe what's passed on.
+pub fn unused(scopes: &Scopes) -> Vec<Unused> {
+    let mut used = vec![false; scopes.defs().len()];
+    for r in scopes.refs() {
+        if let Resolution::Def(i) = r.resolution {
+            used[i] |= !scopes.defs()[i].node.text_range().contains_range(r.range);
+        }
+    }
+    let is_used = |i: usize| used[i];
+    scopes
+        .defs()
+        .iter()
+        .enumerate()
+        .filter(|&(i, def)| !def.name.starts_with('_') && !is_used(i))
+        .filter_map(|(_, def)| match def.kind {
+            // Attrs of `rec` attrsets are public.
+            DefKind::Rec => None,
+            DefKind::Let => unused_let(def),
+            DefKind::Param => Some(Unused {
+                range: def.range,
+                name: def.name.clone(),
+                kind: UnusedKind::Argument,
+                fix: format!("Rename unused argument `{0}` to `_{0}`", def.name),
+                edits: vec![(def.range, format!("_{}", def.name))],
+            }),
+            DefKind::Formal => {
+                let entry = ast::PatEntry::cast(def.node.clone())?;
+                let pattern = ast::Pattern::cast(entry.syntax().parent()?)?;
+                let bind_used = pattern
+                    .pat_bind()
+                    .and_then(|bind| scopes.def(bind.ident()?.syntax().text_range()))
+                    .map_or(false, is_used);
+                if bind_used {
+                    return None;
+                }
+                Some(Unused {
+                    range: def.range,
+                    name: def.name.clone(),
+                    kind: UnusedKind::Argument,
+                    fix: format!("Remove unused argument `{}`", def.name),
+                    edits: remove_formal(&pattern, &entry)?,
+                })
+            },
+            DefKind::PatBind => {
+                let bind = ast::PatBind::cast(def.node.clone())?;
+                // The bind includes its `@`, along with the whitespace between it and the
+                // formals.
+                let range = bind.syntax().text_range();
+                let whitespace = |token: Option<SyntaxToken>| {
+                    token.filter(|token| token.kind() == SyntaxKind::TOKEN_WHITESPACE)
+                };
+                let range = match (
+                    whitespace(bind.syntax().first_token()?.prev_token()),
+                    whitespace(bind.syntax().last_token()?.next_token()),
+                ) {
+                    (Some(prev), _) => prev.text_range().cover(range),
+                    (None, Some(next))
+                        if next.next_token().map(|t| t.kind()) != Some(SyntaxKind::TOKEN_COLON) =>
+                    {
+                        range.cover(next.text_range())
+                    },
+                    _ => range,
+                };
+                Some(Unused {
+                    range: def.range,
+                    name: def.name.clone(),
+                    kind: UnusedKind::Argument,
+                    fix: format!("Remove unused argument `{}`", def.name),
+                    edits: vec![(range, String::new())],
+                })
+            },
+        })
+        .collect()
+}
+/// An unused binding or inherit of a `let` block, removing every binding of its name such as
+/// both of `a.b = 1; a.c = 2;`.
+fn unused_let(def: &Def) -> Option<Unused> {
+    let (kind, edits) = match def.node.kind() {
+        SyntaxKind::NODE_IDENT => {
+            let inherit = ast::Inherit::cast(def.node.parent()?)?;
+            ast::LetIn::cast(inherit.syntax().parent()?)?;
+            let range = if inherit.attrs().count() == 1 {
+                removal_range(inherit.syntax())
+            } else {
+                // The name along with the whitespace before it.
+                let prev = def.node.first_token()?.prev_token()?;
+                let start = match prev.kind() {
+                    SyntaxKind::TOKEN_WHITESPACE => prev.text_range().start(),
+                    _ => def.range.start(),
+                };
+                TextRange::new(start, def.range.end())
+            };
+            (UnusedKind::Inherit, vec![(range, String::new())])
+        },
+        _ => {
+            // The body of a legacy `let { body = ...; }` is never referred to.
+            let let_in = ast::LetIn::cast(def.node.parent()?)?;
+            let edits = let_in
+                .attrpath_values()
+                .filter(|apv| {
+                    let first = apv.attrpath().and_then(|attrpath| attrpath.attrs().next());
+                    first.map_or(false, |attr| attr.syntax().text() == def.name.as_str())
+                })
+                .map(|apv| (removal_range(apv.syntax()), String::new()))
+                .collect();
+            (UnusedKind::Binding, edits)
+        },
+    };
+    Some(Unused {
+        range: def.range,
+        name: def.name.clone(),
+        kind,
+        fix: format!("Remove unused {} `{}`", kind.describe(), def.name),
+        edits,
+    })
+}
+/// The edits removing a formal from a pattern, along with its comma.
+///
+/// Callers may still pass the formal, so a pattern without `...` is given one rather than
+/// failing such calls.
+fn remove_formal(
+    pattern: &ast::Pattern,
+    entry: &ast::PatEntry,
+) -> Option<Vec<(TextRange, String)>> {
+    let range = entry.syntax().text_range();
+    let entries: Vec<_> = pattern.pat_entries().collect();
+    let is_last = entries.last() == Some(entry);
+    if pattern.ellipsis_token().is_none() && is_last {
+        return Some(vec![(range, "...".into())]);
+    }
+    let mut edits = Vec::new();
+    match non_trivia_token(
+        entry.syntax().last_token()?.next_token(),
+        SyntaxToken::next_token,
+    ) {
+        // The formal along with the comma and whitespace after it.
+        Some(comma) if comma.kind() == SyntaxKind::TOKEN_COMMA => {
+            let end = match comma.next_token() {
+                Some(ws) if ws.kind() == SyntaxKind::TOKEN_WHITESPACE => ws.text_range().end(),
+                _ => comma.text_range().end(),
+            };
+            edits.push((TextRange::new(range.start(), end), String::new()));
+        },
+        // The last formal along with the comma before it.
+        _ => {
+            let prev = entry.syntax().first_token()?.prev_token();
+            let comma = non_trivia_token(prev, SyntaxToken::prev_token)
+                .filter(|comma| comma.kind() == SyntaxKind::TOKEN_COMMA)?;
+            edits.push((
+                TextRange::new(comma.text_range().start(), range.end()),
+                String::new(),
+            ));
+        },
+    }
+    if pattern.ellipsis_token().is_none() {
+        let last = entries.last()?.syntax().text_range().end();
+        edits.push((TextRange::empty(last), ", ...".into()));
+    }
+    Some(edits)
+}
+/// The first token from `token` on which isn't whitespace or a comment, moving by `next`.
+fn non_trivia_token(
+    mut token: Option<SyntaxToken>,
+    next: fn(&SyntaxToken) -> Option<SyntaxToken>,
+) -> Option<SyntaxToken> {
+    while let Some(t) = token {
+        if !matches!(
+            t.kind(),
+            SyntaxKind::TOKEN_WHITESPACE | SyntaxKind::TOKEN_COMMENT
+        ) {
+            return Some(t);
+        }
+        token = next(&t);
+    }
+    None
+}
+/// The range removing a node along with the whitespace around it, such that a node alone on its
+/// line is removed along with the line.
+fn removal_range(node: &SyntaxNode) -> TextRange {
+    let range = node.text_range();
+    let whitespace = |token: Option<SyntaxToken>| {
+        token.filter(|token| token.kind() == SyntaxKind::TOKEN_WHITESPACE)
+    };
+    let prev = whitespace(node.first_token().and_then(|token| token.prev_token()));
+    let next = whitespace(node.last_token().and_then(|token| token.next_token()));
+    let offset = |token: &SyntaxToken, i: usize| {
+        token.text_range().start() + TextSize::try_from(i).unwrap_or_default()
+    };
+    match (prev, next) {
+        (Some(prev), Some(next)) if prev.text().contains('\n') && next.text().contains('\n') => {
+            let start = prev
+                .text()
+                .rfind('\n')
+                .map_or(range.start(), |i| offset(&prev, i + 1));
+            let end = next
+                .text()
+                .find('\n')
+                .map_or(range.end(), |i| offset(&next, i + 1));
+            TextRange::new(start, end)
+        },
+        (_, Some(next)) => TextRange::new(range.start(), next.text_range().end()),
+        (Some(prev), None) => TextRange::new(prev.text_range().start(), range.end()),
+        (None, None) => range,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The messages of the unused bindings of `src`, along with `src` once each is fixed.
+    fn fixes(src: &str) -> Vec<(String, String)> {
+        let root = rnix::Root::parse(src).syntax();
+        unused(&Scopes::analyze(&root))
+            .into_iter()
+            .map(|unused| {
+                let mut fixed = src.to_owned();
+                let mut edits = unused.edits.clone();
+                edits.sort_by_key(|(range, _)| std::cmp::Reverse(range.start()));
+                for (range, replacement) in edits {
+                    fixed.replace_range(std::ops::Range::<usize>::from(range), &replacement);
+                }
+                (unused.message(), fixed)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn let_bindings() {
+        let src = "let\n  a = 1;\n  b.c = 2;\n  b.d = 3;\n  f = x: f x;\n  _g = 1;\nin a";
+        assert_eq!(
+            fixes(src),
+            [
+                (
+                    "unused `let` binding `b`".to_owned(),
+                    "let\n  a = 1;\n  f = x: f x;\n  _g = 1;\nin a".to_owned()
+                ),
+                (
+                    "unused `let` binding `f`".to_owned(),
+                    "let\n  a = 1;\n  b.c = 2;\n  b.d = 3;\n  _g = 1;\nin a".to_owned()
+                ),
+            ]
+        );
+        assert_eq!(
+            fixes("let a = 1; b = 2; in b"),
+            [(
+                "unused `let` binding `a`".to_owned(),
+                "let b = 2; in b".to_owned()
+            )]
+        );
+        // Attrs are public.
+        assert_eq!(fixes("rec { a = 1; }"), []);
+    }
+    #[test]
+    fn inherits() {
+        let src = "{ lib }: let inherit (lib) mkIf mkOption; inherit (lib) types; in mkIf";
+        assert_eq!(
+            fixes(src),
+            [
+                (
+                    "unused inherit `mkOption`".to_owned(),
+                    "{ lib }: let inherit (lib) mkIf; inherit (lib) types; in mkIf".to_owned()
+                ),
+                (
+                    "unused inherit `types`".to_owned(),
+                    "{ lib }: let inherit (lib) mkIf mkOption; in mkIf".to_owned()
+                ),
+            ]
+        );
+    }
+    #[test]
+    fn arguments() {
+        assert_eq!(
+            fixes("x: y: y"),
+            [("unused argument `x`".to_owned(), "_x: y: y".to_owned())]
+        );
+        assert_eq!(
+            fixes("{ a, b, c, ... }: a + c"),
+            [(
+                "unused argument `b`".to_owned(),
+                "{ a, c, ... }: a + c".to_owned()
+            )]
+        );
+        // Without `...`, callers may still pass what's removed.
+        assert_eq!(
+            fixes("{ a, b }: a"),
+            [("unused argument `b`".to_owned(), "{ a, ... }: a".to_owned())]
+        );
+        assert_eq!(
+            fixes("{ b, a }: a"),
+            [("unused argument `b`".to_owned(), "{ a, ... }: a".to_owned())]
+        );
+        assert_eq!(
+            fixes("{ a }@args: a"),
+            [("unused argument `args`".to_owned(), "{ a }: a".to_owned())]
+        );
+        // Formals describe what's passed on through a used `@args`.
+        assert_eq!(fixes("args@{ a, ... }: f args"), []);
+        assert_eq!(
+            fixes("args @ { a }: a"),
+            [("unused argument `args`".to_owned(), "{ a }: a".to_owned())]
+        );
+    }
+}
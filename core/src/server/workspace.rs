@@ -0,0 +1,295 @@
+//! Indexing of every Nix file under the workspace roots of a client, in the background with
+//! progress reported to the client.
+use super::{Config, SharedIndex};
+use crate::{index::TancIndex, library::Library, project::nix_files, search_path::SearchPath};
+use compact_str::CompactString;
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tower_lsp::{lsp_types::*, Client};
+
+/// How often indexing progress is reported to the client.
+const REPORT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What the client told us about its workspace in `initialize`.
+#[derive(Debug, Default, Clone)]
+pub struct Workspace {
+    pub roots: Vec<PathBuf>,
+    /// Directories to index as read-only libraries, such as a checkout of nixpkgs, including the
+    /// named directories of the search path.
+    pub libraries: Vec<PathBuf>,
+    /// The search path resolving angle-bracket paths such as `<nixpkgs>`.
+    pub search_path: SearchPath,
+    /// Whether the client supports server initiated `$/progress`.
+    pub work_done_progress: bool,
+    /// Whether the `with-shadowing` lint is enabled. See [`Config::with_shadowing`].
+    pub with_shadowing: bool,
+}
+impl Workspace {
+    pub fn new(params: &InitializeParams, config: &Config) -> Self {
+        // `root_uri` is deprecated in favour of workspace folders, but still the only root sent by
+        // some clients.
+        let uris: Vec<&Url> = match &params.workspace_folders {
+            Some(folders) if !folders.is_empty() => folders.iter().map(|f| &f.uri).collect(),
+            _ => params.root_uri.iter().collect(),
+        };
+        let search_path = SearchPath::parse(config.nix_path.as_deref().unwrap_or_default());
+        let mut libraries = config.libraries.clone();
+        libraries.extend(search_path.named_directories().map(PathBuf::from));
+        Self {
+            roots: uris
+                .into_iter()
+                .filter_map(|uri| uri.to_file_path().ok())
+                .collect(),
+            libraries,
+            search_path,
+            work_done_progress: params
+                .capabilities
+                .window
+                .as_ref()
+                .and_then(|window| window.work_done_progress)
+                .unwrap_or(false),
+            with_shadowing: config.with_shadowing,
+        }
+    }
+    /// Index every `*.nix` file under the roots on a pool of worker threads, merging them into
+    /// the shared index once all are indexed. Libraries are indexed afterwards, unless the shared
+    /// index already has them at the same commit.
+    ///
+    /// Files which are open, as returned by `open_files` once the workspace is indexed, are left
+    /// as they are in the shared index since they may have unsaved edits. Once indexed, the cache
+    /// is pruned of entries of other commits.
+    pub async fn index(
+        &self,
+        client: &Client,
+        index: &SharedIndex,
+        open_files: impl FnOnce() -> HashSet<CompactString>,
+    ) {
+        if let Some(workspace_index) = self.index_files(client, index, &self.roots, None).await {
+            let open = open_files();
+            index.rcu(|current| {
+                let mut workspace_index = workspace_index.clone();
+                workspace_index.retain(|key| !open.contains(&key.file_path));
+                let mut next = TancIndex::clone(current);
+                next.merge(workspace_index);
+                next
+            });
+        }
+        for root in &self.libraries {
+            let library = match Library::open(root) {
+                Ok(library) => library,
+                Err(err) => {
+                    tracing::warn!("failed to open library {}: {err}", root.display());
+                    continue;
+                },
+            };
+            let Some(library_root) = library.root.to_str() else {
+                tracing::warn!("ignoring library with a non UTF-8 path {}", root.display());
+                continue;
+            };
+            let commit = library.commit.as_deref();
+            if commit.is_none() && self.overlaps(&library.root) {
+                // Its files would be keyed like those of the workspace, and replace them.
+                tracing::warn!(
+                    "ignoring library {library_root} without a commit within the workspace"
+                );
+                continue;
+            }
+            if index
+                .load()
+                .libraries()
+                .any(|indexed| indexed == (library_root, commit))
+            {
+                tracing::debug!("library {library_root} is already indexed");
+                continue;
+            }
+            let roots = [library.root.clone()];
+            let Some(library_index) = self.index_files(client, index, &roots, commit).await
+            else {
+                continue;
+            };
+            index.rcu(|current| {
+                let mut next = TancIndex::clone(current);
+                next.replace_library(library_root, commit, library_index.clone());
+                next
+            });
+        }
+        // Entries of other commits are only pruned once every library is known.
+        let indexed = index.load_full();
+        let pruned = tokio::task::spawn_blocking(move || indexed.prune_cache()).await;
+        match pruned {
+            Ok(Ok(())) => {},
+            Ok(Err(err)) => tracing::warn!("failed to prune the cache: {err}"),
+            Err(err) => tracing::error!("failed to prune the cache: {err}"),
+        }
+    }
+    /// Whether the canonical path of a directory is within a root of the workspace, or contains
+    /// one.
+    fn overlaps(&self, dir: &Path) -> bool {
+        self.roots.iter().any(|root| {
+            let root = root.canonicalize().unwrap_or_else(|_| root.clone());
+            dir.starts_with(&root) || root.starts_with(dir)
+        })
+    }
+    /// Index every `*.nix` file under the given roots into a fork of the shared index, keyed by
+    /// the given commit and reporting progress to the client.
+    async fn index_files(
+        &self,
+        client: &Client,
+        index: &SharedIndex,
+        roots: &[PathBuf],
+        commit: Option<&str>,
+    ) -> Option<TancIndex> {
+        let roots = roots.to_vec();
+        let discovered = tokio::task::spawn_blocking(move || {
+            roots
+                .iter()
+                .flat_map(|root| {
+                    nix_files(root).unwrap_or_else(|err| {
+                        tracing::warn!("failed to discover files of {}: {err}", root.display());
+                        Vec::new()
+                    })
+                })
+                .collect::<Vec<_>>()
+        })
+        .await;
+        let files = match discovered {
+            Ok(files) => files,
+            Err(err) => {
+                tracing::error!("failed to discover files: {err}");
+                return None;
+            },
+        };
+        let total = files.len();
+        tracing::info!("indexing {total} files");
+        let progress = Progress::begin(client, self.work_done_progress, total).await;
+        let indexed = Arc::new(AtomicUsize::new(0));
+        let mut forked_index = index.load().fork();
+        let commit: Option<CompactString> = commit.map(Into::into);
+        let handle = tokio::task::spawn_blocking({
+            let indexed = indexed.clone();
+            move || {
+                forked_index.par_insert(
+                    commit.as_deref(),
+                    files,
+                    |path| {
+                        let src = fs::read_to_string(&path)
+                            .map_err(|err| {
+                                tracing::warn!("failed to read {}: {err}", path.display())
+                            })
+                            .ok()?;
+                        let file_path = CompactString::from(path.to_str()?);
+                        Some((file_path, src))
+                    },
+                    || {
+                        indexed.fetch_add(1, Ordering::Relaxed);
+                    },
+                );
+                forked_index
+            }
+        });
+        while !handle.is_finished() {
+            tokio::time::sleep(REPORT_INTERVAL).await;
+            progress.report(indexed.load(Ordering::Relaxed)).await;
+        }
+        progress.end().await;
+        match handle.await {
+            Ok(forked_index) => Some(forked_index),
+            Err(err) => {
+                tracing::error!("failed to index files: {err}");
+                None
+            },
+        }
+    }
+}
+/// A work done progress of the client, or nothing if the client doesn't support them.
+struct Progress<'a> {
+    client: &'a Client,
+    token: Option<NumberOrString>,
+    total: usize,
+}
+impl<'a> Progress<'a> {
+    async fn begin(client: &'a Client, supported: bool, total: usize) -> Progress<'a> {
+        let mut progress = Self {
+            client,
+            token: None,
+            total,
+        };
+        if !supported {
+            return progress;
+        }
+        let token = NumberOrString::String("tanc/indexing".into());
+        let created = client
+            .send_request::<request::WorkDoneProgressCreate>(WorkDoneProgressCreateParams {
+                token: token.clone(),
+            })
+            .await;
+        if let Err(err) = created {
+            tracing::warn!("failed to create indexing progress: {err}");
+            return progress;
+        }
+        progress.token = Some(token);
+        progress
+            .notify(WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                title: "Indexing".into(),
+                cancellable: Some(false),
+                message: Some(progress.message(0)),
+                percentage: Some(0),
+            }))
+            .await;
+        progress
+    }
+    async fn report(&self, indexed: usize) {
+        self.notify(WorkDoneProgress::Report(WorkDoneProgressReport {
+            cancellable: Some(false),
+            message: Some(self.message(indexed)),
+            percentage: Some((indexed * 100).checked_div(self.total).unwrap_or(100) as u32),
+        }))
+        .await;
+    }
+    async fn end(&self) {
+        self.notify(WorkDoneProgress::End(WorkDoneProgressEnd {
+            message: Some(format!("Indexed {} files", self.total)),
+        }))
+        .await;
+    }
+    fn message(&self, indexed: usize) -> String {
+        format!("{indexed}/{} files", self.total)
+    }
+    async fn notify(&self, value: WorkDoneProgress) {
+        let Some(token) = &self.token else {
+            return;
+        };
+        self.client
+            .send_notification::<notification::Progress>(ProgressParams {
+                token: token.clone(),
+                value: ProgressParamsValue::WorkDone(value),
+            })
+            .await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn overlapping_libraries() {
+        let workspace = Workspace {
+            roots: vec!["/project".into()],
+            ..Default::default()
+        };
+        assert!(workspace.overlaps(Path::new("/project/vendor/nixpkgs")));
+        assert!(workspace.overlaps(Path::new("/project")));
+        assert!(workspace.overlaps(Path::new("/")));
+        assert!(!workspace.overlaps(Path::new("/project-nixpkgs")));
+        assert!(!workspace.overlaps(Path::new("/nix/store/nixpkgs")));
+    }
+}
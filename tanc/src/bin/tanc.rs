@@ -1,13 +1,17 @@
 use anyhow::Result;
-use clap::{ArgAction, Parser};
+use clap::{ArgAction, Parser, Subcommand};
 use std::{fs::File, path::PathBuf};
-use tanc_core::server::Backend;
+use tanc::{builtins::BuiltinsArgs, doc::DocArgs, lsp::LspArgs};
 use tracing::{metadata::LevelFilter, subscriber};
 use tracing_subscriber::{fmt::Layer, layer::SubscriberExt, EnvFilter};
 
 #[derive(Parser, Debug, Default)]
 #[command(author, version, about, long_about = None)]
 pub struct CliConfig {
+    /// The command to run. Defaults to `lsp`, running the language server over stdio.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// If defined, log to a file in addition to stderr.
     #[arg(long)]
     pub log_file: Option<PathBuf>,
@@ -41,6 +45,12 @@ pub struct CliConfig {
     )]
     pub quiet: u8,
 }
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    Builtins(BuiltinsArgs),
+    Doc(DocArgs),
+    Lsp(LspArgs),
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -77,7 +87,7 @@ async fn main() -> Result<()> {
         0 => None,
         1 => Some(LevelFilter::INFO),
         2 => Some(LevelFilter::DEBUG),
-        3 | _ => Some(LevelFilter::TRACE),
+        _ => Some(LevelFilter::TRACE),
     };
 
     let env_filter = {
@@ -110,6 +120,10 @@ async fn main() -> Result<()> {
     tracing::info!("main");
     tracing::warn!("main");
     tracing::error!("main");
-    Backend::new().await;
-    Ok(())
+    match config.command {
+        Some(Command::Builtins(builtins)) => builtins.run(),
+        Some(Command::Doc(doc)) => doc.run(),
+        Some(Command::Lsp(lsp)) => lsp.run().await,
+        None => LspArgs::default().run().await,
+    }
 }
@@ -0,0 +1,73 @@
+use anyhow::{Context, Result};
+use clap::Args;
+use std::{fs, path::PathBuf, process::Command};
+use tanc_core::builtins::Table;
+
+/// Regenerate the table of builtins embedded in tanc from the output of `nix __dump-builtins`.
+///
+/// Types and the versions introducing builtins aren't dumped by Nix, so are kept from the embedded
+/// table. New builtins are recorded as introduced by the version of Nix dumping them.
+#[derive(Args, Debug)]
+pub struct BuiltinsArgs {
+    /// A file of the output of `nix __dump-builtins`, rather than running `nix`.
+    #[arg(long, value_name = "FILE")]
+    pub dump: Option<PathBuf>,
+    /// The version of Nix which dumped the builtins, such as `2.24`. Defaults to that of
+    /// `nix --version`.
+    #[arg(long, value_name = "VERSION")]
+    pub nix_version: Option<String>,
+    /// The file to write the table to, defaulting to stdout. Such as
+    /// `core/src/builtins/primops.json` to update the embedded table.
+    #[arg(long)]
+    pub out: Option<PathBuf>,
+}
+impl BuiltinsArgs {
+    pub fn run(self) -> Result<()> {
+        let dump = match &self.dump {
+            Some(path) => fs::read_to_string(path)
+                .with_context(|| format!("failed to read {}", path.display()))?,
+            None => nix(&["__dump-builtins"])?,
+        };
+        let nix_version = match self.nix_version {
+            Some(version) => version,
+            None => minor_version(&nix(&["--version"])?)
+                .context("failed to parse the output of `nix --version`")?,
+        };
+        let mut table = Table::embedded().clone();
+        table
+            .update(&nix_version, &dump)
+            .context("failed to parse the dumped builtins")?;
+        match &self.out {
+            Some(path) => fs::write(path, table.to_json())
+                .with_context(|| format!("failed to write {}", path.display()))?,
+            None => print!("{}", table.to_json()),
+        }
+        tracing::info!(
+            "regenerated {} builtins of nix {nix_version}",
+            table.builtins.len()
+        );
+        Ok(())
+    }
+}
+/// The stdout of running `nix` with the given args.
+fn nix(args: &[&str]) -> Result<String> {
+    let output = Command::new("nix")
+        .args(args)
+        .output()
+        .with_context(|| format!("failed to run `nix {}`", args.join(" ")))?;
+    anyhow::ensure!(
+        output.status.success(),
+        "`nix {}` failed: {}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    Ok(String::from_utf8(output.stdout)?)
+}
+/// The major and minor version of the output of `nix --version`, such as `2.24` of
+/// `nix (Nix) 2.24.9`.
+fn minor_version(version: &str) -> Option<String> {
+    let version = version.split_whitespace().last()?;
+    let mut parts = version.split('.');
+    let (major, minor) = (parts.next()?, parts.next()?);
+    Some(format!("{major}.{minor}"))
+}
@@ -0,0 +1,186 @@
+use anyhow::{Context, Result};
+use clap::{Args, Subcommand, ValueEnum};
+use std::{
+    env,
+    fs::File,
+    io::{self, BufWriter, IsTerminal, Write},
+    path::{Path, PathBuf},
+};
+use tanc_core::{
+    export::{self, html, json, man, markdown, nixdoc, terminal},
+    path,
+    project::Project,
+};
+
+/// Look up the doc of a binding, or generate documentation from the doc comments of Nix
+/// sources.
+#[derive(Args, Debug)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+pub struct DocArgs {
+    #[command(subcommand)]
+    pub command: Option<DocCommand>,
+    #[command(flatten)]
+    pub lookup: LookupArgs,
+}
+/// Print the doc of a binding, like `ri` or `pydoc`.
+#[derive(Args, Debug)]
+pub struct LookupArgs {
+    /// The binding to look up. Either a path within a file such as
+    /// `lib/strings.nix::concatStrings`, or an attr path such as `lib.strings.concatStrings`.
+    #[arg(required = true)]
+    pub path: Option<String>,
+    /// The root directory of the Nix project.
+    #[arg(long, default_value = ".")]
+    pub dir: PathBuf,
+    /// Print matching bindings as JSON records, one per line, in the format of
+    /// `tanc doc export --format jsonl`.
+    #[arg(long)]
+    pub json: bool,
+}
+#[derive(Subcommand, Debug)]
+pub enum DocCommand {
+    /// Render a static HTML site documenting every file and attribute set of a project.
+    Build {
+        /// The root directory of the Nix project.
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+        /// The directory to write the site into.
+        #[arg(long, default_value = "site")]
+        out: PathBuf,
+    },
+    /// Export the documentation of a project in a machine readable or publishable format.
+    Export {
+        /// The root directory of the Nix project.
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+        #[arg(long, value_enum, default_value_t = ExportFormat::Json)]
+        format: ExportFormat,
+        /// The file to write to, defaulting to stdout. The `markdown`, `nixdoc` and `man` formats
+        /// write many files, and require this to be a directory.
+        #[arg(long)]
+        out: Option<PathBuf>,
+        /// Print the JSON Schema of the `json` and `jsonl` formats, rather than exporting.
+        #[arg(long)]
+        schema: bool,
+    },
+}
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// A single, versioned JSON document.
+    Json,
+    /// Newline delimited JSON records, streamed for large projects.
+    Jsonl,
+    /// CommonMark, one file per module along with a `SUMMARY.md` for mdBook.
+    Markdown,
+    /// CommonMark structured like the output of `nixdoc`, as used by the nixpkgs manual.
+    Nixdoc,
+    /// roff man pages, one per top level function.
+    Man,
+}
+impl DocArgs {
+    pub fn run(self) -> Result<()> {
+        let Some(command) = self.command else {
+            return self.lookup.run();
+        };
+        match command {
+            DocCommand::Build { dir, out } => {
+                let project = Project::load(&dir)
+                    .with_context(|| format!("failed to index {}", dir.display()))?;
+                html::write(&project, &out)
+                    .with_context(|| format!("failed to write site to {}", out.display()))?;
+                tracing::info!("wrote documentation site to {}", out.display());
+                Ok(())
+            },
+            DocCommand::Export {
+                dir,
+                format,
+                out,
+                schema,
+            } => {
+                if schema {
+                    let mut w = writer(out.as_deref())?;
+                    w.write_all(json::SCHEMA.as_bytes())?;
+                    return Ok(w.flush()?);
+                }
+                let project = Project::load(&dir)
+                    .with_context(|| format!("failed to index {}", dir.display()))?;
+                let write_dir = |write: fn(&Project, &Path) -> io::Result<()>| -> Result<()> {
+                    let out = out
+                        .as_deref()
+                        .context("--out <DIR> is required for this format")?;
+                    write(&project, out)
+                        .with_context(|| format!("failed to write to {}", out.display()))?;
+                    tracing::info!("wrote documentation to {}", out.display());
+                    Ok(())
+                };
+                match format {
+                    ExportFormat::Json => {
+                        let mut w = writer(out.as_deref())?;
+                        json::write_json(project.index(), &mut w)?;
+                        Ok(w.flush()?)
+                    },
+                    ExportFormat::Jsonl => {
+                        let mut w = writer(out.as_deref())?;
+                        json::write_jsonl(project.index(), &mut w)?;
+                        Ok(w.flush()?)
+                    },
+                    ExportFormat::Markdown => write_dir(markdown::write),
+                    ExportFormat::Nixdoc => write_dir(nixdoc::write),
+                    ExportFormat::Man => write_dir(man::write),
+                }
+            },
+        }
+    }
+}
+impl LookupArgs {
+    pub fn run(self) -> Result<()> {
+        let query = self.path.context("expected a path to look up")?;
+        let project = Project::load(&self.dir)
+            .with_context(|| format!("failed to index {}", self.dir.display()))?;
+        // Attr paths can't contain the separators of files, so anything else is a `path::Path`.
+        let is_file_path = query.contains([':', '/']) || query.ends_with(".nix");
+        let items = if is_file_path {
+            let path: path::Path = query
+                .parse()
+                .with_context(|| format!("invalid path `{query}`"))?;
+            if path.remote.is_some() || path.commit.is_some() {
+                tracing::warn!("ignoring the remote and commit of `{path}`");
+            }
+            export::find_path(&project, &path)
+        } else {
+            export::find_attr(&project, &query)
+        };
+        anyhow::ensure!(!items.is_empty(), "no binding found for `{query}`");
+        let mut w = io::stdout().lock();
+        if self.json {
+            for item in &items {
+                let record = json::Record::Binding {
+                    file: item.file_path.to_owned(),
+                    binding: item.into(),
+                };
+                serde_json::to_writer(&mut w, &record)?;
+                writeln!(w)?;
+            }
+            return Ok(w.flush()?);
+        }
+        let color = w.is_terminal() && env::var_os("NO_COLOR").is_none();
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                writeln!(w)?;
+            }
+            w.write_all(terminal::render(item, color).as_bytes())?;
+        }
+        Ok(w.flush()?)
+    }
+}
+/// A buffered writer to the given file, or stdout if none.
+fn writer(out: Option<&Path>) -> Result<Box<dyn Write>> {
+    Ok(match out {
+        Some(path) => {
+            Box::new(BufWriter::new(File::create(path).with_context(|| {
+                format!("failed to create {}", path.display())
+            })?))
+        },
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    })
+}
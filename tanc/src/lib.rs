@@ -1,3 +1,7 @@
+pub mod builtins;
+pub mod doc;
+pub mod lsp;
+
 pub fn add(left: usize, right: usize) -> usize {
     left + right
 }
@@ -0,0 +1,88 @@
+use anyhow::{Context, Result};
+use clap::Args;
+use std::path::PathBuf;
+use tanc_core::server::{Backend, Config};
+
+/// Run the language server.
+///
+/// The TCP and Unix socket transports serve clients one after another, sharing a single index so
+/// that it stays warm between editor instances.
+#[derive(Args, Debug, Default)]
+pub struct LspArgs {
+    #[command(flatten)]
+    pub transport: TransportArgs,
+    /// Index the given directory, such as a local checkout of nixpkgs, as a read-only library.
+    /// May be given many times, and is extended by the `libraries` initialization option of
+    /// clients.
+    #[arg(long = "library", value_name = "DIR")]
+    pub libraries: Vec<PathBuf>,
+    /// The search path resolving angle-bracket paths such as `<nixpkgs>`, overridden by the
+    /// `nixPath` initialization option of clients.
+    #[arg(long, env = "NIX_PATH", value_name = "NIX_PATH")]
+    pub nix_path: Option<String>,
+    /// Warn of identifiers bound lexically within a `with` whose namespace also provides them,
+    /// enabled by the `withShadowing` initialization option of clients too.
+    #[arg(long)]
+    pub with_shadowing: bool,
+}
+/// How clients connect to the server.
+#[derive(Args, Debug, Default)]
+#[group(multiple = false)]
+pub struct TransportArgs {
+    /// Serve a single client over stdin and stdout. This is the default.
+    #[arg(long)]
+    pub stdio: bool,
+    /// Listen for clients over TCP on the given address, such as `127.0.0.1:9257` or
+    /// `localhost:9257`.
+    #[arg(long, value_name = "HOST:PORT")]
+    pub listen: Option<String>,
+    /// Listen for clients on a Unix domain socket at the given path.
+    #[cfg(unix)]
+    #[arg(long, value_name = "PATH")]
+    pub socket: Option<PathBuf>,
+}
+impl LspArgs {
+    pub async fn run(self) -> Result<()> {
+        let config = Config {
+            libraries: self.libraries,
+            nix_path: self.nix_path,
+            with_shadowing: self.with_shadowing,
+        };
+        if let Some(addr) = self.transport.listen {
+            return Backend::listen_tcp(addr.as_str(), config)
+                .await
+                .with_context(|| format!("failed to serve on {addr}"));
+        }
+        #[cfg(unix)]
+        if let Some(path) = self.transport.socket {
+            return Backend::listen_unix(&path, config)
+                .await
+                .with_context(|| format!("failed to serve on {}", path.display()));
+        }
+        Backend::serve_stdio(config).await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser, Debug)]
+    struct Cli {
+        #[command(flatten)]
+        lsp: LspArgs,
+    }
+
+    #[test]
+    fn listen_addresses() {
+        let listen = |addr: &str| {
+            let cli = Cli::try_parse_from(["tanc", "--listen", addr]).unwrap();
+            cli.lsp.transport.listen
+        };
+        assert_eq!(listen("127.0.0.1:9257").as_deref(), Some("127.0.0.1:9257"));
+        assert_eq!(listen("localhost:9257").as_deref(), Some("localhost:9257"));
+        assert!(Cli::try_parse_from(["tanc", "--listen", "localhost:9257", "--stdio"]).is_err());
+    }
+}